@@ -0,0 +1,11 @@
+import sys
+
+print("mytool", sys.version.split()[0])
+print("Hello, World!")
+
+# The version line differs depending on the python used to run this test,
+# its difference is reported but doesn't fail the test
+# allow diff: mytool [0-9.]+
+# expected stdout:
+# mytool 0.0.0
+# Hello, World!
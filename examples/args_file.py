@@ -0,0 +1,6 @@
+import sys
+
+print(sys.flags.dont_write_bytecode, sys.flags.optimize)
+
+# args file: ../tests/fixtures/python_flags.txt
+# expected stdout: 1 1
@@ -0,0 +1,6 @@
+with open("target/examples/scratch.txt") as scratch:
+    print(scratch.read().strip())
+
+# before: mkdir -p target/examples && echo "created by before:" > target/examples/scratch.txt
+# after: rm target/examples/scratch.txt
+# expected stdout: created by before:
@@ -0,0 +1,7 @@
+import sys
+
+# Output that isn't valid UTF-8 and contains \r, which would otherwise be stripped
+sys.stdout.buffer.write(bytes(range(256)) + b"\r\n")
+
+# The expected stdout is stored byte for byte in raw_bytes.py.stdout
+# binary output: true
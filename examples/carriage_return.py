@@ -0,0 +1,7 @@
+import sys
+
+# Carriage returns within a line are kept while each \r\n line ending is still normalized.
+# The expected output is kept in a sidecar file since python reads a lone \r as a line break.
+sys.stdout.write("progress: 50%\rprogress: 100%\r\ndone\r\n")
+
+# sidecar files: true
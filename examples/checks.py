@@ -0,0 +1,11 @@
+print("define i32 @main() {")
+print("entry:")
+print("  %0 = add i32 1, 2")
+print("  ret i32 %0")
+print("}")
+
+# Only the lines which matter are checked, in order. The rest of stdout isn't compared.
+# check: define i32 @main()
+# check-next: entry:
+# check-not: alloca
+# check: ret i32
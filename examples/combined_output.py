@@ -0,0 +1,10 @@
+import sys
+
+print("compiling", flush=True)
+print("warning: unused variable 'x'", file=sys.stderr, flush=True)
+print("done", flush=True)
+
+# expected output:
+# compiling
+# warning: unused variable 'x'
+# done
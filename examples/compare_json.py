@@ -0,0 +1,13 @@
+import json
+import time
+
+# Only the structure is compared, so the key order and formatting may differ from what is expected
+print(json.dumps({"status": "ok", "timestamp": time.time(), "counts": {"errors": 0, "warnings": 2}}))
+
+# compare: json
+# ignore fields: timestamp
+# expected stdout:
+# {
+#   "counts": { "warnings": 2, "errors": 0 },
+#   "status": "ok"
+# }
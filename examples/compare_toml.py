@@ -0,0 +1,8 @@
+# Tables and keys may be written in any order
+print('[package]')
+print('version = "1.0.0"')
+print('name = "example"')
+
+# compare: toml
+# expected stdout:
+# package = { name = "example", version = "1.0.0" }
@@ -0,0 +1,13 @@
+# A config generator whose output is checked regardless of how it is formatted
+print("name: server")
+print("ports: [80, 443]")
+print("tls: {enabled: true}")
+
+# compare: yaml
+# expected stdout:
+# name: server
+# tls:
+#   enabled: true
+# ports:
+#   - 80
+#   - 443
@@ -0,0 +1,15 @@
+import random
+import sys
+
+print("build id:", random.randint(0, 1000000))
+print("compiled 3 modules")
+print("warning: unused variable 'x'", file=sys.stderr)
+print("finished in", random.random(), "seconds")
+
+# Only the key messages are checked, the rest of the output may vary
+# expected stdout contains: compiled 3 modules
+
+# expected stderr contains:
+# warning: unused variable 'x'
+
+# expected stderr excludes: error:
@@ -0,0 +1,8 @@
+import sys
+
+print(sys.flags.dont_write_bytecode, sys.flags.optimize)
+
+# Long argument lists can be continued onto the next line with a trailing \
+# args: -B \
+#     -O
+# expected stdout: 1 1
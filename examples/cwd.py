@@ -0,0 +1,6 @@
+# Relative paths are resolved from the working directory set by "cwd:" below,
+# which is itself relative to the directory containing this test.
+print(open("Cargo.toml").readline().strip())
+
+# cwd: ..
+# expected stdout: [package]
@@ -0,0 +1,10 @@
+import sys
+
+print("\x1b[1mbold\x1b[0m")
+sys.stderr.buffer.write(b"\x00\x01\xff")
+
+# Output containing control characters or invalid UTF-8 can be written encoded.
+# expected stdout (base64):
+# G1sxbWJvbGQbWzBtCg==
+
+# expected stderr (hex): 00 01 ff
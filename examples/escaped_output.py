@@ -0,0 +1,9 @@
+print("name\tsize")
+print("a.txt\t12")
+print("\x1b[32mok\x1b[0m")
+
+# Tabs and control characters can be written as escape sequences.
+# expected stdout (escaped):
+# name\tsize
+# a.txt\t12
+# \x1b[32mok\x1b[0m
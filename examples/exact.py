@@ -0,0 +1,11 @@
+print()
+print("  indented")
+print("trailing  ")
+print("carriage return\r")
+
+# exact: true
+# expected stdout:
+# 
+#   indented
+# trailing  
+# carriage return
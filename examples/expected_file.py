@@ -0,0 +1,10 @@
+import json
+import os
+
+os.makedirs("target/examples", exist_ok=True)
+with open("target/examples/result.json", "w") as file:
+    json.dump({"result": 3}, file)
+
+# The path is relative to the directory the tests are ran in
+# expected file "target/examples/result.json":
+# {"result": 3}
@@ -0,0 +1,12 @@
+print("first paragraph")
+print()
+print()
+print("second paragraph")
+
+# A fenced block keeps reading blank lines until the line ending it.
+# expected stdout until: EOF
+# first paragraph
+
+#
+# second paragraph
+# EOF
@@ -0,0 +1,9 @@
+for fruit in {"cherry", "apple", "banana"}:
+    print(fruit)
+
+# Sets are iterated in a different order on each run, so the output is sorted before comparing
+# filter: sort
+# expected stdout:
+# apple
+# banana
+# cherry
@@ -0,0 +1,10 @@
+import math
+
+# Platforms may round the last few digits of these differently
+print("pi =", math.pi)
+print("e^10 =", math.exp(10))
+
+# float tolerance: 1e-6
+# expected stdout:
+# pi = 3.1415926
+# e^10 = 22026.465794
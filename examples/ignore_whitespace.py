@@ -0,0 +1,11 @@
+rows = [("name", "size"), ("golden_tests", 12), ("a", 3)]
+width = max(len(str(name)) for name, _ in rows) + 2
+
+for name, size in rows:
+    print(str(name).ljust(width) + str(size) + "  ")
+
+# ignore whitespace: true
+# expected stdout:
+# name size
+# golden_tests 12
+# a 3
@@ -0,0 +1,12 @@
+import random
+
+print("starting up")
+for _ in range(random.randint(0, 5)):
+    print("processing", random.random())
+print("done")
+
+# Any number of lines may appear in place of the "..." line
+# expected stdout:
+# starting up
+# ...
+# done
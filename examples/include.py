@@ -0,0 +1,8 @@
+print("mycompiler v1.0")
+print("(c) the mycompiler authors")
+print("compiled 3 files")
+
+# The expected output of the included file comes before the expected output of this test
+# include: ../tests/fixtures/banner.txt
+# expected stdout: compiled 3 files
+
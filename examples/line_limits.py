@@ -0,0 +1,17 @@
+import random
+import sys
+
+print("header line 1")
+print("header line 2")
+for _ in range(random.randint(1, 5)):
+    print(random.random())
+
+print("took", random.random(), "seconds", file=sys.stderr)
+print("done", file=sys.stderr)
+
+# Only the first lines of stdout and the last line of stderr are compared.
+# expected stdout (first 2 lines):
+# header line 1
+# header line 2
+
+# expected stderr (last line): done
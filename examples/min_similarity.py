@@ -0,0 +1,30 @@
+import time
+
+for step in range(1, 20):
+    print("step", step, "ok")
+print("finished at", int(time.time()))
+
+# The last line differs on every run, but the output as a whole is similar enough to pass.
+# Its difference is still reported.
+# min similarity: 90%
+# expected stdout:
+# step 1 ok
+# step 2 ok
+# step 3 ok
+# step 4 ok
+# step 5 ok
+# step 6 ok
+# step 7 ok
+# step 8 ok
+# step 9 ok
+# step 10 ok
+# step 11 ok
+# step 12 ok
+# step 13 ok
+# step 14 ok
+# step 15 ok
+# step 16 ok
+# step 17 ok
+# step 18 ok
+# step 19 ok
+# finished at 0
@@ -0,0 +1,5 @@
+# Each run must print exactly the same output, so sets are sorted before printing.
+print(sorted({"c", "a", "b"}))
+
+# repeat: 3
+# expected stdout: ['a', 'b', 'c']
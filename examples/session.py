@@ -0,0 +1,17 @@
+# A tiny calculator REPL, tested by sending it one line at a time and waiting for each answer.
+import sys
+
+while True:
+    print("> ", end="", flush=True)
+    line = sys.stdin.readline()
+    if not line or line.strip() == "quit":
+        print("bye")
+        break
+    print(eval(line), flush=True)
+
+# send: 1 + 2
+# expect: 3
+# send: 6 * 7
+# expect: 42
+# send: quit
+# expect: bye
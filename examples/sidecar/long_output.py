@@ -0,0 +1,5 @@
+for i in range(1, 31):
+    print("line", i, "of a long output")
+
+# The expected stdout of this test is stored in long_output.py.stdout
+# sidecar files: true
@@ -0,0 +1,10 @@
+import random
+
+print("mycompiler v1.0")
+print("build id:", random.randint(0, 1000000))
+print("finished with 0 errors")
+
+# The header and trailer are stable even though the middle of the output varies
+# expected stdout starts with: mycompiler v1.0
+
+# expected stdout ends with: finished with 0 errors
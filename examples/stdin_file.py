@@ -0,0 +1,10 @@
+import sys
+
+for line in sorted(sys.stdin):
+    print(line.strip())
+
+# stdin file: ../tests/fixtures/fruits.txt
+# expected stdout:
+# apple
+# banana
+# cherry
@@ -0,0 +1,9 @@
+import os
+import sys
+
+stem, tmpdir = sys.argv[1:]
+print(stem, os.path.isdir(tmpdir))
+
+# The test path is placed explicitly with {file} so it isn't appended again
+# args: {file} {stem} {tmpdir}
+# expected stdout: template_args True
@@ -0,0 +1,11 @@
+# Each test has its own scratch directory which is removed once it finishes
+import os
+
+path = os.path.join(os.environ["GOLDENTESTS_TMPDIR"], "scratch.txt")
+with open(path, "w") as file:
+    file.write("written to a scratch file")
+
+with open(path) as file:
+    print(file.read())
+
+# expected stdout: written to a scratch file
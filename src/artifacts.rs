@@ -0,0 +1,195 @@
+//! Artifacts written for failing tests and the failure hook which is ran with them
+use crate::colors::{strip_colors, Colorize};
+use crate::config::TestConfig;
+use crate::error::Mismatch;
+
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+/// Everything about a failing test run that is written out as an artifact
+pub(crate) struct Failure<'a> {
+    pub test_path: &'a Path,
+    pub environment: Option<&'a str>,
+    pub stdout: &'a [u8],
+    pub stderr: &'a [u8],
+    pub exit_status: Option<i32>,
+    pub errors: &'a [Mismatch],
+}
+
+/// The actual output of a failing test written by `save_actual`, read back to be promoted
+pub(crate) struct SavedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_status: ExitStatus,
+}
+
+impl TestConfig {
+    /// The directory artifacts of failing tests are written to
+    fn artifacts_directory(&self) -> PathBuf {
+        match &self.state_directory {
+            Some(directory) => directory.clone(),
+            None => std::env::temp_dir().join("goldentests"),
+        }
+    }
+
+    /// The path each artifact of the given test starts with. The test path is kept so artifacts
+    /// are easy to find, e.g. `target/goldentests/tests/parser/let.ant`, which artifacts
+    /// then add an extension to.
+    fn artifact_base_path(&self, test_path: &Path, environment: Option<&str>) -> PathBuf {
+        let mut path = self.artifacts_directory();
+        path.extend(test_path.components().filter(|component| matches!(component, Component::Normal(_))));
+
+        if let Some(environment) = environment {
+            let mut with_environment = path.into_os_string();
+            with_environment.push(".");
+            with_environment.push(environment);
+            path = PathBuf::from(with_environment);
+        }
+        path
+    }
+
+    /// The paths of the actual stdout, stderr, exit status, and diff artifacts of the given test
+    fn artifact_paths(&self, test_path: &Path, environment: Option<&str>) -> [PathBuf; 4] {
+        let base = self.artifact_base_path(test_path, environment).into_os_string();
+        let with_extension = |extension: &str| {
+            let mut path = base.clone();
+            path.push(extension);
+            PathBuf::from(path)
+        };
+
+        [
+            with_extension(".actual.stdout"),
+            with_extension(".actual.stderr"),
+            with_extension(".actual.status"),
+            with_extension(".diff"),
+        ]
+    }
+
+    /// Writes the actual stdout, stderr, exit status, and diff of a failing test to the artifacts
+    /// directory, returning the paths of each file written in that order. The exit status file is
+    /// empty if the test was killed by a signal.
+    pub(crate) fn write_artifacts(&self, failure: &Failure) -> std::io::Result<[PathBuf; 4]> {
+        let paths = self.artifact_paths(failure.test_path, failure.environment);
+        if let Some(directory) = paths[0].parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        std::fs::write(&paths[0], failure.stdout)?;
+        std::fs::write(&paths[1], failure.stderr)?;
+        let status = failure.exit_status.map_or_else(String::new, |status| status.to_string());
+        std::fs::write(&paths[2], status)?;
+        let errors: Vec<String> = failure.errors.iter().map(ToString::to_string).collect();
+        std::fs::write(&paths[3], strip_colors(&errors.join("\n")))?;
+        Ok(paths)
+    }
+
+    /// Removes the artifacts of a test which no longer fails, if there are any
+    pub(crate) fn remove_artifacts(&self, test_path: &Path, environment: Option<&str>) {
+        for path in &self.artifact_paths(test_path, environment) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Reads the actual output saved for the given test on the last run, if there is any
+    pub(crate) fn read_saved_output(
+        &self,
+        test_path: &Path,
+        environment: Option<&str>,
+    ) -> std::io::Result<Option<SavedOutput>> {
+        let [stdout, stderr, status, _] = self.artifact_paths(test_path, environment);
+        let stdout = match std::fs::read(stdout) {
+            Ok(stdout) => stdout,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let status = std::fs::read_to_string(status)?;
+        let exit_status = match status.trim() {
+            "" => None,
+            status => {
+                let code = status.parse().map_err(|_| {
+                    let message = format!("invalid saved exit status '{}'", status);
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+                })?;
+                Some(code)
+            }
+        };
+
+        Ok(Some(SavedOutput {
+            stdout,
+            stderr: std::fs::read(stderr)?,
+            exit_status: exit_status_from_code(exit_status),
+        }))
+    }
+
+    /// Writes the artifacts of a failing test for `save_actual`, returning a note with their paths
+    /// to add to the failure. Any problems writing them are reported within the note instead.
+    pub(crate) fn save_actual_output(&self, failure: &Failure) -> Mismatch {
+        match self.write_artifacts(failure) {
+            Ok([stdout, stderr, status, _]) => format!(
+                "Actual output saved to {}, {}, and {}\n",
+                stdout.display(),
+                stderr.display(),
+                status.display()
+            ),
+            Err(error) => format!("Failed to save actual output: {}\n", error),
+        }
+        .into()
+    }
+
+    /// Runs `failure_hook`, if any, for the given failing test. Any problems running
+    /// the hook are reported as warnings since they don't affect the test itself.
+    pub(crate) fn run_failure_hook(&self, failure: &Failure) {
+        let (program, args) = match self.failure_hook.as_ref().and_then(|hook| hook.split_first()) {
+            Some(hook) => hook,
+            None => return,
+        };
+
+        let warn = |message: String| {
+            let warning = format!("warning: {}: failure hook {}", failure.test_path.display(), message);
+            eprintln!("{}", warning.as_str().bright_yellow());
+        };
+
+        let [stdout, stderr, _, diff] = match self.write_artifacts(failure) {
+            Ok(paths) => paths,
+            Err(error) => return warn(format!("not ran, failed to write artifacts: {}", error)),
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .env("GOLDENTESTS_TEST", failure.test_path)
+            .env("GOLDENTESTS_ENVIRONMENT", failure.environment.unwrap_or_default())
+            .env("GOLDENTESTS_STDOUT", stdout)
+            .env("GOLDENTESTS_STDERR", stderr)
+            .env("GOLDENTESTS_DIFF", diff)
+            .env(
+                "GOLDENTESTS_EXIT_STATUS",
+                failure.exit_status.map_or_else(String::new, |status| status.to_string()),
+            )
+            // Our stdout may be a stream of JSON messages, see `TestConfig::message_format`
+            .stdout(std::io::stderr());
+
+        match command.status() {
+            Ok(status) if status.success() => (),
+            Ok(status) => warn(format!("exited with {}", status)),
+            Err(error) => warn(format!("could not be ran: {}", error)),
+        }
+    }
+}
+
+/// Recreates the exit status of a saved test. A missing code means the test was killed by a signal.
+#[cfg(unix)]
+fn exit_status_from_code(code: Option<i32>) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    match code {
+        Some(code) => ExitStatus::from_raw((code & 0xff) << 8),
+        None => ExitStatus::from_raw(libc::SIGKILL),
+    }
+}
+
+#[cfg(windows)]
+fn exit_status_from_code(code: Option<i32>) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code.unwrap_or(1) as u32)
+}
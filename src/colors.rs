@@ -0,0 +1,70 @@
+//! Re-exports `colored` when the "color" feature is enabled. Otherwise this provides
+//! a replacement with the same interface which leaves all output as plain text.
+#[cfg(feature = "color")]
+pub use colored::{Color, ColoredString, Colorize};
+
+#[cfg(not(feature = "color"))]
+pub use plain::{Color, ColoredString, Colorize};
+
+/// Removes any color codes from the given text so it can be written to a file
+pub fn strip_colors(text: &str) -> String {
+    let colors = regex::Regex::new("\x1b\\[[0-9;]*m").unwrap();
+    colors.replace_all(text, "").into_owned()
+}
+
+#[cfg(not(feature = "color"))]
+mod plain {
+    #[derive(Copy, Clone)]
+    pub enum Color {
+        Black,
+        Red,
+        Green,
+    }
+
+    pub type ColoredString = String;
+
+    pub trait Colorize {
+        fn normal(self) -> ColoredString;
+        fn color(self, color: Color) -> ColoredString;
+        fn on_color(self, color: Color) -> ColoredString;
+        fn red(self) -> ColoredString;
+        fn green(self) -> ColoredString;
+        fn cyan(self) -> ColoredString;
+        fn bold(self) -> ColoredString;
+        fn bright_yellow(self) -> ColoredString;
+    }
+
+    impl Colorize for &str {
+        fn normal(self) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn color(self, _: Color) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn on_color(self, _: Color) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn red(self) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn green(self) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn cyan(self) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn bold(self) -> ColoredString {
+            self.to_owned()
+        }
+
+        fn bright_yellow(self) -> ColoredString {
+            self.to_owned()
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Running the command of each test, which may be replaced to run tests somewhere other than
+//! a local process, e.g. within a container or on another machine. See `TestConfig::command_runner`.
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::time::Duration;
+
+/// Everything needed to run the command of a single test
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+
+    /// The directory to run the command in, if different from the current directory
+    pub current_dir: Option<PathBuf>,
+
+    /// Environment variables to set in addition to those inherited from the current process
+    pub env: Vec<(String, String)>,
+
+    /// If true, the command should start with an empty environment rather than inheriting that of
+    /// the current process, so only the variables in `env` are set. See `TestConfig::clean_env`.
+    pub clean_env: bool,
+
+    /// A file whose contents are piped into the stdin of the command. If this is None, the
+    /// command should be given an empty stdin rather than inheriting it.
+    pub stdin: Option<PathBuf>,
+
+    /// If true, stderr should be interleaved into stdout as with `2>&1`, in the order each
+    /// was written. The stderr of the returned output is then ignored.
+    pub combine_output: bool,
+
+    /// Limits on the resources the command may use. See `TestConfig::limits`.
+    pub limits: ResourceLimits,
+
+    /// If true, the command should be ran with a pseudo-terminal as its stdin, stdout, and stderr.
+    /// Everything written to the terminal is returned as stdout. See `TestConfig::pty`.
+    pub pty: bool,
+}
+
+/// Limits on the memory and CPU time of each test so that a runaway program can't take down the
+/// machine running the tests. These are set with `setrlimit` in the child process, so are only
+/// supported on Unix. Elsewhere, running a test locally with any limit set fails with an error
+/// rather than running it unlimited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ResourceLimits {
+    /// The maximum size in bytes of the data of the program: its heap and other private memory.
+    /// Allocations past this fail, which most programs handle by aborting or exiting with an error.
+    pub memory: Option<u64>,
+
+    /// The maximum CPU time of the program, rounded up to a whole second. The program is sent
+    /// `SIGXCPU` once this is exceeded, and killed a second later if it is still running.
+    pub cpu_time: Option<Duration>,
+}
+
+impl ResourceLimits {
+    /// Describes the limit the program exceeded, given how it exited and the peak memory in bytes
+    /// it used, if known. A program killed by `SIGXCPU` exceeded its CPU time, while a program which
+    /// failed only exceeded its memory if its peak memory reached the limit. Any other failure is
+    /// reported as usual rather than blamed on a limit.
+    pub fn exceeded(&self, status: &ExitStatus, peak_memory: Option<u64>) -> Option<String> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let (Some(libc::SIGXCPU), Some(cpu_time)) = (status.signal(), self.cpu_time) {
+                return Some(format!("CPU time limit of {}s", cpu_seconds(cpu_time)));
+            }
+        }
+
+        match (self.memory, peak_memory) {
+            (Some(memory), Some(peak)) if !status.success() && peak >= memory => {
+                Some(format!("memory limit of {} bytes", memory))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets these limits on the current process. This is ran in the child process after forking,
+    /// so it must not allocate.
+    #[cfg(unix)]
+    fn apply(&self) -> std::io::Result<()> {
+        fn set(resource: libc::c_int, limit: libc::rlim_t, hard_limit: libc::rlim_t) -> std::io::Result<()> {
+            let limit = libc::rlimit {
+                rlim_cur: limit,
+                rlim_max: hard_limit,
+            };
+            // The resource parameter differs in type between platforms
+            if unsafe { libc::setrlimit(resource as _, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        if let Some(memory) = self.memory {
+            // Unlike the address space, which also counts memory that is reserved but never used,
+            // the data of a program is resident once it is used, so it can be compared with the peak
+            // resident memory of the program to tell whether the limit was reached
+            set(libc::RLIMIT_DATA as _, memory as libc::rlim_t, memory as libc::rlim_t)?;
+        }
+        if let Some(cpu_time) = self.cpu_time {
+            // The soft limit sends SIGXCPU, while the hard limit a second later sends SIGKILL
+            let seconds = cpu_seconds(cpu_time) as libc::rlim_t;
+            set(libc::RLIMIT_CPU as _, seconds, seconds + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// CPU time limits are given in whole seconds, rounded up so that a limit is never zero
+fn cpu_seconds(cpu_time: Duration) -> u64 {
+    cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0)
+}
+
+impl Invocation {
+    /// Creates a local process for this invocation. Output is not redirected.
+    pub fn command(&self) -> std::io::Result<Command> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if self.clean_env {
+            command.env_clear();
+        }
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+
+        if let Some(current_dir) = &self.current_dir {
+            command.current_dir(current_dir);
+        }
+
+        // Set explicitly since `Command::spawn` would otherwise inherit our stdin
+        match &self.stdin {
+            Some(stdin) => command.stdin(File::open(stdin)?),
+            None => command.stdin(Stdio::null()),
+        };
+
+        #[cfg(unix)]
+        if self.limits != ResourceLimits::default() {
+            use std::os::unix::process::CommandExt;
+            let limits = self.limits;
+            // Safety: `apply` only calls setrlimit, which is async-signal-safe
+            unsafe {
+                command.pre_exec(move || limits.apply());
+            }
+        }
+
+        #[cfg(not(unix))]
+        if self.limits != ResourceLimits::default() {
+            let message = "resource limits are only supported on Unix";
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, message));
+        }
+        Ok(command)
+    }
+}
+
+impl fmt::Display for Invocation {
+    /// Displays the program and its arguments as a shell command line
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> =
+            std::iter::once(&self.program).chain(&self.args).map(|word| crate::shell::quote(word)).collect();
+        f.write_str(&words.join(" "))
+    }
+}
+
+/// Runs the command of each test. Tests may be ran in parallel, so `run` can be called from
+/// multiple threads at once. The `before:` and `after:` commands of a test are always ran locally.
+pub trait CommandRunner: Send + Sync {
+    /// Runs the given invocation to completion, returning its exit status and everything it wrote
+    /// to stdout and stderr. An error here is reported as the test failing to run at all.
+    fn run(&self, invocation: &Invocation) -> std::io::Result<Output>;
+
+    /// Runs the given invocation as with `run`, also returning the peak memory in bytes the command
+    /// used if it could be measured. This tells whether a crashed command exceeded its memory limit.
+    /// By default, the memory isn't measured.
+    fn run_measured(&self, invocation: &Invocation) -> std::io::Result<(Output, Option<u64>)> {
+        self.run(invocation).map(|output| (output, None))
+    }
+
+    /// Identifies this runner and any options which change how it runs tests, so that an incremental
+    /// run reruns every test once the runner changes. Defaults to the name of the runner's type.
+    fn id(&self) -> String {
+        std::any::type_name::<Self>().to_owned()
+    }
+}
+
+/// The default `CommandRunner` which runs each test as a local process. On Unix, each test is ran
+/// in its own process group, and any processes it leaves running are killed once it exits or the
+/// run is interrupted. Elsewhere, only the process of the test itself is waited on, and any processes
+/// it starts are left running.
+pub struct LocalRunner;
+
+impl CommandRunner for LocalRunner {
+    #[cfg(unix)]
+    fn run(&self, invocation: &Invocation) -> std::io::Result<Output> {
+        self.run_measured(invocation).map(|(output, _)| output)
+    }
+
+    #[cfg(unix)]
+    fn run_measured(&self, invocation: &Invocation) -> std::io::Result<(Output, Option<u64>)> {
+        if invocation.pty {
+            let stdin = invocation.stdin.as_ref().map(std::fs::read).transpose()?;
+            crate::pty::output(invocation.command()?, stdin).map(|output| (output, None))
+        } else {
+            let (output, peak_memory) = crate::process_group::output(invocation.command()?, invocation.combine_output)?;
+            Ok((output, Some(peak_memory)))
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn run(&self, invocation: &Invocation) -> std::io::Result<Output> {
+        if invocation.pty {
+            let message = "running tests under a pseudo-terminal is only supported on Unix";
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, message));
+        }
+
+        let command = invocation.command()?;
+        if invocation.combine_output {
+            output_combined(command)
+        } else {
+            let mut command = command;
+            command.output()
+        }
+    }
+}
+
+/// Runs each test within a new docker container of the given image, with the test directory mounted
+/// read-only at the same path it has on the host. The binary being tested must be available within
+/// the image, or within the test directory.
+pub struct DockerRunner {
+    pub image: String,
+
+    /// The directory mounted within each container, usually the test directory
+    pub mount: PathBuf,
+}
+
+impl DockerRunner {
+    pub fn new(image: impl Into<String>, mount: impl Into<PathBuf>) -> DockerRunner {
+        DockerRunner {
+            image: image.into(),
+            mount: mount.into(),
+        }
+    }
+}
+
+impl CommandRunner for DockerRunner {
+    fn run(&self, invocation: &Invocation) -> std::io::Result<Output> {
+        // Paths given to the binary are relative to the current directory, so the container runs
+        // within the same path, under which the test directory is mounted at its usual place
+        let current_dir = std::path::absolute(invocation.current_dir.as_deref().unwrap_or(Path::new(".")))?;
+        let mount = std::path::absolute(&self.mount)?;
+
+        let mut args = vec!["run".to_owned(), "--rm".to_owned(), "--interactive".to_owned()];
+        if invocation.pty {
+            args.push("--tty".to_owned());
+        }
+        args.push(format!("--volume={}:{}:ro", mount.display(), mount.display()));
+        args.push(format!("--workdir={}", current_dir.display()));
+        for (key, value) in &invocation.env {
+            args.push(format!("--env={}={}", key, value));
+        }
+        if let Some(memory) = invocation.limits.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        if let Some(cpu_time) = invocation.limits.cpu_time {
+            let seconds = cpu_seconds(cpu_time);
+            args.push(format!("--ulimit=cpu={}:{}", seconds, seconds + 1));
+        }
+        args.push(self.image.clone());
+        args.push(invocation.program.clone());
+        args.extend(invocation.args.iter().cloned());
+
+        LocalRunner.run(&Invocation {
+            program: "docker".to_owned(),
+            args,
+            current_dir: None,
+            env: Vec::new(),
+
+            // Containers never inherit the environment of the host
+            clean_env: false,
+            stdin: invocation.stdin.clone(),
+            combine_output: invocation.combine_output,
+            limits: ResourceLimits::default(),
+
+            // Docker only allocates a terminal within the container when its own stdin is one
+            pty: invocation.pty,
+        })
+    }
+
+    fn id(&self) -> String {
+        format!("docker {} {}", self.image, self.mount.display())
+    }
+}
+
+/// Runs the command with its stdout and stderr redirected into the same pipe, as with `2>&1`,
+/// so that the order in which lines were written to either stream is preserved. The merged
+/// output is returned as stdout, leaving stderr empty.
+#[cfg(not(unix))]
+fn output_combined(mut command: Command) -> std::io::Result<Output> {
+    use std::io::Read;
+    let (mut reader, writer) = std::io::pipe()?;
+    command.stdout(writer.try_clone()?).stderr(writer);
+    let mut child = command.spawn()?;
+
+    // The command holds our copies of the write end, which must be closed for
+    // reading to finish once the child exits
+    drop(command);
+
+    let mut stdout = Vec::new();
+    reader.read_to_end(&mut stdout)?;
+    let status = child.wait()?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
@@ -1,15 +1,32 @@
+use crate::colors::Colorize;
+use crate::command_runner::{CommandRunner, LocalRunner, ResourceLimits};
 use crate::error::{TestError, TestResult};
-use colored::Colorize;
-use std::path::PathBuf;
+use crate::report::Report;
+use crate::reporter::Reporter;
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub struct TestConfig {
     /// The binary path to your program, typically "target/debug/myprogram"
     pub binary_path: PathBuf,
 
+    /// A second binary which is ran exactly the same way as `binary_path` for every test, e.g. a
+    /// reference implementation or the same compiler without an optimization. When set, each test
+    /// fails if the stdout, stderr, or exit status of the two binaries differ, and the expected
+    /// output within tests is ignored. Defaults to None.
+    pub reference_binary: Option<PathBuf>,
+
     /// The path to the subdirectory containing your tests. This subdirectory will be
-    /// searched recursively for all files.
+    /// searched recursively for all files, unless `test_files` is set.
     pub test_path: PathBuf,
 
+    /// If set, exactly these tests are ran rather than each file found within `test_path`. Any
+    /// directory listed is searched recursively as usual. See `TestConfig::with_test_files`.
+    pub test_files: Option<Vec<PathBuf>>,
+
     /// The sequence of characters starting at the beginning of a line that
     /// all test options should be prefixed with. This is typically a comment
     /// in your language. For example, if we had a C like language we could
@@ -20,6 +37,12 @@ pub struct TestConfig {
     /// The "args:" keyword used while parsing tests. Anything after
     /// `test_line_prefix + test_args_prefix` is read in as a space-delimited
     /// argument to the program.
+    ///
+    /// The placeholders `{file}`, `{dir}`, and `{stem}` expand to the test path, its directory, and
+    /// its file name without the extension. `{tmpdir}` expands to a fresh temporary directory which is
+    /// removed after the test. The test path is passed as the last argument unless `{file}` is used.
+    ///
+    /// A line of args ending in `\` is continued by the next line starting with `test_line_prefix`.
     pub test_args_prefix: String,
 
     /// The "expected stdout:" keyword used while parsing tests. Any line starting
@@ -61,9 +84,965 @@ pub struct TestConfig {
     /// ```
     pub test_exit_status_prefix: String,
 
+    /// The "expected stdout contains:" keyword used while parsing tests. This reads a block of text in
+    /// the same way as `test_stdout_prefix` which must appear somewhere within the actual stdout.
+    /// If the test has no "expected stdout:" block, the rest of stdout is not checked. May be given
+    /// multiple times.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stdout_contains_prefix = "expected stdout contains:"`
+    /// ```rust
+    /// // expected stdout contains: compiled successfully
+    /// ```
+    pub test_stdout_contains_prefix: String,
+
+    /// The same as `test_stdout_contains_prefix`, but for stderr.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stderr_contains_prefix = "expected stderr contains:"`
+    /// ```rust
+    /// // expected stderr contains:
+    /// // warning: unused variable `x`
+    /// ```
+    pub test_stderr_contains_prefix: String,
+
+    /// The "expected stdout excludes:" keyword used while parsing tests. The opposite of
+    /// `test_stdout_contains_prefix`: the test fails if the given block of text appears anywhere
+    /// within the actual stdout.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stdout_excludes_prefix = "expected stdout excludes:"`
+    /// ```rust
+    /// // expected stdout excludes: DEBUG
+    /// ```
+    pub test_stdout_excludes_prefix: String,
+
+    /// The same as `test_stdout_excludes_prefix`, but for stderr.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stderr_excludes_prefix = "expected stderr excludes:"`
+    /// ```rust
+    /// // expected stderr excludes: warning: unused variable
+    /// ```
+    pub test_stderr_excludes_prefix: String,
+
+    /// The "expected stdout starts with:" keyword used while parsing tests. Like
+    /// `test_stdout_contains_prefix`, but the given block of text must appear at the very start of the
+    /// actual stdout, ignoring leading whitespace. This is useful for output with a stable header but a
+    /// noisy remainder. May be given multiple times.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stdout_starts_with_prefix = "expected stdout starts with:"`
+    /// ```rust
+    /// // expected stdout starts with:
+    /// // mycompiler v1.0
+    /// ```
+    pub test_stdout_starts_with_prefix: String,
+
+    /// The same as `test_stdout_starts_with_prefix`, but for stderr.
+    pub test_stderr_starts_with_prefix: String,
+
+    /// The "expected stdout ends with:" keyword used while parsing tests. Like
+    /// `test_stdout_starts_with_prefix`, but the given block of text must appear at the very end of the
+    /// actual stdout, ignoring trailing whitespace.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stdout_ends_with_prefix = "expected stdout ends with:"`
+    /// ```rust
+    /// // expected stdout ends with: 0 errors
+    /// ```
+    pub test_stdout_ends_with_prefix: String,
+
+    /// The same as `test_stdout_ends_with_prefix`, but for stderr.
+    pub test_stderr_ends_with_prefix: String,
+
+    /// The "check:" keyword used while parsing tests. Like FileCheck's `CHECK:`, the rest of the line
+    /// must appear within stdout after the text matched by the previous check. Checks are tested in
+    /// the order they are written, and the rest of stdout isn't checked without `test_stdout_prefix`.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_check_prefix = "check:"`
+    /// ```rust
+    /// // check: define i32 @main()
+    /// // check-next: entry:
+    /// // check-not: alloca
+    /// // check: ret i32 0
+    /// ```
+    pub test_check_prefix: String,
+
+    /// The "check-next:" keyword used while parsing tests. The same as `test_check_prefix`, but the
+    /// text must appear on the line directly after the text matched by the previous check.
+    pub test_check_next_prefix: String,
+
+    /// The "check-not:" keyword used while parsing tests. The text must not appear within stdout
+    /// between the text matched by the checks before and after it, or the end of stdout if it is last.
+    pub test_check_not_prefix: String,
+
+    /// The "expected output:" keyword used while parsing tests. This reads the expected
+    /// output in the same way as `test_stdout_prefix`, but the binary's stdout and stderr
+    /// are merged into a single stream (as with `2>&1`) so the order of messages written
+    /// to either stream can be tested. When given, stdout and stderr are not checked separately.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_output_prefix = "expected output:"`
+    /// ```rust
+    /// // expected output:
+    /// // compiling main.c
+    /// // main.c:3: warning: unused variable
+    /// // done
+    /// ```
+    pub test_output_prefix: String,
+
+    /// The "expected file" keyword used while parsing tests. This expects a quoted path
+    /// followed by a ':', after which the expected contents of the file are read in the same
+    /// way as `test_stdout_prefix`. After running the test, the file must exist with the given
+    /// contents. The path is relative to the directory the binary is ran in (see `test_cwd_prefix`).
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_expected_file_prefix = "expected file"`
+    /// ```rust
+    /// // expected file "out/result.json":
+    /// // { "result": 3 }
+    /// ```
+    pub test_expected_file_prefix: String,
+
+    /// The "expected directory" keyword used while parsing tests. This expects a quoted path followed
+    /// by a ':' and the path of a golden directory tree, relative to the directory containing the test.
+    /// After running the test, the directory at the quoted path, relative to the directory the binary is
+    /// ran in, must contain exactly the same files with the same contents as the golden tree. Overwriting
+    /// the test replaces the golden tree with the directory produced by the binary.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_expected_directory_prefix = "expected directory"`
+    /// ```rust
+    /// // expected directory "generated": golden/generated
+    /// ```
+    pub test_expected_directory_prefix: String,
+
+    /// The "cwd:" keyword used while parsing tests. This sets the working directory the binary
+    /// is ran in for the given test. A relative directory is relative to the directory containing
+    /// the test file. When set, the test path and a relative binary path are passed as absolute
+    /// paths so that they still resolve correctly.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_cwd_prefix = "cwd:"`
+    /// ```rust
+    /// // cwd: ../fixtures
+    /// ```
+    pub test_cwd_prefix: String,
+
+    /// The "stdin file:" keyword used while parsing tests. The contents of the given file are
+    /// piped into the stdin of the binary. A relative path is relative to the directory
+    /// containing the test file. By default the binary receives no input.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_stdin_file_prefix = "stdin file:"`
+    /// ```rust
+    /// // stdin file: inputs/large.bin
+    /// ```
+    pub test_stdin_file_prefix: String,
+
+    /// The "args file:" keyword used while parsing tests. The contents of the given file are read
+    /// as space-delimited arguments in the same way as `test_args_prefix`, and passed to the binary
+    /// before the test's own args. Newlines separate arguments like any other whitespace. A relative
+    /// path is relative to the directory containing the test file.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_args_file_prefix = "args file:"`
+    /// ```rust
+    /// // args file: flags.txt
+    /// ```
+    pub test_args_file_prefix: String,
+
+    /// The "name:" keyword used while parsing tests. The rest of the line is a short, human readable
+    /// name for the test which is shown alongside its path in failures, the progress bar, and reports.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_name_prefix = "name:"`
+    /// ```rust
+    /// // name: constant folding of shifts
+    /// ```
+    pub test_name_prefix: String,
+
+    /// The "description:" keyword used while parsing tests. The rest of the line describes what the
+    /// test checks, and is shown alongside the details of the test within reports.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_description_prefix = "description:"`
+    /// ```rust
+    /// // description: shifts by a constant amount are folded before code generation
+    /// ```
+    pub test_description_prefix: String,
+
+    /// The "issue:" keyword used while parsing tests. The rest of the line is a URL or ticket ID tracking
+    /// a known problem with the test, which is printed alongside any failure of the test, including
+    /// quarantined failures, and within reports.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_issue_prefix = "issue:"`
+    /// ```rust
+    /// // issue: https://github.com/jfecher/golden-tests/issues/12
+    /// ```
+    pub test_issue_prefix: String,
+
+    /// The "requires:" keyword used while parsing tests. This expects either the name of a program
+    /// which must be found on the `PATH`, or `env` followed by the name of an environment variable
+    /// which must be set. If the condition isn't met the test is reported as skipped instead of being
+    /// ran. May be given multiple times.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_requires_prefix = "requires:"`
+    /// ```rust
+    /// // requires: python3
+    /// // requires: env DISPLAY
+    /// ```
+    pub test_requires_prefix: String,
+
+    /// The "serial:" keyword used while parsing tests. This expects a comma-separated list of lock names,
+    /// and tests naming the same lock are never ran at the same time, even when the rest of the tests
+    /// run in parallel. Useful for tests which share a global resource such as a port or a database.
+    /// A `serial:` line without any names uses a single lock shared by every other such test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_serial_prefix = "serial:"`
+    /// ```rust
+    /// // serial: database, port 8080
+    /// ```
+    pub test_serial_prefix: String,
+
+    /// The "sidecar files:" keyword used while parsing tests. This expects `true` or `false`
+    /// after the keyword and overrides `sidecar_files` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_sidecar_files_prefix = "sidecar files:"`
+    /// ```rust
+    /// // sidecar files: true
+    /// ```
+    pub test_sidecar_files_prefix: String,
+
+    /// The "binary output:" keyword used while parsing tests. This expects `true` or `false`
+    /// after the keyword. Tests with binary output store their expected output in sidecar files
+    /// (see `sidecar_files`) and compare it byte for byte against the actual output without
+    /// removing `\r` or applying any scrubbers. Differences are shown as a diff of hex dumps.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_binary_output_prefix = "binary output:"`
+    /// ```rust
+    /// // binary output: true
+    /// ```
+    pub test_binary_output_prefix: String,
+
+    /// The "ignore whitespace:" keyword used while parsing tests. This expects "true" or "false"
+    /// and overrides `TestConfig::ignore_whitespace` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_ignore_whitespace_prefix = "ignore whitespace:"`
+    /// ```rust
+    /// // ignore whitespace: true
+    /// ```
+    pub test_ignore_whitespace_prefix: String,
+
+    /// The "exact:" keyword used while parsing tests. This expects "true" or "false"
+    /// and overrides `TestConfig::exact` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_exact_prefix = "exact:"`
+    /// ```rust
+    /// // exact: true
+    /// ```
+    pub test_exact_prefix: String,
+
+    /// The "environments:" keyword used while parsing tests. This expects a comma-separated
+    /// list of environment names to run the test within, overriding the `paths` of each
+    /// `Environment`.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_environments_prefix = "environments:"`
+    /// ```rust
+    /// // environments: native, aarch64
+    /// ```
+    pub test_environments_prefix: String,
+
+    /// The "allow diff:" keyword used while parsing tests. This expects a regex which must match
+    /// an entire line. If every line which differs between the expected and actual output matches
+    /// one of these patterns, the differences are still shown but the test does not fail.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_allow_diff_prefix = "allow diff:"`
+    /// ```rust
+    /// // allow diff: compiled in [0-9.]+s
+    /// ```
+    pub test_allow_diff_prefix: String,
+
+    /// The "min similarity:" keyword used while parsing tests. This expects a ratio between 0 and 1,
+    /// or a percentage. If the actual output of a stream is at least this similar to the expected
+    /// output, the differences are still shown but the test does not fail.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_min_similarity_prefix = "min similarity:"`
+    /// ```rust
+    /// // min similarity: 0.95
+    /// ```
+    pub test_min_similarity_prefix: String,
+
+    /// The "before:" keyword used while parsing tests. The rest of the line is a command ran
+    /// through the system shell before the test, in the same directory as the test's binary.
+    /// If it fails, the test fails without running. May be given multiple times.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_before_prefix = "before:"`
+    /// ```rust
+    /// // before: sqlite3 scratch.db < schema.sql
+    /// ```
+    pub test_before_prefix: String,
+
+    /// The "after:" keyword used while parsing tests. The same as `test_before_prefix` but the
+    /// command is ran after the test's binary, whether or not the test passed.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_after_prefix = "after:"`
+    /// ```rust
+    /// // after: rm scratch.db
+    /// ```
+    pub test_after_prefix: String,
+
+    /// The "filter:" keyword used while parsing tests. The actual stdout and stderr of the test are
+    /// each piped through the given shell command before being compared, overriding the `filter`
+    /// config option. An empty command disables the filter for this test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_filter_prefix = "filter:"`
+    /// ```rust
+    /// // filter: sort
+    /// ```
+    pub test_filter_prefix: String,
+
+    /// The "include:" keyword used while parsing tests. This expects the path of a file, relative to
+    /// the directory containing the test, whose directives and expected output blocks are parsed as if
+    /// they were written in place of the include. Expected output given by an included file comes
+    /// before any expected output of the test itself, and isn't copied into the test when overwriting it.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_include_prefix = "include:"`
+    /// ```rust
+    /// // include: common/banner.txt
+    /// ```
+    pub test_include_prefix: String,
+
+    /// The "repeat:" keyword used while parsing tests. This expects a number of times to run the
+    /// binary and overrides `TestConfig::repeat` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_repeat_prefix = "repeat:"`
+    /// ```rust
+    /// // repeat: 5
+    /// ```
+    pub test_repeat_prefix: String,
+
+    /// The "compare:" keyword used while parsing tests. This expects `text`, `json`, `yaml`, or `toml` and
+    /// overrides `TestConfig::comparison` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_compare_prefix = "compare:"`
+    /// ```rust
+    /// // compare: json
+    /// ```
+    pub test_compare_prefix: String,
+
+    /// The "ignore fields:" keyword used while parsing tests. This expects a comma separated list of
+    /// fields to ignore in addition to `TestConfig::ignored_fields`.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_ignore_fields_prefix = "ignore fields:"`
+    /// ```rust
+    /// // ignore fields: timestamp, /build/id
+    /// ```
+    pub test_ignore_fields_prefix: String,
+
+    /// The "float tolerance:" keyword used while parsing tests. This expects an absolute tolerance, a
+    /// relative tolerance given as a percentage, or both, and overrides `TestConfig::float_tolerance`
+    /// for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_float_tolerance_prefix = "float tolerance:"`
+    /// ```rust
+    /// // float tolerance: 1e-9 0.001%
+    /// ```
+    pub test_float_tolerance_prefix: String,
+
+    /// The "pty:" keyword used while parsing tests. This expects either `true` or `false` and
+    /// overrides `TestConfig::pty` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_pty_prefix = "pty:"`
+    /// ```rust
+    /// // pty: true
+    /// ```
+    pub test_pty_prefix: String,
+
+    /// The "hermetic:" keyword used while parsing tests. This expects either `true` or `false` and
+    /// overrides `TestConfig::hermetic` for the given test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_hermetic_prefix = "hermetic:"`
+    /// ```rust
+    /// // hermetic: true
+    /// ```
+    pub test_hermetic_prefix: String,
+
+    /// The "fixtures:" keyword used while parsing tests. This expects a comma-separated list of files
+    /// or directories, relative to the directory containing the test, which are copied along with the
+    /// test when it is ran in `hermetic` mode. Fixtures can't be outside the directory of the test.
+    ///
+    /// Example with `test_line_prefix = "// "` and `test_fixtures_prefix = "fixtures:"`
+    /// ```rust
+    /// // fixtures: input.txt, data/
+    /// ```
+    pub test_fixtures_prefix: String,
+
+    /// The "send:" keyword used while parsing tests. Tests with this keyword are ran as an interactive
+    /// session: the binary is started with its stdin piped, then each "send:" line is written to its
+    /// stdin in turn, and each "expect:" waits for its stdout to contain the given text after the text
+    /// of the previous "expect:". Once every step is done, stdin is closed and the binary must exit.
+    /// Sessions are always ran locally rather than with `command_runner`.
+    ///
+    /// Example with `test_line_prefix = "// "`, `test_send_prefix = "send:"`, and `test_expect_prefix = "expect:"`
+    /// ```rust
+    /// // send: 1 + 2
+    /// // expect: 3
+    /// // send: let x = 4
+    /// // expect (30s): x = 4
+    /// ```
+    pub test_send_prefix: String,
+
+    /// The "expect:" keyword used while parsing tests. See `test_send_prefix`. Each step fails if
+    /// its text doesn't appear within `session_step_timeout`, or the timeout given in parentheses.
+    pub test_expect_prefix: String,
+
+    /// A line within expected stdout or stderr consisting only of this marker matches any
+    /// number of arbitrary lines (including none) in the actual output. Defaults to "...".
+    ///
+    /// Example with `test_line_prefix = "// "`
+    /// ```rust
+    /// // expected stdout:
+    /// // starting up
+    /// // ...
+    /// // done
+    /// ```
+    pub ignore_line_marker: String,
+
+    /// Collapse each run of spaces and tabs into a single space and ignore trailing whitespace on
+    /// each line when comparing output, so that changes which only affect alignment don't fail
+    /// tests. Can be overridden per test with `test_ignore_whitespace_prefix`.
+    pub ignore_whitespace: bool,
+
+    /// How the expected and actual stdout, merged output, and expected files of each test are compared.
+    /// Defaults to `Comparison::Text`. Stderr is always compared as text. Can be overridden per test
+    /// with `test_compare_prefix`.
+    pub comparison: Comparison,
+
+    /// Fields removed from structured output before comparing it when `comparison` isn't `Comparison::Text`,
+    /// e.g. timestamps. Each is either a JSON pointer such as `/build/id`, removing only that field, or a key
+    /// name such as `timestamp`, removing that key from every object. Tests can ignore more fields with
+    /// `test_ignore_fields_prefix`.
+    pub ignored_fields: Vec<String>,
+
+    /// Compare the numbers within expected and actual output with this tolerance rather than exactly, so
+    /// that numerical programs can be tested across platforms which round the last few digits differently.
+    /// Lines which differ in anything other than their numbers still fail. Disabled by default, and can be
+    /// overridden per test with `test_float_tolerance_prefix`.
+    pub float_tolerance: Option<FloatTolerance>,
+
+    /// The exit status expected of each test without a `test_exit_status_prefix` directive.
+    /// Defaults to None, in which case the exit status of such tests is ignored.
+    pub default_exit_status: Option<i32>,
+
+    /// Compare output exactly as written rather than trimming leading and trailing whitespace
+    /// and removing each `\r`. In this mode each line of an expected output block ends in a
+    /// newline, and the single space after a keyword like "expected stdout:" is not part of the
+    /// output. Can be overridden per test with `test_exact_prefix`.
+    pub exact: bool,
+
+    /// How carriage returns in the expected and actual output are normalized before comparing.
+    /// Defaults to `LineEndings::Lf`. Has no effect on tests using `exact` mode.
+    pub line_endings: LineEndings,
+
+    /// Fail any test file which doesn't contain a single keyword. This catches a mistyped
+    /// `test_line_prefix` or stray non-test files within the test directory, which would
+    /// otherwise pass vacuously as long as they output nothing.
+    pub strict_discovery: bool,
+
+    /// Glob patterns of files and directories within `test_path` which aren't tests, such as editor swap
+    /// files, READMEs, or build directories. Patterns are matched against paths relative to `test_path`,
+    /// and patterns without a `/` are also matched against the name of each file and directory, so `.*`
+    /// ignores every hidden file. Files within an ignored directory are ignored as well.
+    pub ignore_patterns: Vec<glob::Pattern>,
+
+    /// A shell command ran once before any tests, e.g. to build the binary being tested.
+    /// If it fails, no tests are ran.
+    pub setup: Option<String>,
+
+    /// Arguments passed to the binary before the arguments given by each test and its environment.
+    /// These may use the same placeholders as `test_args_prefix`.
+    pub base_args: Vec<String>,
+
+    /// Run each test's command line through the system shell (`sh -c`, or `cmd /C` on windows)
+    /// rather than running the binary directly. The test's `args:` are given to the shell as
+    /// written, allowing pipes, redirection, and invoking several programs within one test.
+    pub shell: bool,
+
+    /// Run the binary of each test with an empty environment rather than inheriting ours, so tests
+    /// can't accidentally depend on e.g. the `PATH`, locale, or `RUST_LOG` of whoever runs them. Only
+    /// the variables named in `env_allowlist` and those of the test's environment are set. The binary
+    /// itself is still found using our `PATH`. `before:` and `after:` commands are unaffected.
+    pub clean_env: bool,
+
+    /// The names of the environment variables passed through to the binary when `clean_env` is set
+    pub env_allowlist: Vec<String>,
+
+    /// Shell commands ran before every test, before any of the test's own `before:` commands
+    pub before_each: Vec<String>,
+
+    /// Shell commands ran after every test, after any of the test's own `after:` commands
+    pub after_each: Vec<String>,
+
+    /// A shell command the actual stdout and stderr of every test are each piped through before
+    /// being scrubbed and compared, e.g. `sort` or `jq .`. Can be overridden per test with
+    /// `test_filter_prefix`.
+    pub filter: Option<String>,
+
     /// Flag the current output as correct and regenerate the test files. This assumes the order of
     /// the `goldenfiles` sections can be moved around.
     pub overwrite_tests: bool,
+
+    /// If this environment variable is set to anything other than "" or "0", tests are
+    /// overwritten as if `overwrite_tests` were set. This allows blessing the output of tests
+    /// ran through `cargo test` without changing any code, e.g. `GOLDENTESTS_OVERWRITE=1 cargo test`.
+    /// Defaults to "GOLDENTESTS_OVERWRITE".
+    pub overwrite_env_var: Option<String>,
+
+    /// The environment variable set to a fresh temporary directory for the binary and the `before:` and
+    /// `after:` commands of each test, the same directory `{tmpdir}` expands to. The directory is removed
+    /// after the test, so tests can write scratch files there without polluting the repository or
+    /// colliding with other tests ran in parallel. Defaults to "GOLDENTESTS_TMPDIR". Setting this to
+    /// None only creates the directory for tests using `{tmpdir}`.
+    pub tmpdir_env_var: Option<String>,
+
+    /// Store the expected stdout and stderr of each test in the companion files `<test>.stdout`
+    /// and `<test>.stderr` next to the test instead of within the test file itself. A missing
+    /// sidecar file means the stream is expected to be empty. `overwrite_tests` will update these
+    /// files instead of the test. This can be changed per test with the "sidecar files:" keyword.
+    pub sidecar_files: bool,
+
+    /// Regex substitutions applied in order to the actual stdout and stderr of each test
+    /// before it is compared against the expected output or written back with `overwrite_tests`.
+    /// These are useful for replacing nondeterministic output such as timestamps or pointers
+    /// with a fixed placeholder. See `TestConfig::add_scrubber`.
+    pub scrubbers: Vec<(Regex, String)>,
+
+    /// The directory used to persist state between test runs, such as how long each test took
+    /// to run so that the longest tests can be started first. Defaults to "target/goldentests".
+    /// Setting this to None disables persisting any state.
+    pub state_directory: Option<PathBuf>,
+
+    /// The maximum width of the output. Long lines in diffs are wrapped and long paths are
+    /// shortened to fit within it. If this is None, the width of the terminal is used, and if
+    /// that can't be detected either (e.g. when output is redirected) the output isn't limited.
+    pub width: Option<usize>,
+
+    /// If set, the given number of slowest tests are listed after running all tests, along with
+    /// the total time taken. This helps track down which tests dominate the time of a test suite.
+    pub report_time: Option<usize>,
+
+    /// The number of times the binary of each test is ran. If the output of any run differs from the
+    /// output of the first, the test fails, which catches nondeterminism in the program being tested.
+    /// Defaults to 1. Can be overridden per test with `test_repeat_prefix`.
+    pub repeat: usize,
+
+    /// Run the binary of each test under a pseudo-terminal, so that programs which behave differently
+    /// when `isatty` is true, e.g. by printing colors, progress bars, or prompts, can be tested in that
+    /// mode. Stdout and stderr are both written to the terminal, so all output is checked as stdout.
+    /// Input is not echoed and newlines are not translated, so output otherwise matches that of a pipe.
+    /// Only supported on Unix, and not for interactive sessions. Can be overridden per test with `test_pty_prefix`.
+    pub pty: bool,
+
+    /// Copy each test, along with the files given by its `test_fixtures_prefix` directive, into a fresh
+    /// temporary directory and run the binary within it, so programs which modify their input or write
+    /// artifacts next to it can't dirty the test directory or interfere with tests ran in parallel. The
+    /// directory is removed once the test finishes. A `test_cwd_prefix` directive is relative to the copy
+    /// of the test. Can be overridden per test with `test_hermetic_prefix`.
+    pub hermetic: bool,
+
+    /// Fail each test whose run leaves new files within the directory the binary is ran in, other than
+    /// its expected files and those matching `stray_file_allowlist`, to catch programs which leak
+    /// artifacts. Files created by `before:` commands and removed by `after:` commands aren't stray.
+    /// Files created by other tests ran in parallel within the same directory are indistinguishable
+    /// from those of the test itself, so this is best combined with `hermetic` or `serial:` tests.
+    pub forbid_stray_files: bool,
+
+    /// Glob patterns, relative to the directory the binary is ran in, of files which aren't stray
+    /// even if a test creates them. See `forbid_stray_files`.
+    pub stray_file_allowlist: Vec<glob::Pattern>,
+
+    /// How long each "expect:" step of a session waits for its text, and how long the binary has to exit
+    /// once the session is done, before the test fails. Defaults to 10 seconds. See `test_send_prefix`.
+    pub session_step_timeout: Duration,
+
+    /// The maximum number of tests ran at once. Defaults to the number of CPUs. Tests are ran
+    /// within a thread pool owned by each run, so this doesn't affect any other use of rayon.
+    /// Has no effect unless the `parallel` feature is enabled.
+    pub max_jobs: Option<usize>,
+
+    /// Skip each test which passed on its last run if neither the test, its sidecar files, the files it
+    /// refers to such as its `stdin file:` or `fixtures:`, the binary, nor this config have changed since.
+    /// Which tests passed is stored within `state_directory`.
+    pub incremental: bool,
+
+    /// After running, print the number of passing and failing tests within each subdirectory of
+    /// `test_path`, so that it is clear at a glance which parts of a large suite regressed.
+    pub summary_by_directory: bool,
+
+    /// After running, list the tests which newly fail, newly pass, or are new compared to the last
+    /// run. The result of each test is stored within `state_directory`.
+    pub compare: bool,
+
+    /// Warn about or fail each test which took longer than it did in a recorded baseline.
+    /// Disabled by default. See `DurationRegression`.
+    pub duration_regression: Option<DurationRegression>,
+
+    /// Write the duration of each test ran to the baseline file of `duration_regression`
+    /// instead of comparing against it.
+    pub record_baseline: bool,
+
+    /// Only run the tests which failed on the last run, or every test if none did.
+    /// Which tests failed is stored within `state_directory`.
+    pub rerun_failed: bool,
+
+    /// Glob patterns matching tests which are quarantined, e.g. because they are flaky or still in
+    /// progress. Quarantined tests still run and are reported, but their failures don't fail the run.
+    /// Each pattern may match either the path of a test or its path relative to `test_path`.
+    pub quarantine: Vec<glob::Pattern>,
+
+    /// Alternative spellings of keywords, as `(alias, keyword)` pairs which both include the
+    /// `test_line_prefix`. Each line starting with an alias is read as if it started with its
+    /// keyword instead. Add aliases with `TestConfig::add_keyword_alias`.
+    pub keyword_aliases: Vec<(String, String)>,
+
+    /// The order tests are started and reported in. Defaults to `TestOrder::Sorted`.
+    pub order: TestOrder,
+
+    /// The seed tests are shuffled with when `order` is `TestOrder::Random`. If this is None, a new
+    /// seed is chosen each run. The seed is printed either way so that an order can be reproduced.
+    pub seed: Option<u64>,
+
+    /// Reports written after running all tests, e.g. a self-contained HTML file to share with others
+    pub reports: Vec<Report>,
+
+    /// Callbacks notified as each test starts and completes, and once the run is complete.
+    /// These are useful for embedding goldentests within another tool with its own progress UI.
+    pub reporters: Vec<Box<dyn Reporter>>,
+
+    /// A command and its arguments prepended to every invocation of the binary, e.g.
+    /// `["valgrind", "--error-exitcode=99"]` or `["wine"]`, so a suite can run under an emulator or
+    /// dynamic analysis tool. This wraps the `Environment::wrapper` of an environment, if any.
+    pub command_wrapper: Vec<String>,
+
+    /// The runtime command and its arguments used to run the binary if it is a `.wasm` file, e.g. one
+    /// compiled to `wasm32-wasi`. Defaults to `wasmtime run`. The runtime is given a `--dir` for the
+    /// directory the binary is ran in and the directory of each test file so that the test file can be
+    /// read from within the sandbox, and an `--env` for each environment variable of the environment.
+    pub wasm_runtime: Vec<String>,
+
+    /// Limits on the memory and CPU time of the command of each test. A test exceeding one of these
+    /// fails with `InnerTestError::ResourceLimitExceeded` rather than with its output. Unlimited by default.
+    pub limits: ResourceLimits,
+
+    /// Runs the command of each test. Defaults to `LocalRunner`, which runs each test as a local
+    /// process. A custom runner can run tests elsewhere, e.g. within a container or over ssh,
+    /// while still reusing the parsing, diffing, and reporting of goldentests.
+    pub command_runner: Box<dyn CommandRunner>,
+
+    /// How the differences between the expected and actual output of failing tests are printed.
+    /// Defaults to `DiffFormat::Numbered`.
+    pub diff_format: DiffFormat,
+
+    /// How the location of the test at the start of each reported failure is written. Defaults to
+    /// `LocationFormat::Default`.
+    pub location_format: LocationFormat,
+
+    /// How the events of a run are reported on stdout. Defaults to `MessageFormat::Human`.
+    pub message_format: MessageFormat,
+
+    /// Write the actual stdout, stderr, and exit status of each failing test to
+    /// `<state_directory>/<test>.actual.{stdout,stderr,status}` (or within the system's temporary
+    /// directory if `state_directory` is None) and mention their paths in the failure. This lets the
+    /// full output be inspected or diffed with other tools even when the printed diff is truncated.
+    /// The files of a test are removed once it passes again.
+    pub save_actual: bool,
+
+    /// A command and its arguments to run after each failing test, e.g. to upload its output or
+    /// open an issue. The actual stdout, stderr, and diff of the test are written to files within
+    /// `state_directory` (or the system's temporary directory if that is None) beforehand. The
+    /// hook is given the following environment variables:
+    ///
+    /// - `GOLDENTESTS_TEST`: The path of the failing test
+    /// - `GOLDENTESTS_ENVIRONMENT`: The name of the environment the test failed in, if any
+    /// - `GOLDENTESTS_STDOUT`, `GOLDENTESTS_STDERR`: Paths to files with the actual output of the test
+    /// - `GOLDENTESTS_DIFF`: The path to a file with the differences from the expected output
+    /// - `GOLDENTESTS_EXIT_STATUS`: The exit status of the test, or empty if it was killed by a signal
+    ///
+    /// The stdout of the hook is redirected to stderr so it can't corrupt JSON messages written to stdout.
+    pub failure_hook: Option<Vec<String>>,
+
+    /// The environments to run each test in. If this is empty each test is ran once as-is.
+    /// Otherwise each test is ran once within each environment that applies to it and the
+    /// results are reported per environment. See `Environment`.
+    pub environments: Vec<Environment>,
+}
+
+/// An execution environment a test may be ran in, e.g. a native run and one under an emulator.
+/// Tests can be run within multiple environments, and each run is reported separately.
+pub struct Environment {
+    /// The name this environment is reported with and referred to by the "environments:" keyword
+    pub name: String,
+
+    /// A command and its arguments to prepend to each invocation of the binary,
+    /// e.g. `["qemu-aarch64", "-L", "/usr/aarch64-linux-gnu"]`.
+    pub wrapper: Vec<String>,
+
+    /// Environment variables to set when running the binary
+    pub env: Vec<(String, String)>,
+
+    /// Additional arguments passed to the binary before the arguments given by each test.
+    /// These may use the same placeholders as `TestConfig::test_args_prefix`.
+    pub args: Vec<String>,
+
+    /// Glob patterns, relative to the test path, selecting the tests to run within this
+    /// environment. If this is empty, every test is ran within this environment.
+    /// A test can override this with the "environments:" keyword.
+    pub paths: Vec<glob::Pattern>,
+}
+
+impl Environment {
+    /// True if this environment should run the given test path, relative to the test directory,
+    /// when the test does not select its environments explicitly.
+    pub fn applies_to(&self, relative_test_path: &Path) -> bool {
+        self.paths.is_empty() || self.paths.iter().any(|pattern| pattern.matches_path(relative_test_path))
+    }
+}
+
+/// A coarse performance check comparing how long each test took against a recorded baseline,
+/// so golden tests can double as a guard against the program being tested getting slower.
+/// Tests which took under 50ms are never considered to regress since their durations are mostly noise.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DurationRegression {
+    /// A JSON file mapping the path of each test to its baseline duration in seconds, written when
+    /// `TestConfig::record_baseline` is set. Unlike `state_directory`, this is meant to be committed.
+    pub baseline: PathBuf,
+
+    /// How many percent slower than its baseline a test may get before it regresses. Defaults to 50.
+    #[serde(default = "DurationRegression::default_threshold")]
+    pub threshold: f64,
+
+    /// If true, tests which regress fail. Otherwise a warning is printed for each of them.
+    #[serde(default)]
+    pub fail: bool,
+}
+
+impl DurationRegression {
+    /// Compares against the given baseline file, warning about tests which are over 50% slower
+    pub fn new(baseline: impl Into<PathBuf>) -> DurationRegression {
+        DurationRegression {
+            baseline: baseline.into(),
+            threshold: DurationRegression::default_threshold(),
+            fail: false,
+        }
+    }
+
+    fn default_threshold() -> f64 {
+        50.0
+    }
+}
+
+/// How far apart the numbers within expected and actual output may be while still being considered
+/// equal. Two numbers are equal if they differ by at most `absolute`, or by at most `relative` times the
+/// larger of the two. Both default to 0.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloatTolerance {
+    #[serde(default)]
+    pub absolute: f64,
+
+    #[serde(default)]
+    pub relative: f64,
+}
+
+impl FloatTolerance {
+    /// True if the two numbers are equal within this tolerance
+    pub fn allows(&self, expected: f64, actual: f64) -> bool {
+        let difference = (expected - actual).abs();
+        difference <= self.absolute || difference <= self.relative * expected.abs().max(actual.abs())
+    }
+}
+
+/// How line endings are normalized before comparing expected and actual output
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// Convert each `\r\n` into `\n` on windows, and leave line endings untouched elsewhere
+    Native,
+
+    /// Convert each `\r\n` into `\n`. A `\r` anywhere other than the end of a line is kept,
+    /// so carriage returns used to overwrite a line can still be tested.
+    Lf,
+
+    /// Leave line endings untouched
+    Exact,
+}
+
+/// The order tests are ran in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestOrder {
+    /// Sorted by path so that output is the same across machines. Tests are still started
+    /// longest first when ran in parallel, but are reported in sorted order.
+    Sorted,
+
+    /// A random order, useful for finding tests which depend on other tests running first
+    Random,
+
+    /// The order the filesystem lists each file in, which may differ between machines
+    Discovery,
+}
+
+impl std::str::FromStr for TestOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TestOrder, String> {
+        match s {
+            "sorted" => Ok(TestOrder::Sorted),
+            "random" => Ok(TestOrder::Random),
+            "discovery" => Ok(TestOrder::Discovery),
+            other => Err(format!(
+                "expected 'sorted', 'random', or 'discovery' but found '{}'",
+                other
+            )),
+        }
+    }
+}
+
+/// How the expected and actual output of each test are compared
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    /// Line by line as text
+    Text,
+
+    /// Parsed as JSON and compared structurally, so formatting and the order of keys don't matter
+    Json,
+
+    /// Parsed as YAML and compared structurally, the same as `Json`
+    Yaml,
+
+    /// Parsed as TOML and compared structurally, the same as `Json`
+    Toml,
+}
+
+impl Comparison {
+    /// The name of the format output is parsed as, e.g. "JSON"
+    pub(crate) fn format_name(self) -> &'static str {
+        match self {
+            Comparison::Text => "text",
+            Comparison::Json => "JSON",
+            Comparison::Yaml => "YAML",
+            Comparison::Toml => "TOML",
+        }
+    }
+}
+
+impl std::str::FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Comparison, String> {
+        match s {
+            "text" => Ok(Comparison::Text),
+            "json" => Ok(Comparison::Json),
+            "yaml" => Ok(Comparison::Yaml),
+            "toml" => Ok(Comparison::Toml),
+            other => Err(format!(
+                "expected 'text', 'json', 'yaml', or 'toml' but found '{}'",
+                other
+            )),
+        }
+    }
+}
+
+/// How differences between expected and actual output are printed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffFormat {
+    /// Every line of the actual output, numbered, with removed and added lines colored
+    Numbered,
+
+    /// A standard `diff -u` patch with `---` and `+++` headers naming the test file, which
+    /// can be piped into `patch` or reviewed with other diff tooling
+    Unified,
+
+    /// The expected and actual output in two columns with a marker on each changed row,
+    /// which is easier to read for wide, table-like output
+    #[serde(rename = "side-by-side")]
+    SideBySide,
+}
+
+/// How the events of a run are reported on stdout
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Failures and a summary of the run meant to be read by people
+    Human,
+
+    /// A line of JSON for each test started and finished and for the end of the run, meant to be read by
+    /// other tools such as editor plugins. The human-readable output is written to stderr instead.
+    /// See `reporter::JsonMessages`.
+    Json,
+}
+
+impl std::str::FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MessageFormat, String> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            other => Err(format!("expected 'human' or 'json' but found '{}'", other)),
+        }
+    }
+}
+
+/// How the location of the test at the start of each reported failure is written
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationFormat {
+    /// `path:line: message`, with a label such as `- WARNING:` before messages which aren't failures
+    Default,
+
+    /// `path:line:column: error: message`, the format of compiler diagnostics, which many terminals
+    /// and editors turn into links to the test and the line of its expectation
+    Compiler,
+}
+
+impl std::str::FromStr for LocationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LocationFormat, String> {
+        match s {
+            "default" => Ok(LocationFormat::Default),
+            "compiler" => Ok(LocationFormat::Compiler),
+            other => Err(format!("expected 'default' or 'compiler' but found '{}'", other)),
+        }
+    }
+}
+
+impl std::str::FromStr for DiffFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DiffFormat, String> {
+        match s {
+            "numbered" => Ok(DiffFormat::Numbered),
+            "unified" => Ok(DiffFormat::Unified),
+            "side-by-side" => Ok(DiffFormat::SideBySide),
+            other => Err(format!(
+                "expected 'numbered', 'unified', or 'side-by-side' but found '{}'",
+                other
+            )),
+        }
+    }
+}
+
+impl LineEndings {
+    /// Normalizes the line endings of the given text
+    pub(crate) fn normalize(self, text: &str) -> Cow<'_, str> {
+        let strip = match self {
+            LineEndings::Native => cfg!(windows),
+            LineEndings::Lf => true,
+            LineEndings::Exact => false,
+        };
+
+        if !strip || !text.contains('\r') {
+            return text.into();
+        }
+
+        let text = text.replace("\r\n", "\n");
+        match text.strip_suffix('\r') {
+            Some(stripped) => stripped.to_owned().into(),
+            None => text.into(),
+        }
+    }
 }
 
 impl TestConfig {
@@ -111,7 +1090,8 @@ impl TestConfig {
     ///
     /// If you want to change these default keywords you can also create a TestConfig
     /// via `TestConfig::with_custom_keywords` which will allow you to specify each.
-    #[allow(unused)]
+    ///
+    /// Tests are not overwritten by default, see `TestConfig::with_overwrite`.
     pub fn new<Binary, Tests>(binary_path: Binary, test_path: Tests, test_line_prefix: &str) -> TestResult<TestConfig>
     where
         Binary: Into<PathBuf>,
@@ -136,6 +1116,7 @@ impl TestConfig {
     ///
     /// If you don't want to change any of the defaults, you can use `TestConfig::new` to construct
     /// a TestConfig with the default keywords (which are listed in its documentation).
+    #[allow(clippy::too_many_arguments)]
     pub fn with_custom_keywords<Binary, Tests>(
         binary_path: Binary,
         test_path: Tests,
@@ -159,6 +1140,20 @@ impl TestConfig {
             );
 
             Err(TestError::MissingTests(test_path))
+        } else if test_path.is_file() {
+            // A file is a manifest listing the tests to run
+            let test_files = crate::manifest::read_manifest(&test_path)?;
+            let config = TestConfig::with_custom_keywords(
+                binary_path,
+                crate::manifest::manifest_directory(&test_path),
+                test_line_prefix,
+                test_args_prefix,
+                test_stdout_prefix,
+                test_stderr_prefix,
+                test_exit_status_prefix,
+                overwrite_tests,
+            )?;
+            Ok(config.with_test_files(test_files))
         } else if !test_path.is_dir() {
             eprintln!(
                 "{}",
@@ -172,14 +1167,291 @@ impl TestConfig {
 
             Ok(TestConfig {
                 binary_path,
+                reference_binary: None,
+                test_files: None,
                 test_path,
                 test_args_prefix: prefixed(test_args_prefix),
                 test_stdout_prefix: prefixed(test_stdout_prefix),
                 test_stderr_prefix: prefixed(test_stderr_prefix),
                 test_exit_status_prefix: prefixed(test_exit_status_prefix),
+                test_stdout_contains_prefix: prefixed("expected stdout contains:"),
+                test_stderr_contains_prefix: prefixed("expected stderr contains:"),
+                test_stdout_excludes_prefix: prefixed("expected stdout excludes:"),
+                test_stderr_excludes_prefix: prefixed("expected stderr excludes:"),
+                test_stdout_starts_with_prefix: prefixed("expected stdout starts with:"),
+                test_stderr_starts_with_prefix: prefixed("expected stderr starts with:"),
+                test_stdout_ends_with_prefix: prefixed("expected stdout ends with:"),
+                test_stderr_ends_with_prefix: prefixed("expected stderr ends with:"),
+                test_check_prefix: prefixed("check:"),
+                test_check_next_prefix: prefixed("check-next:"),
+                test_check_not_prefix: prefixed("check-not:"),
+                test_output_prefix: prefixed("expected output:"),
+                test_expected_file_prefix: prefixed("expected file"),
+                test_expected_directory_prefix: prefixed("expected directory"),
+                test_cwd_prefix: prefixed("cwd:"),
+                test_stdin_file_prefix: prefixed("stdin file:"),
+                test_args_file_prefix: prefixed("args file:"),
+                test_name_prefix: prefixed("name:"),
+                test_description_prefix: prefixed("description:"),
+                test_issue_prefix: prefixed("issue:"),
+                test_requires_prefix: prefixed("requires:"),
+                test_serial_prefix: prefixed("serial:"),
+                test_sidecar_files_prefix: prefixed("sidecar files:"),
+                test_binary_output_prefix: prefixed("binary output:"),
+                test_ignore_whitespace_prefix: prefixed("ignore whitespace:"),
+                test_exact_prefix: prefixed("exact:"),
+                test_environments_prefix: prefixed("environments:"),
+                test_allow_diff_prefix: prefixed("allow diff:"),
+                test_min_similarity_prefix: prefixed("min similarity:"),
+                test_before_prefix: prefixed("before:"),
+                test_after_prefix: prefixed("after:"),
+                test_filter_prefix: prefixed("filter:"),
+                test_include_prefix: prefixed("include:"),
+                test_repeat_prefix: prefixed("repeat:"),
+                test_compare_prefix: prefixed("compare:"),
+                test_ignore_fields_prefix: prefixed("ignore fields:"),
+                test_float_tolerance_prefix: prefixed("float tolerance:"),
+                test_pty_prefix: prefixed("pty:"),
+                test_hermetic_prefix: prefixed("hermetic:"),
+                test_fixtures_prefix: prefixed("fixtures:"),
+                test_send_prefix: prefixed("send:"),
+                test_expect_prefix: prefixed("expect:"),
                 test_line_prefix,
+                ignore_line_marker: "...".to_string(),
                 overwrite_tests,
+                overwrite_env_var: Some("GOLDENTESTS_OVERWRITE".to_string()),
+                tmpdir_env_var: Some("GOLDENTESTS_TMPDIR".to_string()),
+                sidecar_files: false,
+                scrubbers: Vec::new(),
+                environments: Vec::new(),
+                width: None,
+                report_time: None,
+                max_jobs: None,
+                compare: false,
+                summary_by_directory: false,
+                duration_regression: None,
+                record_baseline: false,
+                repeat: 1,
+                pty: false,
+                hermetic: false,
+                forbid_stray_files: false,
+                stray_file_allowlist: Vec::new(),
+                session_step_timeout: Duration::from_secs(10),
+                incremental: false,
+                rerun_failed: false,
+                quarantine: Vec::new(),
+                keyword_aliases: Vec::new(),
+                order: TestOrder::Sorted,
+                seed: None,
+                reports: Vec::new(),
+                reporters: Vec::new(),
+                command_runner: Box::new(LocalRunner),
+                limits: ResourceLimits::default(),
+                command_wrapper: Vec::new(),
+                wasm_runtime: vec!["wasmtime".to_owned(), "run".to_owned()],
+                diff_format: DiffFormat::Numbered,
+                location_format: LocationFormat::Default,
+                message_format: MessageFormat::Human,
+                failure_hook: None,
+                save_actual: false,
+                ignore_whitespace: false,
+                float_tolerance: None,
+                comparison: Comparison::Text,
+                ignored_fields: Vec::new(),
+                exact: false,
+                default_exit_status: None,
+                line_endings: LineEndings::Lf,
+                strict_discovery: false,
+                ignore_patterns: Vec::new(),
+                setup: None,
+                base_args: Vec::new(),
+                shell: false,
+                clean_env: false,
+                env_allowlist: Vec::new(),
+                before_each: Vec::new(),
+                filter: None,
+                after_each: Vec::new(),
+                state_directory: Some(PathBuf::from("target/goldentests")),
             })
         }
     }
+
+    /// Sets whether failing tests should be overwritten with their actual output rather than
+    /// failing, the same as the goldentests binary's `--overwrite` flag.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let update = std::env::args().any(|arg| arg == "--bless");
+    /// let config = TestConfig::new("python", "examples", "# ")?.with_overwrite(update);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_overwrite(mut self, overwrite_tests: bool) -> TestConfig {
+        self.overwrite_tests = overwrite_tests;
+        self
+    }
+
+    /// Runs exactly the given tests rather than each file found within `test_path`, e.g. to run a
+    /// curated subset of the suite. Any directory given is searched recursively as usual. Passing a
+    /// manifest file listing the tests as the test path of `TestConfig::new` does the same.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let smoke_tests = vec!["examples/multiline.py".into(), "examples/contains.py".into()];
+    /// let config = TestConfig::new("python", "examples", "# ")?.with_test_files(smoke_tests);
+    /// config.run_tests()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_test_files(mut self, test_files: Vec<PathBuf>) -> TestConfig {
+        self.test_files = Some(test_files);
+        self
+    }
+
+    /// Adds a regex scrubber which replaces each match of `pattern` in the actual output
+    /// of a test with `replacement` before comparing. The replacement may refer to capture
+    /// groups using the syntax of `regex::Regex::replace_all`.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let mut config = TestConfig::new("python", "examples", "# ")?;
+    /// config.add_scrubber(r"0x[0-9a-f]+", "<pointer>")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_scrubber(&mut self, pattern: &str, replacement: &str) -> TestResult<()> {
+        let regex = Regex::new(pattern).map_err(|err| TestError::InvalidRegex(pattern.to_owned(), err))?;
+        self.scrubbers.push((regex, replacement.to_owned()));
+        Ok(())
+    }
+
+    /// Accepts `alias` as another spelling of `keyword` while parsing tests, e.g. so that
+    /// `stdout:` may be written in place of `expected stdout:`. Neither should include the
+    /// `test_line_prefix`. Qualifiers such as `(first 2 lines)` may follow an alias as usual.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let mut config = TestConfig::new("python", "examples", "# ")?;
+    /// config.add_keyword_alias("expected stdout:", "stdout:")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_keyword_alias(&mut self, keyword: &str, alias: &str) -> TestResult<()> {
+        let invalid = |reason: &str| TestError::InvalidKeywordAlias(alias.to_owned(), reason.to_owned());
+        let prefixed_keyword = format!("{}{}", self.test_line_prefix, keyword);
+
+        if !self.keywords().contains(&prefixed_keyword.as_str()) {
+            return Err(invalid(&format!("'{}' is not a keyword", keyword)));
+        } else if alias.trim().is_empty() {
+            return Err(invalid("aliases cannot be empty"));
+        } else if alias.ends_with(':') != keyword.ends_with(':') {
+            let reason = if keyword.ends_with(':') {
+                "must end with ':'"
+            } else {
+                "must not end with ':'"
+            };
+            return Err(invalid(&format!("aliases of '{}' {}", keyword, reason)));
+        }
+
+        let alias = format!("{}{}", self.test_line_prefix, alias);
+        self.keyword_aliases.push((alias, prefixed_keyword));
+        Ok(())
+    }
+
+    /// Rewrites a line of a test starting with one of the `keyword_aliases` to start with the
+    /// keyword it is an alias of instead. Other lines are returned unchanged.
+    pub(crate) fn canonicalize_keyword<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        // Longer aliases are tried first so that e.g. `stdout contains:` isn't read as `stdout:`
+        let mut aliases: Vec<_> = self.keyword_aliases.iter().collect();
+        aliases.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+
+        for (alias, keyword) in aliases {
+            // Qualifiers are written between a keyword and its colon, so only the stem is matched
+            let alias_stem = alias.strip_suffix(':').unwrap_or(alias);
+            let keyword_stem = keyword.strip_suffix(':').unwrap_or(keyword);
+
+            if let Some(rest) = line.strip_prefix(alias_stem) {
+                let matches = if alias.ends_with(':') {
+                    rest.trim_start().starts_with([':', '('])
+                } else {
+                    rest.starts_with(char::is_whitespace)
+                };
+
+                if matches {
+                    return Cow::Owned(format!("{}{}", keyword_stem, rest));
+                }
+            }
+        }
+        Cow::Borrowed(line)
+    }
+
+    /// True if the given test matches one of the `quarantine` patterns
+    pub(crate) fn is_quarantined(&self, test: &Path) -> bool {
+        let relative_path = test.strip_prefix(&self.test_path).unwrap_or(test);
+        self.quarantine
+            .iter()
+            .any(|pattern| pattern.matches_path(test) || pattern.matches_path(relative_path))
+    }
+
+    /// True if tests should be overwritten, either because `overwrite_tests` is set or via `overwrite_env_var`
+    pub(crate) fn should_overwrite(&self) -> bool {
+        let enabled = |value: std::ffi::OsString| !value.is_empty() && value != "0";
+        self.overwrite_tests || self.overwrite_env_var.as_ref().and_then(std::env::var_os).is_some_and(enabled)
+    }
+
+    /// Every keyword recognized while parsing tests, each including the `test_line_prefix`
+    pub(crate) fn keywords(&self) -> Vec<&str> {
+        vec![
+            &self.test_args_prefix,
+            &self.test_stdout_prefix,
+            &self.test_stderr_prefix,
+            &self.test_exit_status_prefix,
+            &self.test_stdout_contains_prefix,
+            &self.test_stderr_contains_prefix,
+            &self.test_stdout_excludes_prefix,
+            &self.test_stderr_excludes_prefix,
+            &self.test_stdout_starts_with_prefix,
+            &self.test_stderr_starts_with_prefix,
+            &self.test_stdout_ends_with_prefix,
+            &self.test_stderr_ends_with_prefix,
+            &self.test_check_prefix,
+            &self.test_check_next_prefix,
+            &self.test_check_not_prefix,
+            &self.test_output_prefix,
+            &self.test_expected_file_prefix,
+            &self.test_expected_directory_prefix,
+            &self.test_cwd_prefix,
+            &self.test_stdin_file_prefix,
+            &self.test_args_file_prefix,
+            &self.test_name_prefix,
+            &self.test_description_prefix,
+            &self.test_issue_prefix,
+            &self.test_requires_prefix,
+            &self.test_serial_prefix,
+            &self.test_sidecar_files_prefix,
+            &self.test_binary_output_prefix,
+            &self.test_ignore_whitespace_prefix,
+            &self.test_exact_prefix,
+            &self.test_environments_prefix,
+            &self.test_allow_diff_prefix,
+            &self.test_before_prefix,
+            &self.test_after_prefix,
+            &self.test_filter_prefix,
+            &self.test_include_prefix,
+            &self.test_repeat_prefix,
+            &self.test_compare_prefix,
+            &self.test_ignore_fields_prefix,
+            &self.test_float_tolerance_prefix,
+            &self.test_min_similarity_prefix,
+            &self.test_pty_prefix,
+            &self.test_hermetic_prefix,
+            &self.test_fixtures_prefix,
+            &self.test_send_prefix,
+            &self.test_expect_prefix,
+        ]
+    }
 }
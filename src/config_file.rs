@@ -0,0 +1,614 @@
+//! Support for reading additional test options from a `goldentests.toml` file.
+//!
+//! Every field in the file is optional. An example file looks like:
+//!
+//! ```toml
+//! # Each pattern is a regex which is replaced in the actual stdout and stderr
+//! # of every test before comparing it against the expected output.
+//! [scrub]
+//! "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}" = "<uuid>"
+//! "0x[0-9a-f]+" = "<pointer>"
+//!
+//! # Overrides the binary each test is ran with
+//! binary_path = "target/debug/mycompiler"
+//!
+//! # Run every test against a second binary as well, failing tests where the output of the two differs
+//! reference_binary = "target/debug/mycompiler-reference"
+//!
+//! # Arguments passed to the binary before the arguments of each test
+//! base_args = "--color never"
+//!
+//! # Store expected output in `<test>.stdout` and `<test>.stderr` files
+//! sidecar_files = false
+//!
+//! # Collapse runs of spaces and tabs and ignore trailing whitespace when comparing output
+//! ignore_whitespace = false
+//!
+//! # Compare output byte for byte without trimming whitespace or removing \r
+//! exact = false
+//!
+//! # How \r\n is normalized before comparing: "lf" converts it to \n, "native" only
+//! # does so on windows, and "exact" leaves it untouched
+//! line_endings = "lf"
+//!
+//! # The exit status expected of each test without an `expected exit status:` directive
+//! default_exit_status = 0
+//!
+//! # Fail any file in the test directory without a single test directive
+//! strict_discovery = false
+//!
+//! # Files and directories within the test directory which aren't tests
+//! ignore = ["**/target/**", "*.md", ".*"]
+//!
+//! # The order tests are ran in: "sorted" by path, "random", or the "discovery" order of the filesystem
+//! order = "sorted"
+//!
+//! # The seed a "random" order is shuffled with, defaults to a new seed each run
+//! seed = 42
+//!
+//! # Where to persist state between runs, such as test durations
+//! state_directory = "target/goldentests"
+//!
+//! # Run the binary of each test this many times, failing if the output of any run differs
+//! repeat = 1
+//!
+//! # Run each test under a pseudo-terminal, for programs which behave differently when ran interactively
+//! pty = false
+//!
+//! # Run each test within a temporary copy of it and its fixtures: so it can't modify the test directory
+//! hermetic = false
+//!
+//! # Fail tests which leave new files in the directory the program is ran in, other than these
+//! forbid_stray_files = false
+//! stray_file_allowlist = ["target/**", "*.log"]
+//!
+//! # How many seconds each expect: step of an interactive session waits for its output
+//! session_step_timeout = 10
+//!
+//! # List the tests which newly fail, newly pass, or are new compared to the last run
+//! compare = false
+//!
+//! # Print the number of passing and failing tests within each subdirectory after running
+//! summary_by_directory = false
+//!
+//! # The maximum number of tests ran at once, defaults to the number of CPUs
+//! max_jobs = 4
+//!
+//! # The maximum width of the output, defaults to the width of the terminal
+//! width = 100
+//!
+//! # How differences from the expected output are printed: "numbered" lines,
+//! # a "unified" diff, or "side-by-side" columns
+//! diff_format = "numbered"
+//!
+//! # How the location of each failure is written: "default", or "compiler" for
+//! # `path:line:column: error: message` like a compiler diagnostic
+//! location_format = "default"
+//!
+//! # List the given number of slowest tests along with the total time taken after running
+//! report_time = 10
+//!
+//! # A command prepended to every invocation of the binary, e.g. to run it under an emulator
+//! command_wrapper = "valgrind --error-exitcode=99"
+//!
+//! # Where each test is ran: "local" processes, or "docker:<image>" to run each test in a new
+//! # container of the given image with the test directory mounted read-only
+//! runner = "docker:ubuntu:24.04"
+//!
+//! # Limits on the memory and CPU time (in seconds) of each test. A memory limit may
+//! # end with K, M, or G. Only enforced on Unix.
+//! memory_limit = "512M"
+//! cpu_time_limit = 10
+//!
+//! # The runtime used to run the binary if it is a .wasm file
+//! wasm_runtime = "wasmtime run"
+//!
+//! # Write the actual output of each failing test to <state_directory>/<test>.actual.*
+//! save_actual = false
+//!
+//! # A command ran after each failing test. See `TestConfig::failure_hook`
+//! # for the environment variables given to it.
+//! failure_hook = "scripts/upload-failure.sh"
+//!
+//! # A shell command ran once before any tests. If it fails, no tests are ran.
+//! setup = "cargo build --bin mycompiler"
+//!
+//! # Run each test's command line through `sh -c` so its args may use pipes and redirection
+//! shell = false
+//!
+//! # Run each binary with an empty environment, apart from the variables listed in env_allowlist
+//! clean_env = true
+//! env_allowlist = ["HOME", "LANG"]
+//!
+//! # Shell commands ran before and after every test, see the `before:` and `after:` keywords
+//! before_each = ["mkdir -p target/scratch"]
+//! after_each = ["rm -rf target/scratch"]
+//!
+//! # A shell command the actual stdout and stderr of every test are piped through before comparing,
+//! # see the `filter:` keyword
+//! filter = "sort"
+//!
+//! # Other spellings accepted for each keyword, which is written without the test line prefix
+//! [keyword_aliases]
+//! "expected stdout:" = ["stdout:", "out:"]
+//! "expected exit status:" = ["exit:"]
+//!
+//! # Each test is ran once within every environment that applies to it
+//! [environments.native]
+//!
+//! [environments.aarch64]
+//! wrapper = "qemu-aarch64 -L /usr/aarch64-linux-gnu"
+//! args = "--target aarch64"
+//! env = { LANG = "C" }
+//! # Glob patterns relative to the test directory. Defaults to every test.
+//! paths = ["codegen/**"]
+//!
+//! # Tests which still run and are reported, but whose failures don't fail the run
+//! quarantine = ["tests/flaky/*.ant"]
+//!
+//! # Compare output as "text", or parse it as "json", "yaml", or "toml" and compare it structurally,
+//! # ignoring the given fields
+//! comparison = "text"
+//! ignored_fields = ["timestamp", "/build/id"]
+//!
+//! # Compare the numbers within output with a tolerance rather than exactly. Numbers are equal if they
+//! # differ by at most `absolute`, or by at most `relative` times the larger of the two.
+//! [float_tolerance]
+//! absolute = 1e-9
+//! relative = 1e-6
+//!
+//! # Warn about each test which is over `threshold` percent slower than its duration in `baseline`,
+//! # or fail it if `fail` is set. Record the baseline with `goldentests --record-baseline`.
+//! [duration_regression]
+//! baseline = "tests/baseline-durations.json"
+//! threshold = 50
+//! fail = false
+//!
+//! # Profiles override any of the options above when selected, e.g. with
+//! # `goldentests --profile release` or `TestConfig::load_config_file_with_profile`
+//! [profile.release]
+//! binary_path = "target/release/mycompiler"
+//! base_args = "--color never -O2"
+//! ```
+use crate::command_runner::{DockerRunner, LocalRunner};
+use crate::config::{
+    Comparison, DiffFormat, DurationRegression, Environment, FloatTolerance, LineEndings, LocationFormat, TestConfig,
+    TestOrder,
+};
+use crate::error::{TestError, TestResult};
+
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The default name of the config file the goldentests binary looks for in the current directory.
+pub const DEFAULT_CONFIG_FILE: &str = "goldentests.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    binary_path: Option<PathBuf>,
+
+    reference_binary: Option<PathBuf>,
+
+    base_args: Option<String>,
+
+    /// Regex patterns mapped to their replacements, kept in the order they were written
+    #[serde(default)]
+    scrub: IndexMap<String, String>,
+
+    sidecar_files: Option<bool>,
+
+    state_directory: Option<PathBuf>,
+
+    order: Option<TestOrder>,
+
+    seed: Option<u64>,
+
+    max_jobs: Option<usize>,
+
+    repeat: Option<usize>,
+
+    pty: Option<bool>,
+
+    hermetic: Option<bool>,
+
+    forbid_stray_files: Option<bool>,
+
+    #[serde(default)]
+    stray_file_allowlist: Vec<String>,
+
+    session_step_timeout: Option<f64>,
+
+    compare: Option<bool>,
+
+    summary_by_directory: Option<bool>,
+
+    duration_regression: Option<DurationRegression>,
+
+    float_tolerance: Option<FloatTolerance>,
+
+    comparison: Option<Comparison>,
+
+    #[serde(default)]
+    quarantine: Vec<String>,
+
+    #[serde(default)]
+    ignored_fields: Vec<String>,
+
+    width: Option<usize>,
+
+    diff_format: Option<DiffFormat>,
+
+    location_format: Option<LocationFormat>,
+
+    report_time: Option<usize>,
+
+    failure_hook: Option<String>,
+
+    save_actual: Option<bool>,
+
+    wasm_runtime: Option<String>,
+
+    runner: Option<String>,
+
+    memory_limit: Option<String>,
+
+    cpu_time_limit: Option<u64>,
+
+    command_wrapper: Option<String>,
+
+    setup: Option<String>,
+
+    strict_discovery: Option<bool>,
+
+    #[serde(default)]
+    ignore: Vec<String>,
+
+    ignore_whitespace: Option<bool>,
+
+    exact: Option<bool>,
+
+    default_exit_status: Option<i32>,
+
+    line_endings: Option<LineEndings>,
+
+    shell: Option<bool>,
+
+    clean_env: Option<bool>,
+
+    env_allowlist: Option<Vec<String>>,
+
+    #[serde(default)]
+    before_each: Vec<String>,
+
+    filter: Option<String>,
+
+    #[serde(default)]
+    after_each: Vec<String>,
+
+    /// Keywords mapped to the other spellings they may be written with
+    #[serde(default)]
+    keyword_aliases: IndexMap<String, Vec<String>>,
+
+    #[serde(default)]
+    environments: IndexMap<String, EnvironmentConfig>,
+
+    /// Named sets of options applied on top of the rest of the file when selected
+    #[serde(default)]
+    profile: IndexMap<String, ConfigFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentConfig {
+    #[serde(default)]
+    wrapper: String,
+
+    #[serde(default)]
+    env: IndexMap<String, String>,
+
+    #[serde(default)]
+    args: String,
+
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+impl EnvironmentConfig {
+    fn into_environment(self, name: String) -> Result<Environment, String> {
+        let split = |field, value: &str| {
+            shlex::split(value).ok_or_else(|| format!("Error parsing {} of environment '{}': {}", field, name, value))
+        };
+
+        let paths = self
+            .paths
+            .iter()
+            .map(|path| glob::Pattern::new(path).map_err(|err| format!("Invalid glob pattern '{}': {}", path, err)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Environment {
+            wrapper: split("wrapper", &self.wrapper)?,
+            args: split("args", &self.args)?,
+            env: self.env.into_iter().collect(),
+            paths,
+            name,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> TestResult<ConfigFile> {
+    let invalid = |reason: String| TestError::InvalidConfigFile(path.to_owned(), reason);
+    let contents = std::fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    toml::from_str(&contents).map_err(|err| invalid(err.to_string()))
+}
+
+impl TestConfig {
+    /// Reads a `goldentests.toml` file at the given path and applies each option
+    /// found within to this config. Any profiles within the file are ignored.
+    pub fn load_config_file<P: Into<PathBuf>>(&mut self, path: P) -> TestResult<()> {
+        let path = path.into();
+        let config_file = read_config_file(&path)?;
+        self.apply_config_file(&path, config_file)
+    }
+
+    /// Reads a `goldentests.toml` file at the given path and applies each option found within
+    /// to this config, followed by the options of the `[profile.<name>]` table with the given name.
+    pub fn load_config_file_with_profile<P: Into<PathBuf>>(&mut self, path: P, profile: &str) -> TestResult<()> {
+        let path = path.into();
+        let mut config_file = read_config_file(&path)?;
+
+        let profile = config_file.profile.shift_remove(profile).ok_or_else(|| {
+            TestError::InvalidConfigFile(path.clone(), format!("No profile named '{}' exists", profile))
+        })?;
+
+        if !profile.profile.is_empty() {
+            let reason = "Profiles cannot contain other profiles".to_owned();
+            return Err(TestError::InvalidConfigFile(path, reason));
+        }
+
+        self.apply_config_file(&path, config_file)?;
+        self.apply_config_file(&path, profile)
+    }
+
+    fn apply_config_file(&mut self, path: &Path, config_file: ConfigFile) -> TestResult<()> {
+        if let Some(binary_path) = config_file.binary_path {
+            self.binary_path = binary_path;
+        }
+
+        if let Some(reference_binary) = config_file.reference_binary {
+            self.reference_binary = Some(reference_binary);
+        }
+
+        if let Some(base_args) = config_file.base_args {
+            let reason = || format!("Error parsing base_args: {}", base_args);
+            let base_args =
+                shlex::split(&base_args).ok_or_else(|| TestError::InvalidConfigFile(path.to_owned(), reason()))?;
+            self.base_args = base_args;
+        }
+
+        for (pattern, replacement) in config_file.scrub {
+            self.add_scrubber(&pattern, &replacement)?;
+        }
+
+        for (keyword, aliases) in config_file.keyword_aliases {
+            for alias in aliases {
+                self.add_keyword_alias(&keyword, &alias)
+                    .map_err(|error| TestError::InvalidConfigFile(path.to_owned(), error.to_string()))?;
+            }
+        }
+
+        if let Some(sidecar_files) = config_file.sidecar_files {
+            self.sidecar_files = sidecar_files;
+        }
+
+        if let Some(state_directory) = config_file.state_directory {
+            self.state_directory = Some(state_directory);
+        }
+
+        if let Some(duration_regression) = config_file.duration_regression {
+            self.duration_regression = Some(duration_regression);
+        }
+
+        if let Some(float_tolerance) = config_file.float_tolerance {
+            self.float_tolerance = Some(float_tolerance);
+        }
+
+        if let Some(comparison) = config_file.comparison {
+            self.comparison = comparison;
+        }
+        self.ignored_fields.extend(config_file.ignored_fields);
+
+        for pattern in config_file.quarantine {
+            let pattern = glob::Pattern::new(&pattern).map_err(|err| {
+                let reason = format!("Invalid glob pattern '{}' in quarantine: {}", pattern, err);
+                TestError::InvalidConfigFile(path.to_owned(), reason)
+            })?;
+            self.quarantine.push(pattern);
+        }
+
+        if let Some(compare) = config_file.compare {
+            self.compare = compare;
+        }
+
+        if let Some(summary_by_directory) = config_file.summary_by_directory {
+            self.summary_by_directory = summary_by_directory;
+        }
+
+        if let Some(repeat) = config_file.repeat {
+            self.repeat = repeat;
+        }
+
+        if let Some(pty) = config_file.pty {
+            self.pty = pty;
+        }
+
+        if let Some(hermetic) = config_file.hermetic {
+            self.hermetic = hermetic;
+        }
+
+        if let Some(forbid_stray_files) = config_file.forbid_stray_files {
+            self.forbid_stray_files = forbid_stray_files;
+        }
+
+        for pattern in config_file.stray_file_allowlist {
+            let pattern = glob::Pattern::new(&pattern).map_err(|err| {
+                let reason = format!("Invalid glob pattern '{}' in stray_file_allowlist: {}", pattern, err);
+                TestError::InvalidConfigFile(path.to_owned(), reason)
+            })?;
+            self.stray_file_allowlist.push(pattern);
+        }
+
+        if let Some(seconds) = config_file.session_step_timeout {
+            self.session_step_timeout = std::time::Duration::try_from_secs_f64(seconds).map_err(|error| {
+                let reason = format!("Error parsing session_step_timeout '{}': {}", seconds, error);
+                TestError::InvalidConfigFile(path.to_owned(), reason)
+            })?;
+        }
+
+        if let Some(max_jobs) = config_file.max_jobs {
+            self.max_jobs = Some(max_jobs);
+        }
+
+        if let Some(order) = config_file.order {
+            self.order = order;
+        }
+
+        if let Some(seed) = config_file.seed {
+            self.seed = Some(seed);
+        }
+
+        if let Some(width) = config_file.width {
+            self.width = Some(width);
+        }
+
+        if let Some(diff_format) = config_file.diff_format {
+            self.diff_format = diff_format;
+        }
+
+        if let Some(location_format) = config_file.location_format {
+            self.location_format = location_format;
+        }
+
+        if let Some(report_time) = config_file.report_time {
+            self.report_time = Some(report_time);
+        }
+
+        if let Some(save_actual) = config_file.save_actual {
+            self.save_actual = save_actual;
+        }
+
+        if let Some(hook) = config_file.failure_hook {
+            let reason = || format!("Error parsing failure_hook: {}", hook);
+            let hook = shlex::split(&hook).ok_or_else(|| TestError::InvalidConfigFile(path.to_owned(), reason()))?;
+            self.failure_hook = Some(hook);
+        }
+
+        if let Some(wrapper) = config_file.command_wrapper {
+            let reason = || format!("Error parsing command_wrapper: {}", wrapper);
+            self.command_wrapper =
+                shlex::split(&wrapper).ok_or_else(|| TestError::InvalidConfigFile(path.to_owned(), reason()))?;
+        }
+
+        if let Some(runner) = config_file.runner {
+            self.command_runner = match runner.strip_prefix("docker:") {
+                Some(image) => Box::new(DockerRunner::new(image, &self.test_path)),
+                None if runner == "local" => Box::new(LocalRunner),
+                None => {
+                    let reason = format!("Unknown runner '{}', expected 'local' or 'docker:<image>'", runner);
+                    return Err(TestError::InvalidConfigFile(path.to_owned(), reason));
+                }
+            };
+        }
+
+        if let Some(memory_limit) = config_file.memory_limit {
+            let memory = parse_memory_size(&memory_limit).ok_or_else(|| {
+                let reason = format!("Error parsing memory_limit '{}', expected e.g. \"512M\"", memory_limit);
+                TestError::InvalidConfigFile(path.to_owned(), reason)
+            })?;
+            self.limits.memory = Some(memory);
+        }
+
+        if let Some(seconds) = config_file.cpu_time_limit {
+            self.limits.cpu_time = Some(std::time::Duration::from_secs(seconds));
+        }
+
+        if let Some(runtime) = config_file.wasm_runtime {
+            let reason = || format!("Error parsing wasm_runtime: {}", runtime);
+            self.wasm_runtime =
+                shlex::split(&runtime).ok_or_else(|| TestError::InvalidConfigFile(path.to_owned(), reason()))?;
+        }
+
+        if let Some(shell) = config_file.shell {
+            self.shell = shell;
+        }
+
+        if let Some(clean_env) = config_file.clean_env {
+            self.clean_env = clean_env;
+        }
+
+        if let Some(env_allowlist) = config_file.env_allowlist {
+            self.env_allowlist = env_allowlist;
+        }
+
+        if let Some(ignore_whitespace) = config_file.ignore_whitespace {
+            self.ignore_whitespace = ignore_whitespace;
+        }
+
+        if let Some(exact) = config_file.exact {
+            self.exact = exact;
+        }
+
+        if let Some(default_exit_status) = config_file.default_exit_status {
+            self.default_exit_status = Some(default_exit_status);
+        }
+
+        if let Some(line_endings) = config_file.line_endings {
+            self.line_endings = line_endings;
+        }
+
+        if let Some(strict_discovery) = config_file.strict_discovery {
+            self.strict_discovery = strict_discovery;
+        }
+
+        for pattern in config_file.ignore {
+            let pattern = glob::Pattern::new(&pattern).map_err(|err| {
+                let reason = format!("Invalid glob pattern '{}' in ignore: {}", pattern, err);
+                TestError::InvalidConfigFile(path.to_owned(), reason)
+            })?;
+            self.ignore_patterns.push(pattern);
+        }
+
+        if let Some(setup) = config_file.setup {
+            self.setup = Some(setup);
+        }
+
+        self.before_each.extend(config_file.before_each);
+
+        if let Some(filter) = config_file.filter {
+            self.filter = Some(filter);
+        }
+        self.after_each.extend(config_file.after_each);
+
+        for (name, environment) in config_file.environments {
+            let environment = environment
+                .into_environment(name)
+                .map_err(|reason| TestError::InvalidConfigFile(path.to_owned(), reason))?;
+            self.environments.push(environment);
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a number of bytes with an optional `K`, `M`, or `G` suffix, e.g. "512M"
+fn parse_memory_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last()? {
+        (i, 'K' | 'k') => (&size[..i], 1 << 10),
+        (i, 'M' | 'm') => (&size[..i], 1 << 20),
+        (i, 'G' | 'g') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
@@ -1,29 +1,145 @@
-use colored::{Color, ColoredString, Colorize};
-use similar::{Change, ChangeTag, DiffOp, TextDiff};
+use crate::colors::{Color, ColoredString, Colorize};
+use crate::terminal::{clamp_width, split_at_width};
+use similar::{ChangeTag, DiffOp, InlineChange, TextDiff};
 use std::fmt::{Display, Error, Formatter};
 
-pub struct DiffPrinter<'a>(pub TextDiff<'a, 'a, 'a, str>);
+pub struct DiffPrinter<'a> {
+    expected: &'a str,
+    actual: &'a str,
 
-fn print_line_number(index: Option<usize>, f: &mut Formatter, colorizer: Colorizer) -> Result<(), Error> {
-    let line_number = index.map_or_else(String::new, |line| (line + 1).to_string());
-    let line_number_string = format!("{:>3}| ", line_number);
+    /// The maximum width of each printed line. Longer lines are wrapped
+    /// onto continuation lines with an empty line number gutter.
+    width: Option<usize>,
+}
 
-    write!(f, "{}", colorizer.color(false, &line_number_string))
+impl<'a> DiffPrinter<'a> {
+    pub fn new(expected: &'a str, actual: &'a str, width: Option<usize>) -> DiffPrinter<'a> {
+        DiffPrinter {
+            expected,
+            actual,
+            width,
+        }
+    }
 }
 
-fn fmt_line(f: &mut Formatter, index: Option<usize>, change: Change<&str>) -> Result<(), Error> {
+/// Prints a diff as a standard `diff -u` patch, with `---` and `+++` headers naming the test file
+pub struct UnifiedDiffPrinter<'a> {
+    expected: &'a str,
+    actual: &'a str,
+    path: &'a str,
+    stream: &'a str,
+}
+
+impl<'a> UnifiedDiffPrinter<'a> {
+    pub fn new(expected: &'a str, actual: &'a str, path: &'a str, stream: &'a str) -> UnifiedDiffPrinter<'a> {
+        UnifiedDiffPrinter {
+            expected,
+            actual,
+            path,
+            stream,
+        }
+    }
+}
+
+/// Prints a diff with the expected and actual lines in two columns, marking each row with
+/// `|` if the line changed, `<` if it was removed, or `>` if it was added
+pub struct SideBySidePrinter<'a> {
+    diff: TextDiff<'a, 'a, 'a, str>,
+
+    /// The maximum width of each row. Lines too long for their column are wrapped
+    /// onto continuation rows. If this is None each column fits its longest line.
+    width: Option<usize>,
+}
+
+impl<'a> SideBySidePrinter<'a> {
+    pub fn new(diff: TextDiff<'a, 'a, 'a, str>, width: Option<usize>) -> SideBySidePrinter<'a> {
+        SideBySidePrinter { diff, width }
+    }
+}
+
+/// Formats the given bytes as a hex dump with 16 bytes per line, in the same layout as `hexdump -C`:
+///
+/// `00000000  48 65 6c 6c 6f 0a                                 |Hello.|`
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let (first, second) = hex.split_at(hex.len().min(8));
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        dump += &format!(
+            "{:08x}  {:<23}  {:<23}  |{}|\n",
+            i * 16,
+            first.join(" "),
+            second.join(" "),
+            ascii
+        );
+    }
+    dump
+}
+
+fn line_number_gutter(index: Option<usize>) -> String {
+    let line_number = index.map_or_else(String::new, |line| (line + 1).to_string());
+    format!("{:>3}| ", line_number)
+}
+
+/// Prints a single line of a diff. Spans of a changed line which differ from the line it
+/// replaced are emphasized with a background color so small changes in long lines stand out.
+fn fmt_line(
+    f: &mut Formatter,
+    index: Option<usize>,
+    change: InlineChange<str>,
+    width: Option<usize>,
+) -> Result<(), Error> {
     let colorizer = match change.tag() {
         ChangeTag::Delete => Colorizer::colored(Color::Red),
         ChangeTag::Equal => Colorizer::normal(),
         ChangeTag::Insert => Colorizer::colored(Color::Green),
     };
-    print_line_number(index, f, colorizer)?;
 
-    writeln!(
-        f,
-        "{}",
-        colorizer.color(false, change.to_string().strip_suffix('\n').unwrap())
-    )
+    let gutter = line_number_gutter(index);
+
+    // Each character of the line along with whether it is emphasized
+    let mut characters: Vec<(bool, char)> = change
+        .iter_strings_lossy()
+        .flat_map(|(emphasized, text)| text.chars().map(move |c| (emphasized, c)).collect::<Vec<_>>())
+        .collect();
+    if characters.last().map(|(_, c)| *c) == Some('\n') {
+        characters.pop();
+    }
+
+    let chunks: Vec<&[(bool, char)]> = match width {
+        Some(width) if !characters.is_empty() => {
+            characters.chunks(clamp_width(width).saturating_sub(gutter.len()).max(1)).collect()
+        }
+        _ => vec![&characters[..]],
+    };
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        // Continuation lines get an empty gutter of the same width
+        let gutter = if i == 0 {
+            gutter.clone()
+        } else {
+            format!("{:>width$}| ", "", width = gutter.len() - 2)
+        };
+        write!(f, "{}", colorizer.color(false, &gutter))?;
+
+        for span in chunk.chunk_by(|a, b| a.0 == b.0) {
+            let text: String = span.iter().map(|(_, c)| c).collect();
+            write!(f, "{}", colorizer.color(span[0].0, &text))?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
 }
 
 #[derive(Copy, Clone)]
@@ -57,11 +173,12 @@ impl Colorizer {
 
 impl Display for DiffPrinter<'_> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        for op in self.0.ops() {
+        let diff = TextDiff::from_lines(self.expected, self.actual);
+        for op in diff.ops() {
             match op {
                 DiffOp::Delete { .. } | DiffOp::Equal { .. } | DiffOp::Insert { .. } => {
-                    for change in self.0.iter_changes(op) {
-                        fmt_line(f, change.new_index(), change)?;
+                    for change in diff.iter_inline_changes(op) {
+                        fmt_line(f, change.new_index(), change, self.width)?;
                     }
                 }
                 DiffOp::Replace {
@@ -69,13 +186,122 @@ impl Display for DiffPrinter<'_> {
                     new_len: len,
                     ..
                 } => {
-                    let mut iter = self.0.iter_changes(op);
+                    let mut iter = diff.iter_inline_changes(op);
                     for (line, change) in (*start..).zip(iter.by_ref().take(*len)) {
-                        fmt_line(f, Some(line), change)?;
+                        fmt_line(f, Some(line), change, self.width)?;
                     }
 
                     for change in iter {
-                        fmt_line(f, None, change)?;
+                        fmt_line(f, None, change, self.width)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for UnifiedDiffPrinter<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        // Anything after a tab in a header is ignored by `patch`, so the stream is named there
+        let expected = format!("--- {}\texpected {}", self.path, self.stream);
+        let actual = format!("+++ {}\tactual {}", self.path, self.stream);
+        writeln!(f, "{}\n{}", expected.as_str().bold(), actual.as_str().bold())?;
+
+        let diff = TextDiff::from_lines(self.expected, self.actual);
+        for line in diff.unified_diff().missing_newline_hint(false).to_string().lines() {
+            let line = if line.starts_with("@@") {
+                line.cyan()
+            } else if line.starts_with('-') {
+                line.red()
+            } else if line.starts_with('+') {
+                line.green()
+            } else {
+                line.normal()
+            };
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single line of one column in a side-by-side diff, with its index in the expected or actual output
+type Cell<'a> = Option<(usize, &'a str)>;
+
+impl SideBySidePrinter<'_> {
+    fn fmt_row(&self, f: &mut Formatter, column_width: usize, left: Cell, right: Cell) -> Result<(), Error> {
+        // Without a width lines are never wrapped, the left column is just padded to its longest line
+        let wrap_width = if self.width.is_some() { column_width } else { usize::MAX };
+        let (marker, left_colorizer, right_colorizer) = match (left, right) {
+            (Some(_), None) => ('<', Colorizer::colored(Color::Red), Colorizer::normal()),
+            (None, Some(_)) => ('>', Colorizer::normal(), Colorizer::colored(Color::Green)),
+            (Some((_, old)), Some((_, new))) if old != new => {
+                ('|', Colorizer::colored(Color::Red), Colorizer::colored(Color::Green))
+            }
+            _ => (' ', Colorizer::normal(), Colorizer::normal()),
+        };
+
+        let chunks = |cell: Cell| match cell {
+            Some((_, line)) => split_at_width(line.trim_end_matches('\n'), wrap_width),
+            None => vec![],
+        };
+        let (left_chunks, right_chunks) = (chunks(left), chunks(right));
+
+        for i in 0..left_chunks.len().max(right_chunks.len()).max(1) {
+            // Only the first row of a wrapped line is numbered and marked
+            let (left_index, right_index, marker) = if i == 0 {
+                (left.map(|(index, _)| index), right.map(|(index, _)| index), marker)
+            } else {
+                (None, None, ' ')
+            };
+            let left_chunk = left_chunks.get(i).map_or("", String::as_str);
+            let right_chunk = right_chunks.get(i).map_or("", String::as_str);
+            let padding = column_width.saturating_sub(left_chunk.chars().count());
+
+            write!(f, "{}", left_colorizer.color(false, &line_number_gutter(left_index)))?;
+            write!(
+                f,
+                "{}{:padding$}",
+                left_colorizer.color(false, left_chunk),
+                "",
+                padding = padding
+            )?;
+            write!(f, " {} ", marker)?;
+            write!(f, "{}", right_colorizer.color(false, &line_number_gutter(right_index)))?;
+            writeln!(f, "{}", right_colorizer.color(false, right_chunk))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SideBySidePrinter<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let (old, new) = (self.diff.old_slices(), self.diff.new_slices());
+
+        // Each row is two gutters of 5 characters and the 3 character marker between the columns
+        let column_width = match self.width {
+            Some(width) => (clamp_width(width).saturating_sub(13) / 2).max(1),
+            None => old.iter().map(|line| line.trim_end_matches('\n').chars().count()).max().unwrap_or(0).max(1),
+        };
+
+        for op in self.diff.ops() {
+            let (old_range, new_range) = (op.old_range(), op.new_range());
+            match op {
+                DiffOp::Equal { .. } => {
+                    for (old_index, new_index) in old_range.zip(new_range) {
+                        let (left, right) = (Some((old_index, old[old_index])), Some((new_index, new[new_index])));
+                        self.fmt_row(f, column_width, left, right)?;
+                    }
+                }
+                DiffOp::Delete { .. } | DiffOp::Insert { .. } | DiffOp::Replace { .. } => {
+                    // Removed and added lines are paired up row by row so changed lines are side by side
+                    let rows = old_range.len().max(new_range.len());
+                    for row in 0..rows {
+                        let left = (old_range.start + row < old_range.end)
+                            .then(|| (old_range.start + row, old[old_range.start + row]));
+                        let right = (new_range.start + row < new_range.end)
+                            .then(|| (new_range.start + row, new[new_range.start + row]));
+                        self.fmt_row(f, column_width, left, right)?;
                     }
                 }
             }
@@ -1,80 +1,483 @@
 use std::error::Error;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use colored::Colorize;
+use crate::colors::Colorize;
+use crate::config::{DiffFormat, LocationFormat};
+use crate::diff_printer::{DiffPrinter, SideBySidePrinter, UnifiedDiffPrinter};
+use crate::terminal::{clamp_width, shorten_middle};
+use similar::TextDiff;
 
 pub type TestResult<T> = Result<T, TestError>;
 
+/// An error returned from running or generating tests
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum TestError {
     MissingTests(PathBuf),
     ExpectedDirectory(PathBuf),
-    TestErrors,
+    InvalidConfigFile(PathBuf, /*reason*/ String),
+    InvalidManifest(PathBuf, /*reason*/ String),
+    InvalidRegex(/*pattern*/ String, regex::Error),
+    InvalidKeywordAlias(/*alias*/ String, /*reason*/ String),
+    SetupFailed(/*command*/ String, /*reason*/ String),
+    IoError(PathBuf, std::io::Error),
+    /// The binary tests are ran with doesn't exist or isn't executable
+    InvalidBinary(PathBuf, /*reason*/ String),
+    /// One or more tests failed. Each failure has already been printed, and is also
+    /// given here so that library consumers can inspect them.
+    TestErrors(Vec<InnerTestError>),
 }
 
 impl fmt::Display for TestError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use TestError::*;
         match self {
-            TestErrors => f.write_str("The expected test output differs"),
+            TestErrors(_) => f.write_str("The expected test output differs"),
             MissingTests(path) => write!(f, "Failed to locate test files {}", path.display()),
             ExpectedDirectory(path) => {
                 let msg = "The path given for test files should be a directory ";
                 write!(f, "{}{}", msg, path.display())
             }
+            InvalidConfigFile(path, reason) => {
+                write!(f, "Error reading config file {}: {}", path.display(), reason)
+            }
+            InvalidManifest(path, reason) => {
+                write!(f, "Error reading test manifest {}: {}", path.display(), reason)
+            }
+            InvalidRegex(pattern, error) => write!(f, "Invalid regex '{}': {}", pattern, error),
+            InvalidKeywordAlias(alias, reason) => write!(f, "Invalid keyword alias '{}': {}", alias, reason),
+            SetupFailed(command, reason) => write!(f, "Setup command `{}` {}", command, reason),
+            IoError(path, error) => write!(f, "{}: {}", path.display(), error),
+            InvalidBinary(path, reason) => write!(f, "Cannot run binary {}: {}", path.display(), reason),
         }
     }
 }
 
-impl Error for TestError {}
+impl Error for TestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TestError::InvalidRegex(_, error) => Some(error),
+            TestError::IoError(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
-// Inner test errors shouldn't be visible to the end-user,
-// they'll all be reported internally after running the tests
-pub(crate) enum InnerTestError {
-    TestUpdated { path: PathBuf, errors: Vec<String> },
-    TestFailed { path: PathBuf, errors: Vec<String> },
+/// The outcome of a single test which didn't simply pass. These are reported after running
+/// the tests and most, but not all, count as failures. See `InnerTestError::is_failure`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InnerTestError {
+    /// The test failed and its expected output was overwritten with the actual output
+    TestUpdated {
+        path: PathBuf,
+        errors: Vec<Mismatch>,
+    },
+    /// The actual result of the test differs from what was expected
+    TestFailed {
+        path: PathBuf,
+        errors: Vec<Mismatch>,
+    },
+    /// The test passed but would be rewritten differently by overwrite_tests
+    NeedsCanonicalization(PathBuf),
+    /// The test passed, but only because each difference was allowed by an "allow diff:" directive
+    AllowedDifferences {
+        path: PathBuf,
+        errors: Vec<Mismatch>,
+    },
     IoError(PathBuf, std::io::Error),
-    CommandError(PathBuf, std::process::Command, std::io::Error),
-    ErrorParsingExitStatus(PathBuf, /*status*/ String, std::num::ParseIntError),
+    /// The command of the test could not be started
+    CommandError(PathBuf, /*command*/ String, std::io::Error),
+    /// The command of the test was stopped for exceeding one of `TestConfig::limits`
+    ResourceLimitExceeded(PathBuf, /*limit*/ String),
+    /// A `before:` or `after:` command failed
+    ShellCommandFailed(PathBuf, /*command*/ String, /*reason*/ String),
+    ErrorParsingExitStatus(
+        PathBuf,
+        /*line number*/ usize,
+        /*status*/ String,
+        std::num::ParseIntError,
+    ),
     ErrorParsingArgs(PathBuf, /*args*/ String),
+    ErrorParsingQualifier(PathBuf, /*line number*/ usize, /*qualifier*/ String),
+    ErrorParsingDirective(
+        PathBuf,
+        /*line number*/ usize,
+        /*directive*/ String,
+        /*reason*/ String,
+    ),
+    /// The test contains no keywords while `strict_discovery` is set
+    NoDirectives(PathBuf),
+    /// Environments are configured, but the paths of none of them match the test, so it is never ran
+    NoEnvironments(PathBuf),
+    /// The test took longer than `TestConfig::duration_regression` allows. This is
+    /// only a failure if `DurationRegression::fail` is set, and a warning otherwise.
+    DurationRegressed {
+        path: PathBuf,
+        baseline: std::time::Duration,
+        duration: std::time::Duration,
+        fail: bool,
+    },
+    /// A suspicious line found by `lint::lint_test`
+    Lint(PathBuf, /*line number*/ usize, /*message*/ String),
+    /// A warning for a line which looks like a mistyped keyword
+    UnknownDirective(PathBuf, /*line*/ String, /*suggested keyword*/ String),
+    /// A test matching `TestConfig::quarantine` failed with the given error, which is
+    /// reported but doesn't fail the run
+    Quarantined(Box<InnerTestError>),
+    /// The test wasn't ran because it requires a program or environment variable which is missing
+    Skipped(PathBuf, /*reason*/ String),
 }
 
-impl fmt::Display for InnerTestError {
+/// A single way in which the actual result of a test differs from what was expected
+#[derive(Debug)]
+pub struct Mismatch {
+    /// A description of the mismatch, e.g. "Actual stdout differs from expected stdout (97.5% similar):"
+    pub message: String,
+
+    /// The expected and actual text shown as a diff beneath the message, if any
+    pub diff: Option<Diff>,
+
+    /// The line of the test giving the expectation which wasn't met, if it was given within the test
+    pub line: Option<usize>,
+}
+
+/// The expected and actual text of a stream which differ
+#[derive(Debug)]
+pub struct Diff {
+    pub expected: String,
+    pub actual: String,
+
+    /// The test file and the name of the stream which differs, used as the headers of a unified diff
+    pub path: PathBuf,
+    pub stream: String,
+
+    pub format: DiffFormat,
+
+    /// The maximum width the diff is printed within
+    pub width: Option<usize>,
+}
+
+impl Mismatch {
+    pub(crate) fn with_diff(message: String, diff: Diff) -> Mismatch {
+        Mismatch {
+            message,
+            diff: Some(diff),
+            line: None,
+        }
+    }
+
+    /// Points this mismatch at the line of the test giving the expectation which wasn't met
+    pub(crate) fn at_line(self, line: Option<usize>) -> Mismatch {
+        Mismatch { line, ..self }
+    }
+}
+
+impl From<String> for Mismatch {
+    fn from(message: String) -> Mismatch {
+        Mismatch {
+            message,
+            diff: None,
+            line: None,
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = |path: &PathBuf| path.to_string_lossy().bright_yellow();
+        match &self.diff {
+            Some(diff) => match diff.format {
+                DiffFormat::Numbered => {
+                    let printer = DiffPrinter::new(&diff.expected, &diff.actual, diff.width);
+                    write!(f, "{}\n{}", self.message, printer)
+                }
+                DiffFormat::Unified => {
+                    let path = diff.path.to_string_lossy();
+                    let printer = UnifiedDiffPrinter::new(&diff.expected, &diff.actual, &path, &diff.stream);
+                    write!(f, "{}\n{}", self.message, printer)
+                }
+                DiffFormat::SideBySide => {
+                    let differences = TextDiff::from_lines(&diff.expected, &diff.actual);
+                    write!(
+                        f,
+                        "{}\n{}",
+                        self.message,
+                        SideBySidePrinter::new(differences, diff.width)
+                    )
+                }
+            },
+            None => f.write_str(&self.message),
+        }
+    }
+}
 
+impl InnerTestError {
+    /// The path of the test this error is from
+    pub fn path(&self) -> &Path {
         match self {
-            InnerTestError::TestFailed { path, errors } => {
-                for (i, error) in errors.iter().enumerate() {
-                    write!(f, "{}: {}", s(path), error)?;
-                    if i + 1 != errors.len() {
-                        writeln!(f)?;
+            InnerTestError::TestUpdated { path, .. }
+            | InnerTestError::TestFailed { path, .. }
+            | InnerTestError::AllowedDifferences { path, .. }
+            | InnerTestError::DurationRegressed { path, .. }
+            | InnerTestError::NeedsCanonicalization(path)
+            | InnerTestError::IoError(path, _)
+            | InnerTestError::CommandError(path, _, _)
+            | InnerTestError::ResourceLimitExceeded(path, _)
+            | InnerTestError::ShellCommandFailed(path, _, _)
+            | InnerTestError::ErrorParsingExitStatus(path, _, _, _)
+            | InnerTestError::ErrorParsingArgs(path, _)
+            | InnerTestError::ErrorParsingQualifier(path, _, _)
+            | InnerTestError::ErrorParsingDirective(path, _, _, _)
+            | InnerTestError::NoDirectives(path)
+            | InnerTestError::NoEnvironments(path)
+            | InnerTestError::Lint(path, _, _)
+            | InnerTestError::UnknownDirective(path, _, _)
+            | InnerTestError::Skipped(path, _) => path,
+            InnerTestError::Quarantined(error) => error.path(),
+        }
+    }
+
+    /// Each way the actual result of the test differs from what was expected, if this error is from comparing them
+    pub fn mismatches(&self) -> &[Mismatch] {
+        match self {
+            InnerTestError::TestUpdated { errors, .. }
+            | InnerTestError::TestFailed { errors, .. }
+            | InnerTestError::AllowedDifferences { errors, .. } => errors,
+            InnerTestError::Quarantined(error) => error.mismatches(),
+            _ => &[],
+        }
+    }
+
+    /// True if this error should cause the test run to fail. Updated tests and
+    /// notices are reported but do not count as failures.
+    pub fn is_failure(&self) -> bool {
+        !matches!(
+            self,
+            InnerTestError::TestUpdated { .. }
+                | InnerTestError::NeedsCanonicalization(_)
+                | InnerTestError::AllowedDifferences { .. }
+                | InnerTestError::UnknownDirective(..)
+                | InnerTestError::DurationRegressed { fail: false, .. }
+                | InnerTestError::Quarantined(_)
+                | InnerTestError::Skipped(..)
+        )
+    }
+}
+
+impl InnerTestError {
+    /// Displays this error with each path shortened to fit comfortably within the given width,
+    /// and each location written in the given format
+    pub(crate) fn formatted(&self, width: Option<usize>, locations: LocationFormat) -> Formatted<'_> {
+        Formatted(self, width, locations, None)
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter,
+        width: Option<usize>,
+        locations: LocationFormat,
+        name: Option<&str>,
+    ) -> fmt::Result {
+        let s = |path: &PathBuf| {
+            let path = path.to_string_lossy();
+            match width {
+                // Leave room for the rest of the message after the path
+                Some(width) => shorten_middle(&path, clamp_width(width) / 2).bright_yellow(),
+                None => path.bright_yellow(),
+            }
+        };
+
+        // Writes the path of the test and the line the message is about, if known, followed by its kind
+        let header = |f: &mut fmt::Formatter, path: &PathBuf, line: Option<usize>, kind: Kind| -> fmt::Result {
+            match locations {
+                LocationFormat::Default => {
+                    if let Some(name) = name {
+                        write!(f, "{} — ", name.bold())?;
                     }
+                    write!(f, "{}", s(path))?;
+                    if let Some(line) = line {
+                        write!(f, ":{}", line)?;
+                    }
+                    f.write_str(kind.label())
                 }
-                Ok(())
-            }
-            InnerTestError::TestUpdated { path, errors } => {
-                for (i, error) in errors.iter().enumerate() {
-                    write!(f, "{} - UPDATED:  {}", s(path), error)?;
-                    if i + 1 != errors.len() {
-                        writeln!(f)?;
+                // Editors expect each message to start with its location, so the name follows it instead
+                LocationFormat::Compiler => {
+                    write!(f, "{}", s(path))?;
+                    if let Some(line) = line {
+                        write!(f, ":{}:1", line)?;
+                    }
+                    write!(f, ": {}: ", kind.severity())?;
+                    match name {
+                        Some(name) => write!(f, "{} — ", name),
+                        None => Ok(()),
                     }
                 }
-                Ok(())
+            }
+        };
+
+        let mismatches = |f: &mut fmt::Formatter, path: &PathBuf, errors: &[Mismatch], kind: Kind| -> fmt::Result {
+            for (i, error) in errors.iter().enumerate() {
+                header(f, path, error.line, kind)?;
+                write!(f, "{}", error)?;
+                if i + 1 != errors.len() {
+                    writeln!(f)?;
+                }
+            }
+            Ok(())
+        };
+
+        match self {
+            InnerTestError::TestFailed { path, errors } => mismatches(f, path, errors, Kind::Failure),
+            InnerTestError::TestUpdated { path, errors } => mismatches(f, path, errors, Kind::Updated),
+            InnerTestError::AllowedDifferences { path, errors } => mismatches(f, path, errors, Kind::Allowed),
+            InnerTestError::NeedsCanonicalization(path) => {
+                header(f, path, None, Kind::Notice)?;
+                write!(
+                    f,
+                    "passes but needs canonicalization, its expected output would be rewritten by --overwrite"
+                )
             }
             InnerTestError::IoError(path, error) => {
-                writeln!(f, "{}: {}", s(path), error)
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "{}", error)
             }
             InnerTestError::CommandError(path, command, error) => {
-                writeln!(f, "{}: Error running `{:?}`: {}", s(path), command, error)
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "Error running `{}`: {}", command, error)
+            }
+            InnerTestError::ResourceLimitExceeded(path, limit) => {
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "Exceeded the {}", limit)
             }
-            InnerTestError::ErrorParsingExitStatus(path, status, error) => {
-                writeln!(f, "{}: Error parsing exit status '{}': {}", s(path), status, error)
+            InnerTestError::ShellCommandFailed(path, command, reason) => {
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "Command `{}` {}", command, reason)
+            }
+            InnerTestError::ErrorParsingExitStatus(path, line, status, error) => {
+                header(f, path, Some(*line), Kind::Failure)?;
+                writeln!(f, "Error parsing exit status '{}': {}", status, error)
             }
             InnerTestError::ErrorParsingArgs(path, args) => {
-                writeln!(f, "{}: Error parsing test args: {}", s(path), args)
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "Error parsing test args: {}", args)
+            }
+            InnerTestError::ErrorParsingQualifier(path, line, qualifier) => {
+                let expected = "expected '(first N lines)', '(last N lines)', '(escaped)', '(base64)', or '(hex)'";
+                header(f, path, Some(*line), Kind::Failure)?;
+                writeln!(f, "Unknown qualifier '({})', {}", qualifier, expected)
+            }
+            InnerTestError::ErrorParsingDirective(path, line, directive, reason) => {
+                header(f, path, Some(*line), Kind::Failure)?;
+                writeln!(f, "Error parsing '{}': {}", directive.trim(), reason)
             }
+            InnerTestError::UnknownDirective(path, line, keyword) => {
+                header(f, path, None, Kind::Warning)?;
+                writeln!(f, "Unknown directive '{}', did you mean '{}'?", line.trim(), keyword)
+            }
+            InnerTestError::DurationRegressed {
+                path,
+                baseline,
+                duration,
+                fail,
+            } => {
+                header(f, path, None, if *fail { Kind::Failure } else { Kind::Warning })?;
+                let slower = (duration.as_secs_f64() / baseline.as_secs_f64() - 1.0) * 100.0;
+                writeln!(
+                    f,
+                    "Took {:.2}s, {:.0}% slower than its baseline of {:.2}s",
+                    duration.as_secs_f64(),
+                    slower,
+                    baseline.as_secs_f64()
+                )
+            }
+            InnerTestError::Lint(path, line, message) => {
+                header(f, path, Some(*line), Kind::Failure)?;
+                writeln!(f, "{}", message)
+            }
+            InnerTestError::Quarantined(error) => {
+                write!(f, "{} ", "QUARANTINED".bright_yellow())?;
+                error.fmt_with(f, width, locations, name)
+            }
+            InnerTestError::Skipped(path, reason) => {
+                header(f, path, None, Kind::Skipped)?;
+                writeln!(f, "requires {}", reason)
+            }
+            InnerTestError::NoDirectives(path) => {
+                let hint = "check the test line prefix, or move the file out of the test directory";
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "No test directives found, {}", hint)
+            }
+            InnerTestError::NoEnvironments(path) => {
+                let hint = "check the paths of each environment, or list its environments with 'environments:'";
+                header(f, path, None, Kind::Failure)?;
+                writeln!(f, "No environment applies to this test, {}", hint)
+            }
+        }
+    }
+}
+
+/// The kind of message written after the location of a test, see `InnerTestError::fmt_with`
+#[derive(Copy, Clone)]
+enum Kind {
+    Failure,
+    Updated,
+    Allowed,
+    Warning,
+    Notice,
+    Skipped,
+}
+
+impl Kind {
+    /// The text between the location and the message with `LocationFormat::Default`
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Failure => ": ",
+            Kind::Updated => " - UPDATED:  ",
+            Kind::Allowed => " - ALLOWED: ",
+            Kind::Warning => " - WARNING: ",
+            Kind::Notice => " - NOTICE: ",
+            Kind::Skipped => " - SKIPPED: ",
+        }
+    }
+
+    /// The severity of a compiler diagnostic for this kind of message, used with `LocationFormat::Compiler`
+    fn severity(self) -> &'static str {
+        match self {
+            Kind::Failure => "error",
+            Kind::Allowed | Kind::Warning => "warning",
+            Kind::Updated | Kind::Notice | Kind::Skipped => "note",
+        }
+    }
+}
+
+pub(crate) struct Formatted<'a>(&'a InnerTestError, Option<usize>, LocationFormat, Option<&'a str>);
+
+impl<'a> Formatted<'a> {
+    /// Shows the given name of the test alongside its path, see `TestConfig::test_name_prefix`
+    pub(crate) fn named(self, name: Option<&'a str>) -> Self {
+        Formatted(self.0, self.1, self.2, name)
+    }
+}
+
+impl fmt::Display for Formatted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_with(f, self.1, self.2, self.3)
+    }
+}
+
+impl fmt::Display for InnerTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(f, None, LocationFormat::Default, None)
+    }
+}
+
+impl Error for InnerTestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InnerTestError::IoError(_, error) | InnerTestError::CommandError(_, _, error) => Some(error),
+            InnerTestError::ErrorParsingExitStatus(_, _, _, error) => Some(error),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,199 @@
+//! Translating tests written for other tools into goldentests tests, used by `goldentests import-lit`.
+use crate::colors::Colorize;
+use crate::config::TestConfig;
+use crate::error::{InnerTestError, TestError, TestResult};
+use crate::shell;
+
+use std::fmt::Write;
+
+/// The lit keywords recognized at the start of a line, other than each `CHECK` keyword
+const LIT_KEYWORDS: &[&str] = &["RUN", "REQUIRES", "UNSUPPORTED", "XFAIL", "ALLOW_RETRIES", "END"];
+
+/// Translates a simple [lit](https://llvm.org/docs/CommandGuide/lit.html) test checked by FileCheck into a
+/// test using the keywords of the given config, returning the translated test or the reason it can't be.
+///
+/// The test must have a single `RUN:` line which runs a program on the test file (`%s`), optionally piping
+/// its stdout into `FileCheck %s`. The program itself is replaced by the binary goldentests runs, keeping
+/// only its arguments. `CHECK:`, `CHECK-LABEL:`, `CHECK-NEXT:`, and `CHECK-NOT:` lines become the equivalent
+/// `check:`, `check-next:`, and `check-not:` directives. Any other lit feature, such as FileCheck patterns or
+/// other substitutions, can't be translated. Lines which aren't lit directives are kept as they are.
+///
+/// ```rust
+/// # use goldentests::{TestConfig, TestResult};
+/// # fn main() -> TestResult<()> {
+/// let config = TestConfig::new("python", "examples", "# ")?;
+/// let lit = "print('hello')\n# RUN: %python %s | FileCheck %s\n# CHECK: hello\n";
+/// let test = goldentests::import::import_lit_test(lit, &config).unwrap();
+/// assert_eq!(test, "print('hello')\n# expected exit status: 0\n# check: hello\n");
+/// # Ok(())
+/// # }
+/// ```
+pub fn import_lit_test(contents: &str, config: &TestConfig) -> Result<String, String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let keywords: Vec<_> = lines.iter().map(|line| lit_keyword(line, &config.test_line_prefix)).collect();
+
+    let run_lines: Vec<&str> =
+        keywords.iter().flatten().filter(|(keyword, _)| *keyword == "RUN").map(|(_, text)| *text).collect();
+    let args = match run_lines[..] {
+        [run_line] => translate_run_line(run_line)?,
+        [] => return Err("has no RUN: line".to_owned()),
+        _ => {
+            return Err(format!(
+                "has {} RUN: lines, only a single RUN: line can be imported",
+                run_lines.len()
+            ))
+        }
+    };
+
+    let mut test = String::new();
+    for (index, (line, keyword)) in lines.iter().zip(&keywords).enumerate() {
+        let check_keyword = match keyword {
+            None => {
+                test.push_str(line);
+                test.push('\n');
+                continue;
+            }
+            Some(("RUN", _)) => {
+                if !args.is_empty() {
+                    let _ = writeln!(test, "{} {}", config.test_args_prefix, args);
+                }
+                let _ = writeln!(test, "{} 0", config.test_exit_status_prefix);
+                continue;
+            }
+            Some(("CHECK", _)) | Some(("CHECK-LABEL", _)) => &config.test_check_prefix,
+            Some(("CHECK-NEXT", _)) => &config.test_check_next_prefix,
+            Some(("CHECK-NOT", _)) => &config.test_check_not_prefix,
+            Some((keyword, _)) => return Err(format!("line {} uses {}:, which has no equivalent", index + 1, keyword)),
+        };
+
+        let text = keyword.map_or("", |(_, text)| text);
+        if text.contains("{{") || text.contains("[[") {
+            return Err(format!(
+                "line {} uses a FileCheck pattern, which has no equivalent",
+                index + 1
+            ));
+        }
+        let _ = writeln!(test, "{} {}", check_keyword, text);
+    }
+    Ok(test)
+}
+
+/// The lit keyword at the start of the given line, if any, along with the rest of the line after its ':'
+fn lit_keyword<'a>(line: &'a str, line_prefix: &str) -> Option<(&'a str, &'a str)> {
+    let (keyword, text) = line.strip_prefix(line_prefix)?.split_once(':')?;
+    let is_check = keyword == "CHECK" || keyword.starts_with("CHECK-");
+    (is_check || LIT_KEYWORDS.contains(&keyword)).then_some((keyword, text.trim()))
+}
+
+/// Translates the command of a `RUN:` line into the arguments of an `args:` directive
+fn translate_run_line(command: &str) -> Result<String, String> {
+    if command.ends_with('\\') {
+        return Err("continues its RUN: line onto the next line, which can't be imported".to_owned());
+    }
+
+    let mut stages = command.split('|');
+    let program = stages.next().unwrap_or_default();
+    match (stages.next(), stages.next()) {
+        (None, _) => (),
+        (Some(filecheck), None) if shlex::split(filecheck).is_some_and(|words| words == ["FileCheck", "%s"]) => (),
+        (Some(stage), None) => {
+            return Err(format!(
+                "pipes into `{}`, only `FileCheck %s` is supported",
+                stage.trim()
+            ))
+        }
+        (Some(_), Some(_)) => return Err("pipes through more than one command".to_owned()),
+    }
+
+    let words = shlex::split(program).ok_or_else(|| format!("has a RUN: line which can't be parsed: {}", program))?;
+    let (tool, args) = words.split_first().ok_or("has an empty RUN: line")?;
+    if tool == "not" {
+        return Err("expects the program to fail, which needs an exact `expected exit status:`".to_owned());
+    } else if !args.iter().any(|arg| arg == "%s") {
+        return Err("doesn't pass the test file (%s) to the program".to_owned());
+    }
+
+    let mut translated = Vec::new();
+    for (index, arg) in args.iter().enumerate() {
+        if arg == "%s" {
+            // The test file is passed last by default, so it only needs to be placed elsewhere
+            if index + 1 != args.len() {
+                translated.push("{file}".to_owned());
+            }
+        } else if arg.contains('%') {
+            return Err(format!("uses the lit substitution `{}`, which has no equivalent", arg));
+        } else if arg.contains(['<', '>']) {
+            return Err(format!("redirects the program with `{}`, which has no equivalent", arg));
+        } else {
+            translated.push(shell::quote(arg));
+        }
+    }
+    Ok(translated.join(" "))
+}
+
+impl TestConfig {
+    /// Translates each lit test within `test_path` into a goldentests test in place using
+    /// `import_lit_test`. Files without a `RUN:` line are left untouched, as are lit tests which
+    /// can't be translated, which are printed along with the reason why.
+    pub fn import_lit_tests(&self) -> TestResult<()> {
+        let (mut tests, path_errors) = self.find_tests();
+        tests.sort();
+
+        let width = self.output_width();
+        for error in &path_errors {
+            eprintln!("{}", error.formatted(width, self.location_format));
+        }
+
+        let (mut imported, mut skipped) = (0, 0);
+        let mut failures = path_errors;
+        let run_prefix = format!("{}RUN:", self.test_line_prefix);
+
+        for test in tests {
+            let contents = match std::fs::read_to_string(&test) {
+                Ok(contents) => contents,
+                // Binary files can't be lit tests
+                Err(error) if error.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(error) => {
+                    let error = InnerTestError::IoError(test, error);
+                    eprintln!("{}", error.formatted(width, self.location_format));
+                    failures.push(error);
+                    continue;
+                }
+            };
+
+            if !contents.lines().any(|line| line.starts_with(&run_prefix)) {
+                continue;
+            }
+
+            match import_lit_test(&contents, self) {
+                Ok(translated) => {
+                    if let Err(error) = std::fs::write(&test, translated) {
+                        let error = InnerTestError::IoError(test, error);
+                        eprintln!("{}", error.formatted(width, self.location_format));
+                        failures.push(error);
+                        continue;
+                    }
+                    println!("imported {}", test.display());
+                    imported += 1;
+                }
+                Err(reason) => {
+                    println!("{} {}: {}", "skipped".bright_yellow(), test.display(), reason);
+                    skipped += 1;
+                }
+            }
+        }
+
+        println!(
+            "imported {} lit tests with {} skipped and {}\n",
+            imported,
+            skipped,
+            format!("{} failing", failures.len()).red(),
+        );
+
+        if !failures.is_empty() {
+            Err(TestError::TestErrors(failures))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Support for `TestConfig::incremental`, which skips each test whose inputs haven't changed
+//! since the last time it passed.
+use crate::config::TestConfig;
+use crate::parser::{parse_test, sidecar_path};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Maps the path of each test which passed on its last run to the fingerprint of its inputs at the time
+type Fingerprints = BTreeMap<PathBuf, String>;
+
+const PASSING_FILE: &str = "passing.json";
+
+pub(crate) struct Incremental {
+    /// A hash of every input shared between tests: the binary being tested and the config
+    shared: u64,
+    passing: Fingerprints,
+}
+
+impl Incremental {
+    pub(crate) fn new(config: &TestConfig) -> Incremental {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        find_binary(&config.binary_path).and_then(|path| std::fs::read(path).ok()).hash(&mut hasher);
+        let reference_binary = config.reference_binary.as_ref().and_then(|binary| find_binary(binary));
+        reference_binary.and_then(|path| std::fs::read(path).ok()).hash(&mut hasher);
+        hash_config(config, &mut hasher);
+
+        Incremental {
+            shared: hasher.finish(),
+            passing: config.read_state(PASSING_FILE),
+        }
+    }
+
+    /// A fingerprint of the test file, its sidecar files, every file it refers to, and the shared
+    /// inputs. The referenced files are those given by `include:`, `args file:`, `stdin file:`, and
+    /// `fixtures:`, along with the golden trees of `expected directory`. The text of an `expected file`
+    /// is written within the test or an included file, so is already covered.
+    fn fingerprint(&self, config: &TestConfig, test: &Path) -> Option<String> {
+        let mut hasher = DefaultHasher::new();
+        self.shared.hash(&mut hasher);
+        std::fs::read(test).ok()?.hash(&mut hasher);
+        for stream in ["stdout", "stderr"] {
+            std::fs::read(sidecar_path(test, stream)).ok().hash(&mut hasher);
+        }
+
+        // A test which can't be parsed fails before reading any other file, so only its own contents matter
+        if let Ok(parsed) = parse_test(test, config) {
+            let test_directory = test.parent().unwrap_or_else(|| Path::new(""));
+            let fixtures = parsed.fixtures.iter().map(|fixture| test_directory.join(fixture));
+            let golden = parsed.expected_directories.iter().map(|directory| directory.golden.clone());
+            let files = parsed.includes.iter().chain(&parsed.args_file).chain(&parsed.stdin_file).cloned();
+            for path in files.chain(fixtures).chain(golden) {
+                hash_path(&path, &mut hasher);
+            }
+        }
+        Some(format!("{:016x}", hasher.finish()))
+    }
+
+    /// True if the given test passed on its last run and none of its inputs have changed since
+    pub(crate) fn is_unchanged(&self, config: &TestConfig, test: &Path) -> bool {
+        self.passing
+            .get(test)
+            .is_some_and(|fingerprint| self.fingerprint(config, test).as_ref() == Some(fingerprint))
+    }
+
+    /// Records whether the given test passed, so that it is only skipped next time if it did
+    pub(crate) fn record(&mut self, config: &TestConfig, test: &Path, passed: bool) {
+        match self.fingerprint(config, test) {
+            Some(fingerprint) if passed => self.passing.insert(test.to_owned(), fingerprint),
+            _ => self.passing.remove(test),
+        };
+    }
+
+    pub(crate) fn save(&self, config: &TestConfig) {
+        config.write_state(PASSING_FILE, &self.passing);
+    }
+}
+
+/// Finds the file the given binary refers to, searching `PATH` if it is only a name like `python`
+fn find_binary(binary: &Path) -> Option<PathBuf> {
+    if binary.is_file() || binary.components().count() > 1 {
+        return Some(binary.to_owned());
+    }
+
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths).map(|directory| directory.join(binary)).find(|path| path.is_file())
+}
+
+/// Hashes the path and contents of the given file, or of each file within the given directory in
+/// a stable order. A missing file is hashed as such, so that creating it changes the hash.
+fn hash_path(path: &Path, hasher: &mut impl Hasher) {
+    path.hash(hasher);
+    if path.is_dir() {
+        let mut entries: Vec<_> = match std::fs::read_dir(path) {
+            Ok(entries) => entries.filter_map(|entry| Some(entry.ok()?.path())).collect(),
+            Err(_) => return,
+        };
+        entries.sort();
+        for entry in entries {
+            hash_path(&entry, hasher);
+        }
+    } else {
+        std::fs::read(path).ok().hash(hasher);
+    }
+}
+
+/// Hashes each option which may affect the result of a test
+fn hash_config(config: &TestConfig, hasher: &mut impl Hasher) {
+    config.binary_path.hash(hasher);
+    config.reference_binary.hash(hasher);
+    config.test_line_prefix.hash(hasher);
+    config.keywords().hash(hasher);
+    config.keyword_aliases.hash(hasher);
+    config.ignore_line_marker.hash(hasher);
+    config.base_args.hash(hasher);
+    config.shell.hash(hasher);
+    config.clean_env.hash(hasher);
+    config.tmpdir_env_var.hash(hasher);
+    config.env_allowlist.hash(hasher);
+    config.exact.hash(hasher);
+    config.default_exit_status.hash(hasher);
+    config.line_endings.hash(hasher);
+    config.ignore_whitespace.hash(hasher);
+    config.comparison.hash(hasher);
+    config.ignored_fields.hash(hasher);
+    config
+        .float_tolerance
+        .map(|tolerance| (tolerance.absolute.to_bits(), tolerance.relative.to_bits()))
+        .hash(hasher);
+    config.sidecar_files.hash(hasher);
+    config.before_each.hash(hasher);
+    config.filter.hash(hasher);
+    config.after_each.hash(hasher);
+    config.command_wrapper.hash(hasher);
+    config.repeat.hash(hasher);
+    config.pty.hash(hasher);
+    config.hermetic.hash(hasher);
+    config.forbid_stray_files.hash(hasher);
+    for pattern in &config.stray_file_allowlist {
+        pattern.as_str().hash(hasher);
+    }
+    config.session_step_timeout.hash(hasher);
+    config.limits.hash(hasher);
+    config.wasm_runtime.hash(hasher);
+    config.command_runner.id().hash(hasher);
+
+    for (regex, replacement) in &config.scrubbers {
+        regex.as_str().hash(hasher);
+        replacement.hash(hasher);
+    }
+
+    for environment in &config.environments {
+        environment.name.hash(hasher);
+        environment.wrapper.hash(hasher);
+        environment.args.hash(hasher);
+        environment.env.hash(hasher);
+        for pattern in &environment.paths {
+            pattern.as_str().hash(hasher);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Scaffolding for a new test suite, used by `goldentests init`.
+use crate::config_file::DEFAULT_CONFIG_FILE;
+use crate::error::{TestError, TestResult};
+
+use std::path::{Path, PathBuf};
+
+/// Creates a new suite within `directory`: a starter `goldentests.toml` which runs `binary_path`, the
+/// test directory `test_path` (relative to `directory`), and an example test within it using the given
+/// line prefix. `extension` is the file extension of the example test, e.g. "py", if any.
+///
+/// Existing files are never overwritten. Returns the path of each file which was created.
+///
+/// ```rust
+/// # fn main() -> goldentests::TestResult<()> {
+/// # let directory = std::path::Path::new("target/init-doctest");
+/// # let _ = std::fs::remove_dir_all(directory);
+/// let created = goldentests::init::init_suite(directory, "python", "tests", "# ", Some("py"))?;
+/// assert_eq!(created.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub fn init_suite(
+    directory: &Path,
+    binary_path: &str,
+    test_path: &str,
+    test_prefix: &str,
+    extension: Option<&str>,
+) -> TestResult<Vec<PathBuf>> {
+    let mut created = Vec::new();
+
+    let test_directory = directory.join(test_path);
+    std::fs::create_dir_all(&test_directory).map_err(|error| TestError::IoError(test_directory.clone(), error))?;
+
+    let config_path = directory.join(DEFAULT_CONFIG_FILE);
+    if create_file(&config_path, &starter_config(binary_path))? {
+        created.push(config_path);
+    }
+
+    let mut example_path = test_directory.join("example");
+    if let Some(extension) = extension {
+        example_path.set_extension(extension);
+    }
+    if create_file(&example_path, &example_test(binary_path, test_path, test_prefix))? {
+        created.push(example_path);
+    }
+    Ok(created)
+}
+
+/// Writes the file only if it doesn't already exist, returning whether it was written
+fn create_file(path: &Path, contents: &str) -> TestResult<bool> {
+    let file = std::fs::OpenOptions::new().write(true).create_new(true).open(path);
+    match file {
+        Ok(mut file) => {
+            use std::io::Write;
+            file.write_all(contents.as_bytes()).map_err(|error| TestError::IoError(path.to_owned(), error))?;
+            Ok(true)
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(error) => Err(TestError::IoError(path.to_owned(), error)),
+    }
+}
+
+fn starter_config(binary_path: &str) -> String {
+    format!(
+        r#"# Options for goldentests. Every option is optional, see the documentation of the
+# goldentests::config_file module for the full list.
+
+# The program each test is ran with
+binary_path = {}
+
+# Arguments passed to the program before the arguments of each test
+# base_args = ""
+
+# Collapse runs of spaces and tabs and ignore trailing whitespace when comparing output
+# ignore_whitespace = false
+
+# Each pattern is a regex which is replaced in the actual output of every test before comparing it
+# [scrub]
+# "0x[0-9a-f]+" = "<pointer>"
+"#,
+        toml::Value::String(binary_path.to_owned())
+    )
+}
+
+fn example_test(binary_path: &str, test_path: &str, prefix: &str) -> String {
+    let lines = [
+        "This is an example test. The program is ran with the path of this file as its last",
+        "argument, and its output is compared against the output expected below.",
+        "",
+        "Replace the contents of this file with an input for the program, then fill in its",
+        "expected output by running:",
+        &format!("  goldentests bless {} {} '{}'", binary_path, test_path, prefix),
+        "",
+        "args:",
+        "expected stdout:",
+    ];
+
+    let mut test = String::new();
+    for line in lines.iter() {
+        // Avoid trailing whitespace on empty lines when the prefix ends in a space
+        if line.is_empty() {
+            test.push_str(prefix.trim_end());
+        } else {
+            test.push_str(prefix);
+            test.push_str(line);
+        }
+        test.push('\n');
+    }
+    test.push('\n');
+    test.push_str(prefix);
+    test.push_str("expected exit status: 0\n");
+    test
+}
@@ -25,7 +25,7 @@
 //!
 //! Include a test in your program that looks something like this:
 //!
-//! ```rust
+//! ```rust,no_run
 //! use goldentests::{ TestConfig, TestResult };
 //!
 //! #[test]
@@ -57,10 +57,52 @@
 //!
 //! # expected stderr: error!
 //! ```
+
+/// Prints a line of the human-readable results of a run to stdout, or to stderr when stdout is
+/// reserved for JSON messages. See `TestConfig::message_format`.
+macro_rules! summary {
+    ($config:expr) => {
+        summary!($config, "")
+    };
+    ($config:expr, $($arg:tt)*) => {
+        if $config.message_format == $crate::config::MessageFormat::Json {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod artifacts;
+mod colors;
+pub mod command_runner;
 pub mod config;
+pub mod config_file;
 mod diff_printer;
 pub mod error;
+pub mod import;
+mod incremental;
+pub mod init;
+pub mod lint;
+mod manifest;
+pub mod parser;
+#[cfg(unix)]
+mod process_group;
+#[cfg(unix)]
+mod pty;
+mod regression;
+pub mod report;
+pub mod reporter;
 mod runner;
+mod server;
+mod session;
+mod shell;
+mod state;
+mod structured;
+mod suggestions;
+mod template;
+mod terminal;
+mod tolerance;
 
 pub use config::TestConfig;
 pub use error::TestResult;
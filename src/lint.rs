@@ -0,0 +1,167 @@
+//! Checks for test files which parse, but likely don't test what their author intended.
+use crate::config::TestConfig;
+use crate::error::InnerTestError;
+use crate::parser::{match_block_keyword, match_keyword, strip_prefix, substring_keywords};
+use crate::suggestions;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Returns each suspicious line of the given test as an `InnerTestError::Lint`:
+///
+/// - Prefixed lines which almost match a keyword, e.g. `expected stdot:`
+/// - Directives read as part of an expected output block because the block wasn't ended by a
+///   line without the test line prefix, e.g. an `expected exit status:` directly after `expected stdout:`
+/// - Exit statuses which can't be parsed
+/// - Directives which may only be given once but are given multiple times
+///
+/// Unlike `parse_test`, this reports every problem in the file rather than stopping at the first.
+///
+/// ```rust
+/// # use goldentests::{ TestConfig, TestResult };
+/// # fn main() -> TestResult<()> {
+/// let config = TestConfig::new("python", "examples", "# ")?;
+/// let lints = goldentests::lint::lint_test("examples/all_keywords.py".as_ref(), &config);
+/// assert!(lints.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub fn lint_test(test_path: &Path, config: &TestConfig) -> Vec<InnerTestError> {
+    let contents = match std::fs::read_to_string(test_path) {
+        Ok(contents) => contents,
+        Err(error) => return vec![InnerTestError::IoError(test_path.to_owned(), error)],
+    };
+
+    let keywords = config.keywords();
+    let unprefixed_keywords: Vec<&str> =
+        keywords.iter().map(|keyword| strip_prefix(keyword, &config.test_line_prefix)).collect();
+
+    // Keywords which read the lines after them until a line without the test line prefix
+    let mut block_keywords = vec![
+        config.test_stdout_prefix.as_str(),
+        config.test_stderr_prefix.as_str(),
+        config.test_output_prefix.as_str(),
+        config.test_expected_file_prefix.as_str(),
+    ];
+    block_keywords.extend(substring_keywords(config).iter().map(|(_, _, keyword)| *keyword));
+
+    let single_keywords = [
+        &config.test_args_prefix,
+        &config.test_stdout_prefix,
+        &config.test_stderr_prefix,
+        &config.test_output_prefix,
+        &config.test_exit_status_prefix,
+        &config.test_cwd_prefix,
+        &config.test_stdin_file_prefix,
+        &config.test_args_file_prefix,
+        &config.test_name_prefix,
+        &config.test_description_prefix,
+        &config.test_issue_prefix,
+        &config.test_sidecar_files_prefix,
+        &config.test_binary_output_prefix,
+        &config.test_ignore_whitespace_prefix,
+        &config.test_exact_prefix,
+        &config.test_environments_prefix,
+        &config.test_repeat_prefix,
+        &config.test_pty_prefix,
+        &config.test_hermetic_prefix,
+        &config.test_float_tolerance_prefix,
+        &config.test_min_similarity_prefix,
+        &config.test_compare_prefix,
+        &config.test_filter_prefix,
+    ];
+
+    // Keywords with a qualifier such as `(first 2 lines)` don't start with the keyword itself
+    let keyword_of = |line: &str| keywords.iter().copied().find(|keyword| match_keyword(line, keyword).is_some());
+    let name = |keyword: &str| strip_prefix(keyword, &config.test_line_prefix).trim().to_owned();
+
+    let mut lints = Vec::new();
+    let mut lint = |line: usize, message: String| {
+        lints.push(InnerTestError::Lint(test_path.to_owned(), line, message));
+    };
+
+    let fenced_keywords = [
+        &config.test_stdout_prefix,
+        &config.test_stderr_prefix,
+        &config.test_output_prefix,
+    ];
+    let terminator_of = |line: &str| {
+        fenced_keywords
+            .iter()
+            .find_map(|keyword| match_block_keyword(line, keyword).and_then(|(_, until, _)| until).map(str::to_owned))
+    };
+
+    let mut block: Option<&str> = None;
+    let mut fence: Option<String> = None;
+    let mut first_given: HashMap<&str, usize> = HashMap::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let number = index + 1;
+
+        // Fenced blocks continue through blank lines until their terminator
+        if let Some(terminator) = &fence {
+            if strip_prefix(line, &config.test_line_prefix).trim_end() == terminator {
+                fence = None;
+            }
+            continue;
+        }
+
+        let line = &*config.canonicalize_keyword(line);
+        if !line.starts_with(&config.test_line_prefix) {
+            block = None;
+            continue;
+        }
+
+        let keyword = keyword_of(line);
+        if let Some(block) = block {
+            if let Some(keyword) = keyword {
+                let message = format!(
+                    "'{}' is read as expected output of the '{}' block above it. End the block with a blank line first",
+                    name(keyword),
+                    name(block)
+                );
+                lint(number, message);
+            }
+            continue;
+        }
+
+        if let Some(terminator) = terminator_of(line) {
+            fence = Some(terminator);
+            continue;
+        }
+
+        let keyword = match keyword {
+            Some(keyword) => keyword,
+            None => {
+                let text = strip_prefix(line, &config.test_line_prefix);
+                if let Some(suggestion) = suggestions::suggest_keyword(text, &unprefixed_keywords) {
+                    let message = format!("Unknown directive '{}', did you mean '{}'?", line.trim(), suggestion);
+                    lint(number, message);
+                }
+                continue;
+            }
+        };
+
+        if single_keywords.iter().any(|single| single.as_str() == keyword) {
+            let first = *first_given.entry(keyword).or_insert(number);
+            if first != number {
+                lint(
+                    number,
+                    format!("'{}' was already given on line {}", name(keyword), first),
+                );
+            }
+        }
+
+        if keyword == config.test_exit_status_prefix {
+            let status = strip_prefix(line, keyword).trim();
+            if let Err(error) = status.parse::<i32>() {
+                lint(number, format!("Error parsing exit status '{}': {}", status, error));
+            }
+        }
+
+        if block_keywords.contains(&keyword) {
+            block = Some(keyword);
+        }
+    }
+    lints
+}
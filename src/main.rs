@@ -1,26 +1,306 @@
-mod config;
-mod diff_printer;
-mod error;
-mod runner;
-
-use crate::config::TestConfig;
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use goldentests::config::{DiffFormat, LocationFormat, MessageFormat, TestOrder};
+use goldentests::error::TestError;
+use goldentests::init::init_suite;
+use goldentests::report::Report;
+use goldentests::{config_file::DEFAULT_CONFIG_FILE, TestConfig, TestResult};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    // Giving these without a subcommand is short for `goldentests run`
+    #[clap(required = true, help = "The program to run for each test file")]
+    binary_path: Option<PathBuf>,
+
+    #[clap(
+        required = true,
+        help = "The directory to search for test files recursively within, or a manifest file listing the tests to run"
+    )]
+    test_directory: Option<PathBuf>,
+
+    #[clap(
+        required = true,
+        help = "Prefix string for test commands. This is usually the same as the comment syntax in the language you are testing. For example, in C this would be '// '"
+    )]
+    test_prefix: Option<String>,
+
+    #[clap(
+        long,
+        help = "Update the expected output of each test file to match the actual output"
+    )]
+    overwrite: bool,
+
+    #[clap(flatten)]
+    run_options: RunOptions,
+
+    #[clap(flatten)]
+    options: Options,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[clap(about = "Run each test, reporting any which fail. This is the default when no command is given")]
+    Run {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(
+            long,
+            help = "Update the expected output of each test file to match the actual output"
+        )]
+        overwrite: bool,
+
+        #[clap(flatten)]
+        run_options: RunOptions,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        about = "Run each test and update its expected output to match the actual output. Short for `run --overwrite`"
+    )]
+    Bless {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        run_options: RunOptions,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(about = "Create a goldentests.toml, the test directory, and an example test within it")]
+    Init {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(long, help = "The file extension of the example test, e.g. 'py'")]
+        extension: Option<String>,
+    },
+    #[clap(about = "Check the config file, the program, and every test for problems without running anything")]
+    Check {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        about = "Report suspicious lines in each test, such as mistyped keywords or directives swallowed by an expected output block"
+    )]
+    Lint {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        about = "Translate each lit test using FileCheck within the test directory into a goldentests test in place"
+    )]
+    ImportLit {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(about = "List the path of each test file without running anything")]
+    List {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        visible_alias = "record",
+        about = "Run the program on each file in a directory of raw inputs without any test directives, writing its actual output to each file as the expected output"
+    )]
+    Generate {
+        #[clap(help = "The program to run for each input file")]
+        binary_path: PathBuf,
+
+        #[clap(help = "The directory to search for input files recursively within")]
+        input_directory: PathBuf,
+
+        #[clap(
+            help = "Prefix string for test commands. This is usually the same as the comment syntax in the language you are testing. For example, in C this would be '// '"
+        )]
+        test_prefix: String,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        about = "Serve requests to list and run tests, read from stdin as JSON-RPC with one message per line, for editor integrations"
+    )]
+    Serve {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+    #[clap(
+        about = "Write the actual output saved by --save-actual on the last run as the expected output of each test it was saved for"
+    )]
+    Promote {
+        #[clap(flatten)]
+        target: Target,
+
+        #[clap(help = "Only promote the tests whose path contains this string")]
+        filter: Option<String>,
+
+        #[clap(flatten)]
+        options: Options,
+    },
+}
+
+/// The program and tests most commands operate on
+#[derive(clap::Args, Debug)]
+struct Target {
     #[clap(help = "The program to run for each test file")]
     binary_path: PathBuf,
 
-    #[clap(help = "The directory to search for test files recursively within")]
+    #[clap(
+        help = "The directory to search for test files recursively within, or a manifest file listing the tests to run"
+    )]
     test_directory: PathBuf,
 
     #[clap(
         help = "Prefix string for test commands. This is usually the same as the comment syntax in the language you are testing. For example, in C this would be '// '"
     )]
     test_prefix: String,
+}
+
+/// Options for commands which run the tests
+#[derive(clap::Args, Debug)]
+struct RunOptions {
+    #[clap(
+        long,
+        help = "Skip each test which passed on its last run if neither it, the files it refers to, the program, nor the config have changed since"
+    )]
+    incremental: bool,
+
+    #[clap(
+        long,
+        help = "Run each test within a temporary copy of it and its fixtures, so it can't modify the test directory"
+    )]
+    hermetic: bool,
+
+    #[clap(
+        long,
+        help = "Fail each test which leaves new files in the directory its program is ran in"
+    )]
+    forbid_stray_files: bool,
+
+    #[clap(
+        long,
+        help = "Only run the tests which failed on the last run, or every test if none did"
+    )]
+    failed: bool,
+
+    #[clap(
+        long,
+        help = "Write the actual output of each failing test to target/goldentests/<test>.actual.*"
+    )]
+    save_actual: bool,
+
+    #[clap(
+        long,
+        help = "After running, list the tests which newly fail, newly pass, or are new compared to the last run"
+    )]
+    compare: bool,
+
+    #[clap(
+        long,
+        value_name = "BINARY",
+        help = "Also run each test with this program, failing tests whose output differs between the two programs instead of comparing against the expected output"
+    )]
+    reference: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "After running, print the number of passing and failing tests within each subdirectory of the test path"
+    )]
+    summary_by_dir: bool,
 
+    #[clap(
+        long,
+        help = "Record the duration of each test to the duration_regression baseline file of the config instead of comparing against it"
+    )]
+    record_baseline: bool,
+
+    #[clap(
+        long,
+        possible_values = ["sorted", "random", "discovery"],
+        help = "The order to run tests in, 'sorted' by default. 'discovery' is the order the filesystem lists each file in, which may differ between machines"
+    )]
+    order: Option<TestOrder>,
+
+    #[clap(
+        long,
+        value_name = "SEED",
+        min_values = 0,
+        conflicts_with = "order",
+        help = "Run tests in a random order, shuffled with the given seed if any. The seed used is printed so the same order can be reproduced"
+    )]
+    shuffle: Option<Option<u64>>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Run the program of each test N times, failing if the output of any run differs from the first"
+    )]
+    repeat: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        min_values = 0,
+        default_missing_value = "10",
+        help = "After running, list the N slowest tests (10 by default) along with the total time taken"
+    )]
+    report_time: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "FORMAT=PATH",
+        multiple_occurrences = true,
+        help = "Write a report of the results to the given path after running. The format is either 'html' or 'markdown'"
+    )]
+    report: Vec<Report>,
+
+    #[clap(
+        long,
+        possible_values = ["numbered", "unified", "side-by-side"],
+        help = "How differences from the expected output are printed, 'numbered' by default. 'unified' prints a standard `diff -u` patch and 'side-by-side' prints expected and actual output in two columns"
+    )]
+    diff_format: Option<DiffFormat>,
+
+    #[clap(
+        long,
+        possible_values = ["human", "json"],
+        help = "How the events of the run are reported, 'human' by default. 'json' writes a line of JSON to stdout as each test starts and finishes, moving the usual output to stderr"
+    )]
+    message_format: Option<MessageFormat>,
+}
+
+/// Options shared by each command
+#[derive(clap::Args, Debug)]
+struct Options {
     #[clap(long, default_value = "args:", help = "The program to run for each test file")]
     args_prefix: String,
 
@@ -47,30 +327,259 @@ struct Args {
 
     #[clap(
         long,
-        help = "Update the expected output of each test file to match the actual output"
+        help = "Path to a goldentests.toml file with additional options. Defaults to ./goldentests.toml if it exists"
     )]
-    overwrite: bool,
-}
+    config: Option<PathBuf>,
 
-fn main() {
-    let args = Args::parse();
+    #[clap(
+        short,
+        long,
+        help = "The maximum number of tests to run at once. Defaults to the number of CPUs"
+    )]
+    jobs: Option<usize>,
+
+    #[clap(
+        long,
+        help = "The maximum width of the output. Defaults to the width of the terminal, if there is one"
+    )]
+    width: Option<usize>,
+
+    #[clap(
+        long,
+        possible_values = ["default", "compiler"],
+        help = "How the location of each failure is written, 'default' by default. 'compiler' writes `path:line:column: error: message` like a compiler diagnostic, which many terminals and editors turn into links"
+    )]
+    location_format: Option<LocationFormat>,
 
-    let config = match TestConfig::with_custom_keywords(
-        args.binary_path,
-        args.test_directory,
-        &args.test_prefix,
-        &args.args_prefix,
-        &args.stdout_prefix,
-        &args.stderr_prefix,
-        &args.exit_status_prefix,
-        args.overwrite,
+    #[clap(
+        long,
+        help = "Apply the options of the [profile.<PROFILE>] table in the config file, e.g. to test a release build"
+    )]
+    profile: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "PATTERN",
+        multiple_occurrences = true,
+        help = "A glob pattern of files or directories within the test directory which aren't tests, e.g. '*.md'"
+    )]
+    ignore: Vec<glob::Pattern>,
+}
+
+/// Creates the config for the given options, reporting any error to the user
+fn make_config(
+    binary_path: PathBuf,
+    test_directory: PathBuf,
+    test_prefix: &str,
+    options: Options,
+    overwrite: bool,
+) -> Option<TestConfig> {
+    let mut config = match TestConfig::with_custom_keywords(
+        binary_path,
+        test_directory,
+        test_prefix,
+        &options.args_prefix,
+        &options.stdout_prefix,
+        &options.stderr_prefix,
+        &options.exit_status_prefix,
+        overwrite,
     ) {
         Ok(config) => config,
         Err(error) => {
             eprintln!("error: {}", error);
-            return;
+            return None;
+        }
+    };
+
+    let config_file = options.config.or_else(|| {
+        let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+        default.exists().then_some(default)
+    });
+
+    let result = match (config_file, &options.profile) {
+        (Some(config_file), Some(profile)) => config.load_config_file_with_profile(config_file, profile),
+        (Some(config_file), None) => config.load_config_file(config_file),
+        (None, Some(_)) => {
+            eprintln!(
+                "error: --profile requires a config file, but {} was not found",
+                DEFAULT_CONFIG_FILE
+            );
+            return None;
+        }
+        (None, None) => Ok(()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        return None;
+    }
+
+    if options.width.is_some() {
+        config.width = options.width;
+    }
+
+    if options.jobs.is_some() {
+        config.max_jobs = options.jobs;
+    }
+
+    if let Some(location_format) = options.location_format {
+        config.location_format = location_format;
+    }
+
+    config.ignore_patterns.extend(options.ignore);
+
+    Some(config)
+}
+
+/// Runs a subcommand with the config for the given target, exiting if the config couldn't be made.
+/// Each error the subcommand returns is printed unless it already was, e.g. the failures of tests.
+fn with_config(
+    target: Target,
+    options: Options,
+    overwrite: bool,
+    command: impl FnOnce(TestConfig) -> TestResult<()>,
+) -> TestResult<()> {
+    let config = match make_config(
+        target.binary_path,
+        target.test_directory,
+        &target.test_prefix,
+        options,
+        overwrite,
+    ) {
+        Some(config) => config,
+        None => std::process::exit(1),
+    };
+
+    command(config).map_err(|error| {
+        if !matches!(error, TestError::TestErrors(_) | TestError::SetupFailed(..)) {
+            eprintln!("error: {}", error);
+        }
+        error
+    })
+}
+
+/// Runs each test, applying the options given on the command line over those of the config file
+fn run(target: Target, overwrite: bool, args: RunOptions, options: Options) -> TestResult<()> {
+    with_config(target, options, overwrite, |config| run_with_options(config, args))
+}
+
+fn run_with_options(mut config: TestConfig, args: RunOptions) -> TestResult<()> {
+    config.incremental |= args.incremental;
+    config.hermetic |= args.hermetic;
+    config.forbid_stray_files |= args.forbid_stray_files;
+    config.rerun_failed |= args.failed;
+    config.save_actual |= args.save_actual;
+    config.compare |= args.compare;
+    config.summary_by_directory |= args.summary_by_dir;
+    config.record_baseline |= args.record_baseline;
+    config.reports.extend(args.report);
+    if let Some(reference) = args.reference {
+        config.reference_binary = Some(reference);
+    }
+    if let Some(repeat) = args.repeat {
+        config.repeat = repeat;
+    }
+    if let Some(diff_format) = args.diff_format {
+        config.diff_format = diff_format;
+    }
+    if let Some(message_format) = args.message_format {
+        config.message_format = message_format;
+    }
+    if let Some(order) = args.order {
+        config.order = order;
+    }
+    if let Some(seed) = args.shuffle {
+        config.order = TestOrder::Random;
+        config.seed = seed.or(config.seed);
+    }
+    if args.report_time.is_some() {
+        config.report_time = args.report_time;
+    }
+    config.run_tests()
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let result = match args.command {
+        Some(Command::Run {
+            target,
+            overwrite,
+            run_options,
+            options,
+        }) => run(target, overwrite, run_options, options),
+        Some(Command::Bless {
+            target,
+            run_options,
+            options,
+        }) => run(target, true, run_options, options),
+        Some(Command::Init { target, extension }) => {
+            let binary_path = target.binary_path.to_string_lossy();
+            let test_directory = target.test_directory.to_string_lossy();
+            let created = init_suite(
+                Path::new("."),
+                &binary_path,
+                &test_directory,
+                &target.test_prefix,
+                extension.as_deref(),
+            );
+            created
+                .map_err(|error| {
+                    eprintln!("error: {}", error);
+                    error
+                })
+                .map(|created| {
+                    for path in &created {
+                        println!("created {}", path.display());
+                    }
+                    println!(
+                        "\nrun the tests with: goldentests {} {} '{}'",
+                        binary_path, test_directory, target.test_prefix
+                    );
+                })
+        }
+        Some(Command::Check { target, options }) => with_config(target, options, false, |config| config.check_tests()),
+        Some(Command::Lint { target, options }) => with_config(target, options, false, |config| config.lint_tests()),
+        Some(Command::ImportLit { target, options }) => {
+            with_config(target, options, false, |config| config.import_lit_tests())
+        }
+        Some(Command::List { target, options }) => with_config(target, options, false, |config| {
+            for test in config.list_tests()? {
+                println!("{}", test.display());
+            }
+            Ok(())
+        }),
+        Some(Command::Serve { target, options }) => with_config(target, options, false, |config| {
+            config.serve(std::io::stdin().lock(), std::io::stdout())
+        }),
+        Some(Command::Generate {
+            binary_path,
+            input_directory,
+            test_prefix,
+            options,
+        }) => {
+            let target = Target {
+                binary_path,
+                test_directory: input_directory,
+                test_prefix,
+            };
+            with_config(target, options, false, |config| config.generate_tests())
+        }
+        Some(Command::Promote {
+            target,
+            filter,
+            options,
+        }) => with_config(target, options, false, |config| config.promote_tests(filter.as_deref())),
+        None => {
+            // These are required by clap unless a subcommand is given
+            let target = Target {
+                binary_path: args.binary_path.unwrap(),
+                test_directory: args.test_directory.unwrap(),
+                test_prefix: args.test_prefix.unwrap(),
+            };
+            run(target, args.overwrite, args.run_options, args.options)
         }
     };
 
-    config.run_tests().unwrap_or_else(|_| std::process::exit(1));
+    result.unwrap_or_else(|_| std::process::exit(1));
 }
@@ -0,0 +1,46 @@
+//! Reading manifest files, which list the tests to run in place of a test directory so that a
+//! curated subset of a suite, e.g. its smoke tests, can be versioned and ran directly.
+use crate::error::{TestError, TestResult};
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlManifest {
+    tests: Vec<PathBuf>,
+}
+
+/// Reads the path of each test listed within the manifest at the given path. A `.toml` manifest
+/// holds a `tests = ["..."]` array, while any other manifest lists one path per line, skipping blank
+/// lines and lines starting with `#`. Relative paths are relative to the directory of the manifest.
+pub(crate) fn read_manifest(path: &Path) -> TestResult<Vec<PathBuf>> {
+    let contents = std::fs::read_to_string(path).map_err(|error| TestError::IoError(path.to_owned(), error))?;
+
+    let tests = if path.extension().is_some_and(|extension| extension == "toml") {
+        let manifest: TomlManifest = toml::from_str(&contents)
+            .map_err(|error| TestError::InvalidManifest(path.to_owned(), error.to_string()))?;
+        manifest.tests
+    } else {
+        let lines = contents.lines().map(str::trim);
+        lines.filter(|line| !line.is_empty() && !line.starts_with('#')).map(PathBuf::from).collect()
+    };
+
+    if tests.is_empty() {
+        return Err(TestError::InvalidManifest(
+            path.to_owned(),
+            "it doesn't list any tests".to_owned(),
+        ));
+    }
+
+    let directory = manifest_directory(path);
+    Ok(tests.into_iter().map(|test| directory.join(test)).collect())
+}
+
+/// The directory containing the given manifest, which the tests it lists are relative to
+pub(crate) fn manifest_directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
@@ -0,0 +1,1540 @@
+//! Parsing of test files, exposed so that other tools such as editors and linters can read the
+//! directives of a test without reimplementing the parser. See `parse_test`.
+use crate::config::{Comparison, FloatTolerance, TestConfig};
+use crate::error::InnerTestError;
+use crate::runner::InnerTestResult;
+use crate::suggestions;
+
+use regex::Regex;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// A single test file along with each directive parsed from it
+#[derive(Clone)]
+pub struct Test {
+    pub path: PathBuf,
+
+    /// A human readable name for the test shown alongside its path, given by `name:`
+    pub name: Option<String>,
+
+    /// What the test checks, given by `description:`
+    pub description: Option<String>,
+
+    /// A URL or ticket ID tracking a known problem with the test, given by `issue:`
+    pub issue: Option<String>,
+
+    /// Conditions which must be met for the test to be ran rather than skipped
+    pub requirements: Vec<Requirement>,
+
+    /// The names of the locks held while running the test, sorted and without duplicates, given by `serial:`
+    pub serial: Vec<String>,
+
+    /// The arguments given by the `args:` keyword, before any placeholders are expanded. Arguments
+    /// continued onto the following lines with a trailing `\` are joined into a single line.
+    pub command_line_args: String,
+    pub expected_stdout: ExpectedStream,
+    pub expected_stderr: ExpectedStream,
+
+    /// The expected output of stdout and stderr merged into a single stream, as with `2>&1`.
+    /// When set, this is checked instead of `expected_stdout` and `expected_stderr`.
+    pub expected_output: Option<ExpectedStream>,
+    pub expected_exit_status: Option<i32>,
+
+    /// The line of the test the expected exit status was given on
+    pub expected_exit_status_line: Option<usize>,
+    pub expected_files: Vec<ExpectedFile>,
+    pub expected_directories: Vec<ExpectedDirectory>,
+
+    /// If true, the expected stdout and stderr are stored in the sidecar files
+    /// `<test>.stdout` and `<test>.stderr` rather than within the test itself
+    pub sidecar_files: bool,
+
+    /// If true, the output is compared byte for byte against the sidecar files
+    /// without any normalization. Implies `sidecar_files`.
+    pub binary_output: bool,
+
+    /// The names of the environments to run this test within, if given explicitly
+    pub environments: Option<Vec<String>>,
+
+    /// The directory to run the binary in, if different from the current directory
+    pub cwd: Option<PathBuf>,
+
+    /// A file whose contents are piped into the stdin of the binary
+    pub stdin_file: Option<PathBuf>,
+
+    /// A file whose contents are read as arguments passed to the binary before `command_line_args`
+    pub args_file: Option<PathBuf>,
+
+    /// Shell commands ran before and after the binary
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+
+    /// A shell command the actual stdout and stderr are piped through before being compared
+    pub filter: Option<String>,
+
+    /// False if the test doesn't contain a single keyword
+    pub has_directives: bool,
+
+    /// Lines which look like a mistyped keyword, along with the keyword they most resemble
+    pub warnings: Vec<(String, String)>,
+
+    /// Differences in lines matching any of these patterns are reported but do not fail the test
+    pub allowed_diffs: Vec<Regex>,
+
+    /// If true, runs of spaces and tabs are collapsed and trailing whitespace is ignored when comparing
+    pub ignore_whitespace: bool,
+
+    /// If true, output is compared without trimming whitespace or removing `\r`
+    pub exact: bool,
+
+    /// How expected output is compared against the actual output
+    pub comparison: Comparison,
+
+    /// Fields removed from structured output before comparing it, see `TestConfig::ignored_fields`
+    pub ignored_fields: Vec<String>,
+
+    /// If set, numbers in the output are compared within this tolerance rather than exactly
+    pub float_tolerance: Option<FloatTolerance>,
+
+    /// If set, differences in a stream are allowed if it is at least this similar to its expected output
+    pub min_similarity: Option<f32>,
+
+    /// Blocks of text which must appear somewhere within stdout or stderr
+    pub expected_substrings: Vec<ExpectedSubstring>,
+
+    /// The number of times the binary is ran. The test fails if the output of any run differs from the first.
+    pub repeat: usize,
+
+    /// If true, the binary is ran under a pseudo-terminal rather than with its output piped
+    pub pty: bool,
+
+    /// If true, the binary is ran within a temporary copy of the test and its `fixtures`
+    pub hermetic: bool,
+
+    /// Files and directories copied along with the test in hermetic mode, relative to the directory of the test
+    pub fixtures: Vec<PathBuf>,
+
+    /// FileCheck style assertions about the stdout of the binary, in the order they are checked
+    pub checks: Vec<Check>,
+
+    /// The lines sent to the binary and the output expected back from it, in order. If this isn't empty,
+    /// the test is ran as an interactive session, see `TestConfig::test_send_prefix`.
+    pub session: Vec<SessionStep>,
+
+    /// The shared files given by `include:` whose directives are parsed as part of this test
+    pub includes: Vec<PathBuf>,
+
+    /// True if the args or expected exit status were given by an included file, in which
+    /// case they aren't written into the test itself when overwriting it
+    pub(crate) args_included: bool,
+    pub(crate) exit_status_included: bool,
+
+    /// Each line of the `args:` directive as written, so that continued arguments are overwritten the same way
+    pub(crate) args_lines: Vec<String>,
+
+    /// The contents of the file with each expected output block removed, which the actual
+    /// output is written back into when overwriting the test
+    pub(crate) rest: String,
+}
+
+impl Test {
+    /// True if the given stream is only checked for substrings, by `check:` directives, or by
+    /// the `expect:` steps of a session, since its full expected output was never given
+    pub(crate) fn is_partially_checked(&self, stream: &str, expected: &ExpectedStream) -> bool {
+        !expected.explicit
+            && (self.expected_substrings.iter().any(|substring| substring.stream == stream)
+                || (stream == "stdout" && (!self.session.is_empty() || !self.checks.is_empty())))
+    }
+
+    /// Resolves a path given within this test relative to the directory the binary is ran in
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+}
+
+/// A single step of an interactive session with the binary
+#[derive(Clone)]
+pub enum SessionStep {
+    /// A line written to the stdin of the binary
+    Send(String),
+
+    /// Text which the stdout of the binary must contain after that of the previous `Expect`.
+    /// The timeout overrides `TestConfig::session_step_timeout` if given, e.g. by `expect (30s):`.
+    Expect { text: String, timeout: Option<Duration> },
+}
+
+/// The expected contents of a single output stream, either stdout or stderr
+#[derive(Clone, Default)]
+pub struct ExpectedStream {
+    pub text: String,
+
+    /// If set, only the first or last few lines of the actual output are compared
+    pub limit: Option<LineLimit>,
+
+    /// If set, the block is written in this encoding, e.g. by `expected stdout (base64):`, and the
+    /// decoded bytes in `raw` are compared against the actual output byte for byte
+    pub encoding: Option<Encoding>,
+
+    /// True if the block is written with escape sequences such as `\t` or `\x1b`, given by `expected stdout (escaped):`
+    pub escaped: bool,
+
+    /// The line ending a fenced block given by `expected stdout until: EOF`, which may contain blank lines
+    pub until: Option<String>,
+
+    /// The exact expected bytes for tests using `binary output` or an encoded block
+    pub raw: Vec<u8>,
+
+    /// True if the stream was given by a keyword or sidecar file rather than defaulting to empty
+    pub explicit: bool,
+
+    /// The line of the test the keyword giving this stream is on, if it was given within the test.
+    /// The expected output itself starts on this line or the next. Lines from an included file are
+    /// counted as being on the line of their `include:`.
+    pub line: Option<usize>,
+
+    /// The start of `text` given by included files, which isn't written into the test itself when overwriting it
+    pub(crate) included: String,
+}
+
+/// A block of text which must appear somewhere within the output of a stream, or at its start or
+/// end, or which must not appear anywhere within it, depending on its `kind`
+#[derive(Clone)]
+pub struct ExpectedSubstring {
+    /// Either "stdout" or "stderr"
+    pub stream: &'static str,
+    pub kind: SubstringKind,
+    pub text: String,
+
+    /// The line of the test the keyword giving this substring is on
+    pub line: usize,
+
+    /// True if this was given by an included file rather than the test itself
+    pub(crate) included: bool,
+}
+
+/// A condition given by `requires:` which must be met for a test to be ran. See `TestConfig::test_requires_prefix`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// A program which must be found on the `PATH`, e.g. `requires: python3`
+    Program(String),
+
+    /// An environment variable which must be set, e.g. `requires: env DISPLAY`
+    EnvVar(String),
+}
+
+/// A single `check:`, `check-next:`, or `check-not:` directive. See `TestConfig::test_check_prefix`.
+#[derive(Clone)]
+pub struct Check {
+    pub kind: CheckKind,
+    pub text: String,
+
+    /// The line of the test the check is on
+    pub line: usize,
+}
+
+/// Where the text of a `Check` must appear within stdout, relative to the text matched by the previous check
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CheckKind {
+    /// The text must appear anywhere after the previous match, e.g. `check:`
+    Check,
+
+    /// The text must appear on the line after the previous match, e.g. `check-next:`
+    Next,
+
+    /// The text must not appear between the previous and next matches, e.g. `check-not:`
+    Not,
+}
+
+/// How an `ExpectedSubstring` is checked against the output of its stream
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubstringKind {
+    /// The text must appear somewhere within the output, e.g. `expected stdout contains:`
+    Contains,
+
+    /// The text must not appear anywhere within the output, e.g. `expected stdout excludes:`
+    Excludes,
+
+    /// The output must start with the text, ignoring leading whitespace, e.g. `expected stdout starts with:`
+    StartsWith,
+
+    /// The output must end with the text, ignoring trailing whitespace, e.g. `expected stdout ends with:`
+    EndsWith,
+}
+
+/// Each keyword for an `ExpectedSubstring` along with the stream it checks and how
+pub(crate) fn substring_keywords(config: &TestConfig) -> [(&'static str, SubstringKind, &str); 8] {
+    [
+        ("stdout", SubstringKind::Contains, &config.test_stdout_contains_prefix),
+        ("stderr", SubstringKind::Contains, &config.test_stderr_contains_prefix),
+        ("stdout", SubstringKind::Excludes, &config.test_stdout_excludes_prefix),
+        ("stderr", SubstringKind::Excludes, &config.test_stderr_excludes_prefix),
+        (
+            "stdout",
+            SubstringKind::StartsWith,
+            &config.test_stdout_starts_with_prefix,
+        ),
+        (
+            "stderr",
+            SubstringKind::StartsWith,
+            &config.test_stderr_starts_with_prefix,
+        ),
+        ("stdout", SubstringKind::EndsWith, &config.test_stdout_ends_with_prefix),
+        ("stderr", SubstringKind::EndsWith, &config.test_stderr_ends_with_prefix),
+    ]
+}
+
+impl ExpectedSubstring {
+    pub(crate) fn keyword<'a>(&self, config: &'a TestConfig) -> &'a str {
+        let keywords = substring_keywords(config);
+        let found = keywords.iter().find(|(stream, kind, _)| *stream == self.stream && *kind == self.kind);
+        found.unwrap().2
+    }
+
+    /// True if the given output of this substring's stream satisfies it
+    pub(crate) fn matches(&self, output: &str) -> bool {
+        let text = self.text.trim();
+        match self.kind {
+            SubstringKind::Contains => output.contains(text),
+            SubstringKind::Excludes => !output.contains(text),
+            SubstringKind::StartsWith => output.trim_start().starts_with(text),
+            SubstringKind::EndsWith => output.trim_end().ends_with(text),
+        }
+    }
+}
+
+/// A qualifier on an expected output keyword, e.g. `expected stdout (first 20 lines):`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineLimit {
+    First(usize),
+    Last(usize),
+}
+
+impl LineLimit {
+    /// Parses the qualifier between the parenthesis of `expected stdout (first 20 lines):`
+    pub fn parse(qualifier: &str) -> Option<LineLimit> {
+        let words: Vec<&str> = qualifier.split_whitespace().collect();
+        let (kind, count) = match words.as_slice() {
+            [kind, "line"] => (*kind, 1),
+            [kind, count, "lines"] => (*kind, count.parse().ok()?),
+            _ => return None,
+        };
+
+        match kind {
+            "first" => Some(LineLimit::First(count)),
+            "last" => Some(LineLimit::Last(count)),
+            _ => None,
+        }
+    }
+
+    /// Returns only the lines of the given output this limit applies to
+    pub(crate) fn apply<'a>(self, output: &'a str) -> std::borrow::Cow<'a, str> {
+        // Each line keeps its line ending so that exact comparisons still see them
+        let lines: Vec<&str> = output.split_inclusive('\n').collect();
+        let selected = match self {
+            LineLimit::First(count) => &lines[..count.min(lines.len())],
+            LineLimit::Last(count) => &lines[lines.len().saturating_sub(count)..],
+        };
+
+        if selected.len() == lines.len() {
+            output.into()
+        } else {
+            selected.concat().into()
+        }
+    }
+}
+
+impl std::fmt::Display for LineLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (kind, count) = match self {
+            LineLimit::First(count) => ("first", count),
+            LineLimit::Last(count) => ("last", count),
+        };
+        if *count == 1 {
+            write!(f, "{} line", kind)
+        } else {
+            write!(f, "{} {} lines", kind, count)
+        }
+    }
+}
+
+/// The encoding of a block of expected output, given as a qualifier: `expected stdout (hex):`.
+/// Encoded blocks can represent output which can't be written within comment lines, such as
+/// binary data or control characters. Whitespace within an encoded block is ignored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Base64,
+    Hex,
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Encoding {
+    /// Parses the qualifier between the parenthesis of `expected stdout (base64):`
+    pub fn parse(qualifier: &str) -> Option<Encoding> {
+        match qualifier {
+            "base64" => Some(Encoding::Base64),
+            "hex" => Some(Encoding::Hex),
+            _ => None,
+        }
+    }
+
+    /// Encodes the given bytes, wrapping the result into lines of at most 76 characters
+    ///
+    /// ```rust
+    /// use goldentests::parser::Encoding;
+    /// assert_eq!(Encoding::Base64.encode(b"hi\x1b"), vec!["aGkb"]);
+    /// assert_eq!(Encoding::Hex.encode(b"hi\x1b"), vec!["68691b"]);
+    /// ```
+    pub fn encode(self, bytes: &[u8]) -> Vec<String> {
+        let (chunk_size, encode_chunk): (usize, fn(&[u8]) -> String) = match self {
+            Encoding::Base64 => (57, encode_base64),
+            Encoding::Hex => (38, |chunk| chunk.iter().map(|byte| format!("{:02x}", byte)).collect()),
+        };
+        bytes.chunks(chunk_size).map(encode_chunk).collect()
+    }
+
+    /// Decodes the given block, returning the reason it isn't valid otherwise
+    ///
+    /// ```rust
+    /// use goldentests::parser::Encoding;
+    /// assert_eq!(Encoding::Base64.decode("aGk\nb"), Ok(b"hi\x1b".to_vec()));
+    /// assert_eq!(Encoding::Hex.decode("68 69\n1B"), Ok(b"hi\x1b".to_vec()));
+    /// assert!(Encoding::Hex.decode("686").is_err());
+    /// ```
+    pub fn decode(self, text: &str) -> Result<Vec<u8>, String> {
+        let digits: Vec<u8> = text.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+        match self {
+            Encoding::Base64 => decode_base64(&digits),
+            Encoding::Hex => {
+                if !digits.len().is_multiple_of(2) {
+                    return Err("hex must have an even number of digits".to_owned());
+                }
+                let digit = |byte: u8| {
+                    (byte as char).to_digit(16).ok_or_else(|| format!("'{}' is not a hex digit", byte as char))
+                };
+                digits.chunks(2).map(|pair| Ok((digit(pair[0])? * 16 + digit(pair[1])?) as u8)).collect()
+            }
+        }
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| group | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn decode_base64(digits: &[u8]) -> Result<Vec<u8>, String> {
+    if !digits.len().is_multiple_of(4) {
+        return Err("base64 must have a multiple of 4 characters, including any '=' padding".to_owned());
+    }
+
+    let mut bytes = Vec::new();
+    for (index, chunk) in digits.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|digit| **digit == b'=').count();
+        if padding > 2 || (padding > 0 && index + 1 != digits.len() / 4) {
+            return Err("'=' padding may only end the base64".to_owned());
+        }
+
+        let mut group = 0u32;
+        for (i, digit) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64_ALPHABET
+                .iter()
+                .position(|c| c == digit)
+                .ok_or_else(|| format!("'{}' is not a base64 character", *digit as char))?;
+            group |= (value as u32) << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Encoding::Base64 => write!(f, "base64"),
+            Encoding::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+/// Replaces the escape sequences within an escaped block of expected output: `\t`, `\n`, `\r`, `\0`, `\\`,
+/// `\xNN` for an ASCII character, and `\u{NNNN}` for any other character.
+pub(crate) fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => byte as char,
+                    _ => {
+                        return Err(format!(
+                            "'\\x{}' must be followed by two hex digits of at most 7f",
+                            digits
+                        ))
+                    }
+                }
+            }
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest.strip_prefix('{').and_then(|rest| rest.split_once('}')).map(|(code, _)| code);
+                let c = code.and_then(|code| u32::from_str_radix(code, 16).ok()).and_then(char::from_u32);
+                match (code, c) {
+                    (Some(code), Some(c)) => {
+                        chars = rest[code.len() + 2..].chars();
+                        c
+                    }
+                    _ => return Err("'\\u' must be followed by a unicode character in hex, e.g. '\\u{1b}'".to_owned()),
+                }
+            }
+            Some(other) => return Err(format!("unknown escape sequence '\\{}'", other)),
+            None => return Err("a '\\' can't end an escaped block, use '\\\\' to write a backslash".to_owned()),
+        };
+        unescaped.push(escaped);
+    }
+    Ok(unescaped)
+}
+
+/// Escapes the given output so it can be written within an escaped block, the reverse of `unescape`.
+/// Backslashes and control characters other than newlines are escaped.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push('\n'),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// An on-disk file which is expected to exist with the given contents after running a test
+#[derive(Clone)]
+pub struct ExpectedFile {
+    /// The path of the file as written in the test, relative to the directory the binary is ran in.
+    /// See `Test::resolve_path`.
+    pub path: String,
+    pub text: String,
+
+    /// The line of the test the keyword giving this file is on
+    pub line: usize,
+
+    /// True if this was given by an included file rather than the test itself
+    pub(crate) included: bool,
+}
+
+/// An on-disk directory which is expected to contain the same files as a golden directory tree after running a test
+#[derive(Clone)]
+pub struct ExpectedDirectory {
+    /// The path of the directory as written in the test, relative to the directory the binary is ran in.
+    /// See `Test::resolve_path`.
+    pub path: String,
+
+    /// The golden directory tree, relative to the current directory
+    pub golden: PathBuf,
+
+    /// The line of the test the keyword giving this directory is on
+    pub line: usize,
+}
+
+/// A fenced block of expected output which is still being read, started by `expected stdout until: EOF`
+struct Fence {
+    terminator: String,
+    directive: String,
+    line: usize,
+    included: bool,
+}
+
+#[derive(PartialEq)]
+enum TestParseState {
+    Neutral,
+    ReadingArgs,
+    ReadingExpectedStdout,
+    ReadingExpectedStderr,
+    ReadingExpectedOutput,
+    ReadingExpectedSubstring,
+    ReadingExpectedFile,
+}
+
+/// The path of the sidecar file holding the expected output of the given stream for a test
+pub(crate) fn sidecar_path(test_path: &Path, stream: &str) -> PathBuf {
+    let mut path = test_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(stream);
+    PathBuf::from(path)
+}
+
+pub(crate) fn read_sidecar_file(test_path: &Path, stream: &str) -> InnerTestResult<Vec<u8>> {
+    let path = sidecar_path(test_path, stream);
+    match std::fs::read(&path) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(InnerTestError::IoError(path, err)),
+    }
+}
+
+/// Parses the condition of a `requires:` directive, either a program or `env <VARIABLE>`
+fn parse_requirement(requirement: &str) -> Option<Requirement> {
+    let words: Vec<&str> = requirement.split_whitespace().collect();
+    match words[..] {
+        ["env", variable] => Some(Requirement::EnvVar(variable.to_owned())),
+        [program] => Some(Requirement::Program(program.to_owned())),
+        _ => None,
+    }
+}
+
+/// Parses the value of a boolean directive, which may be written as `true`/`false` or `yes`/`no`
+fn parse_bool(test_path: &Path, line: usize, directive: &str, value: &str) -> InnerTestResult<bool> {
+    match value.trim() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        other => {
+            let reason = format!("expected 'true', 'false', 'yes', or 'no' but found '{}'", other);
+            Err(InnerTestError::ErrorParsingDirective(
+                test_path.to_owned(),
+                line,
+                directive.to_owned(),
+                reason,
+            ))
+        }
+    }
+}
+
+/// Parses the names given to `environments:`, each of which must be a configured environment.
+/// An empty list is an error rather than a test which is never ran.
+fn parse_environments(test_path: &Path, line: usize, config: &TestConfig, names: &str) -> InnerTestResult<Vec<String>> {
+    let error = |reason: String| {
+        let directive = config.test_environments_prefix.clone();
+        InnerTestError::ErrorParsingDirective(test_path.to_owned(), line, directive, reason)
+    };
+
+    let names = names.split(',').map(|name| name.trim().to_owned()).filter(|name| !name.is_empty());
+    let names = names
+        .map(|name| {
+            if config.environments.iter().any(|environment| environment.name == name) {
+                Ok(name)
+            } else {
+                Err(error(format!("there is no environment named '{}'", name)))
+            }
+        })
+        .collect::<InnerTestResult<Vec<_>>>()?;
+
+    if names.is_empty() {
+        return Err(error("expected at least one environment".to_owned()));
+    }
+    Ok(names)
+}
+
+/// Parses the `"out/result.json": ...` after an "expected file" keyword, returning the
+/// path and the rest of the line after the ':'. The path may also be unquoted if it does
+/// not contain a ':'.
+fn parse_expected_file_path(directive: &str) -> Option<(String, &str)> {
+    let directive = directive.trim_start();
+    let (path, rest) = match directive.strip_prefix('"') {
+        Some(quoted) => {
+            let (path, rest) = quoted.split_once('"')?;
+            (path, rest.trim_start().strip_prefix(':')?)
+        }
+        None => {
+            let (path, rest) = directive.split_once(':')?;
+            (path.trim(), rest)
+        }
+    };
+
+    if path.is_empty() {
+        None
+    } else {
+        Some((path.to_owned(), rest))
+    }
+}
+
+pub(crate) fn strip_prefix<'a>(s: &'a str, prefix: &str) -> &'a str {
+    s.strip_prefix(prefix).unwrap_or(s)
+}
+
+fn strip_suffix<'a>(s: &'a str, suffix: &str) -> &'a str {
+    s.strip_suffix(suffix).unwrap_or(s)
+}
+
+pub(crate) fn append_line(s: &mut String, line: &str) {
+    *s += line;
+    *s += "\n";
+}
+
+/// Splits the contents of a test into lines. `str::lines` also removes the \r of each \r\n,
+/// which exact mode must keep.
+fn split_lines(contents: &str, exact: bool) -> Vec<&str> {
+    if exact {
+        contents.split_inclusive('\n').map(|line| strip_suffix(line, "\n")).collect()
+    } else {
+        contents.lines().collect()
+    }
+}
+
+/// Appends a line to the `rest` of a test, which is kept as-is when overwriting the test. Lines
+/// from included files are skipped since they are written in the included file instead.
+fn append_rest(rest: &mut String, line: &str, included: bool) {
+    if !included {
+        append_line(rest, line);
+    }
+}
+
+/// Appends the rest of a keyword's line to the block it starts, skipping the line if it is empty.
+/// Blocks are normally trimmed so leading whitespace is dropped as well, but in exact mode only the
+/// space after the keyword is dropped. The block may continue text given by an included file.
+fn append_first_line(s: &mut String, rest: &str, exact: bool) {
+    let rest = if exact {
+        strip_prefix(rest, " ")
+    } else {
+        rest.trim_start()
+    };
+    if !rest.is_empty() {
+        append_line(s, rest);
+    }
+}
+
+/// Matches a line against a keyword like "# expected stdout:". If the keyword ends in a ':',
+/// a parenthesized qualifier is also accepted before it: "# expected stdout (first 3 lines):".
+/// On a match, this returns the qualifier, if any, and the rest of the line after the keyword.
+pub(crate) fn match_keyword<'a>(line: &'a str, keyword: &str) -> Option<(Option<&'a str>, &'a str)> {
+    if let Some(rest) = line.strip_prefix(keyword) {
+        return Some((None, rest));
+    }
+
+    let rest = line.strip_prefix(keyword.strip_suffix(':')?)?;
+    let rest = rest.trim_start().strip_prefix('(')?;
+    let (qualifier, rest) = rest.split_once(')')?;
+    let rest = rest.trim_start().strip_prefix(':')?;
+    Some((Some(qualifier.trim()), rest))
+}
+
+/// Matches a line against the keyword of a block of expected output, either as it is normally written
+/// or as the start of a fenced block: "# expected stdout until: EOF". On a match, this returns the
+/// qualifier, if any, the terminator of a fenced block, and the rest of the line after the keyword.
+pub(crate) fn match_block_keyword<'a>(
+    line: &'a str,
+    keyword: &str,
+) -> Option<(Option<&'a str>, Option<&'a str>, &'a str)> {
+    if let Some((qualifier, rest)) = match_keyword(line, keyword) {
+        return Some((qualifier, None, rest));
+    }
+
+    let rest = line.strip_prefix(keyword.strip_suffix(':')?)?.trim_start();
+    let (qualifier, rest) = match rest.strip_prefix('(') {
+        Some(rest) => {
+            let (qualifier, rest) = rest.split_once(')')?;
+            (Some(qualifier.trim()), rest.trim_start())
+        }
+        None => (None, rest),
+    };
+    let terminator = rest.strip_prefix("until:")?;
+    Some((qualifier, Some(terminator.trim()), ""))
+}
+
+/// Starts reading a fenced block if the block keyword on the given line has a terminator
+fn start_fence(
+    test_path: &Path,
+    line_number: usize,
+    line: &str,
+    terminator: Option<&str>,
+    included: bool,
+) -> InnerTestResult<Option<Fence>> {
+    match terminator {
+        None => Ok(None),
+        Some("") => {
+            let reason = "expected the line ending the block after 'until:', e.g. 'until: EOF'".to_owned();
+            Err(InnerTestError::ErrorParsingDirective(
+                test_path.to_owned(),
+                line_number,
+                line.to_owned(),
+                reason,
+            ))
+        }
+        Some(terminator) => Ok(Some(Fence {
+            terminator: terminator.to_owned(),
+            directive: line.to_owned(),
+            line: line_number,
+            included,
+        })),
+    }
+}
+
+impl Fence {
+    fn unterminated(&self, test_path: &Path) -> InnerTestError {
+        let reason = format!("the block isn't ended by a line containing '{}'", self.terminator);
+        InnerTestError::ErrorParsingDirective(test_path.to_owned(), self.line, self.directive.clone(), reason)
+    }
+}
+
+/// True if the given line of args ends with a `\`, continuing them onto the next line
+fn continues(args: &str) -> bool {
+    args.trim_end().ends_with('\\')
+}
+
+/// Parses the value of a `float tolerance:` directive, e.g. "1e-9", "0.1%", or "1e-9 0.1%"
+fn parse_float_tolerance(value: &str) -> Option<FloatTolerance> {
+    let mut tolerance = FloatTolerance::default();
+    let mut words = value.split_whitespace().peekable();
+    words.peek()?;
+    for word in words {
+        match word.strip_suffix('%') {
+            Some(percent) => tolerance.relative = percent.parse::<f64>().ok()? / 100.0,
+            None => tolerance.absolute = word.parse().ok()?,
+        }
+    }
+    Some(tolerance)
+}
+
+/// Parses the value of a `min similarity:` directive, either a ratio such as "0.95" or a percentage such as "95%"
+fn parse_similarity(value: &str) -> Option<f32> {
+    let similarity = match value.trim().strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok()? / 100.0,
+        None => value.trim().parse().ok()?,
+    };
+    (0.0..=1.0).contains(&similarity).then_some(similarity)
+}
+
+/// Parses a timeout qualifier in seconds, e.g. "30s" or "0.5s"
+fn parse_timeout(qualifier: &str) -> Option<Duration> {
+    let seconds: f64 = qualifier.strip_suffix('s')?.trim().parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parses the qualifier of an expected output keyword into either a line limit or an encoding of the stream
+fn parse_stream_qualifier(
+    test_path: &Path,
+    line: usize,
+    qualifier: Option<&str>,
+    expected: &mut ExpectedStream,
+) -> InnerTestResult<()> {
+    expected.limit = None;
+    expected.encoding = None;
+    expected.escaped = false;
+    match qualifier {
+        None => Ok(()),
+        Some(qualifier) => {
+            if qualifier == "escaped" {
+                expected.escaped = true;
+            } else if let Some(encoding) = Encoding::parse(qualifier) {
+                expected.encoding = Some(encoding);
+            } else if let Some(limit) = LineLimit::parse(qualifier) {
+                expected.limit = Some(limit);
+            } else {
+                let qualifier = qualifier.to_owned();
+                return Err(InnerTestError::ErrorParsingQualifier(
+                    test_path.to_owned(),
+                    line,
+                    qualifier,
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Parses the test file at the given path, reading its directives with the keywords of the given config.
+/// Sidecar files holding the expected output of the test are read as well. This doesn't run the test.
+///
+/// ```rust
+/// # use goldentests::{TestConfig, TestResult};
+/// # fn main() -> TestResult<()> {
+/// let config = TestConfig::new("python", "examples", "# ")?;
+/// let test = goldentests::parser::parse_test("examples/multiline.py".as_ref(), &config).unwrap();
+/// assert!(test.expected_stdout.explicit);
+/// # Ok(())
+/// # }
+/// ```
+pub fn parse_test(test_path: &Path, config: &TestConfig) -> Result<Test, InnerTestError> {
+    let mut args_lines: Vec<String> = Vec::new();
+    let mut name = None;
+    let mut description = None;
+    let mut issue = None;
+    let mut requirements = Vec::new();
+    let mut serial = Vec::new();
+    let mut expected_stdout = ExpectedStream::default();
+    let mut expected_stderr = ExpectedStream::default();
+    let mut expected_output: Option<ExpectedStream> = None;
+    let mut expected_substrings: Vec<ExpectedSubstring> = Vec::new();
+    let mut expected_exit_status = config.default_exit_status;
+    let mut expected_exit_status_line = None;
+    let mut expected_files: Vec<ExpectedFile> = Vec::new();
+    let mut expected_directories: Vec<ExpectedDirectory> = Vec::new();
+    let mut sidecar_files = config.sidecar_files;
+    let mut binary_output = false;
+    let mut ignore_whitespace = config.ignore_whitespace;
+    let mut exact = config.exact;
+    let mut environments = None;
+    let mut cwd = None;
+    let mut stdin_file = None;
+    let mut args_file = None;
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut filter = config.filter.clone();
+    let mut allowed_diffs = Vec::new();
+    let mut includes = Vec::new();
+    let mut repeat = config.repeat;
+    let mut session = Vec::new();
+    let mut checks = Vec::new();
+    let mut pty = config.pty;
+    let mut hermetic = config.hermetic;
+    let mut fixtures = Vec::new();
+    let mut float_tolerance = config.float_tolerance;
+    let mut min_similarity = None;
+    let mut comparison = config.comparison;
+    let mut ignored_fields = config.ignored_fields.clone();
+    let mut args_included = false;
+    let mut exit_status_included = false;
+    let mut rest = String::new();
+
+    let mut file = File::open(test_path).map_err(|err| InnerTestError::IoError(test_path.to_owned(), err))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|err| InnerTestError::IoError(test_path.to_owned(), err))?;
+
+    let keywords = config.keywords();
+    let has_directives = contents
+        .lines()
+        .map(|line| config.canonicalize_keyword(line))
+        .any(|line| keywords.iter().any(|keyword| line.starts_with(keyword)));
+    let unprefixed_keywords: Vec<&str> =
+        keywords.iter().map(|keyword| strip_prefix(keyword, &config.test_line_prefix)).collect();
+    let mut warnings = Vec::new();
+
+    // Exact mode changes how every block is read, so it must be known before reaching any of them
+    for (index, line) in contents.lines().enumerate() {
+        let line = config.canonicalize_keyword(line);
+        if let Some(value) = line.strip_prefix(&config.test_exact_prefix) {
+            exact = parse_bool(test_path, index + 1, &config.test_exact_prefix, value)?;
+        }
+    }
+
+    // Each included file is read up front so its lines can be parsed in place of the include line
+    let test_directory = test_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut included_contents = Vec::new();
+    for (index, line) in split_lines(&contents, exact).into_iter().enumerate() {
+        let line = config.canonicalize_keyword(line);
+        if let Some(path) = line.strip_prefix(&config.test_include_prefix) {
+            let path = test_directory.join(path.trim());
+            let contents = std::fs::read_to_string(&path).map_err(|err| InnerTestError::IoError(path.clone(), err))?;
+            if contents
+                .lines()
+                .any(|line| config.canonicalize_keyword(line).starts_with(&config.test_include_prefix))
+            {
+                let reason = format!("{} can't include other files", path.display());
+                return Err(InnerTestError::ErrorParsingDirective(
+                    test_path.to_owned(),
+                    index + 1,
+                    line.into_owned(),
+                    reason,
+                ));
+            }
+            includes.push(path);
+            included_contents.push(contents);
+        }
+    }
+
+    // Each line along with whether it is from an included file and its line number within the test.
+    // Lines from an included file are numbered with the line of their `include:`. An empty line ends
+    // each included file so that a block it ends with doesn't continue into the test.
+    let mut lines = Vec::new();
+    let mut included_files = included_contents.iter();
+    for (index, line) in split_lines(&contents, exact).into_iter().enumerate() {
+        let line_number = index + 1;
+        lines.push((line, false, line_number));
+        if config.canonicalize_keyword(line).starts_with(&config.test_include_prefix) {
+            let included = included_files.next().unwrap();
+            lines.extend(split_lines(included, exact).into_iter().map(|line| (line, true, line_number)));
+            lines.push(("", true, line_number));
+        }
+    }
+
+    let mut state = TestParseState::Neutral;
+    let mut fence: Option<Fence> = None;
+    for (original, included, line_number) in lines {
+        // Aliases are only read as keywords outside of any expected output, which is kept as written
+        let line = match state {
+            TestParseState::Neutral => config.canonicalize_keyword(original),
+            _ => Cow::Borrowed(original),
+        };
+        let line = &*line;
+
+        // A fenced block reads each prefixed or blank line until its terminator, which must be in the same file
+        if let Some(current) = &fence {
+            let bare_prefix = config.test_line_prefix.trim_end();
+            let text = match line.strip_prefix(&config.test_line_prefix) {
+                Some(text) => text,
+                None if line.trim().is_empty() || line.trim_end() == bare_prefix => "",
+                None => return Err(current.unterminated(test_path)),
+            };
+            if included != current.included {
+                return Err(current.unterminated(test_path));
+            }
+
+            if text.trim_end() == current.terminator {
+                fence = None;
+                state = TestParseState::Neutral;
+            } else {
+                let stream = match state {
+                    TestParseState::ReadingExpectedStdout => &mut expected_stdout,
+                    TestParseState::ReadingExpectedStderr => &mut expected_stderr,
+                    _ => expected_output.as_mut().unwrap(),
+                };
+                append_line(&mut stream.text, text);
+            }
+        } else if line.starts_with(&config.test_line_prefix) {
+            // If the previous line of args ended with a `\`, this line continues them
+            if state == TestParseState::ReadingArgs {
+                let args = strip_prefix(line, &config.test_line_prefix);
+                if !continues(args) {
+                    state = TestParseState::Neutral;
+                }
+                args_lines.push(args.to_owned());
+
+            // If we're currently reading stdout or stderr, append the line to the expected output
+            } else if state == TestParseState::ReadingExpectedStdout {
+                append_line(&mut expected_stdout.text, strip_prefix(line, &config.test_line_prefix))
+            } else if state == TestParseState::ReadingExpectedStderr {
+                append_line(&mut expected_stderr.text, strip_prefix(line, &config.test_line_prefix));
+            } else if state == TestParseState::ReadingExpectedOutput {
+                let expected = expected_output.as_mut().unwrap();
+                append_line(&mut expected.text, strip_prefix(line, &config.test_line_prefix));
+            } else if state == TestParseState::ReadingExpectedSubstring {
+                let substring = expected_substrings.last_mut().unwrap();
+                append_line(&mut substring.text, strip_prefix(line, &config.test_line_prefix));
+            } else if state == TestParseState::ReadingExpectedFile {
+                let file = expected_files.last_mut().unwrap();
+                append_line(&mut file.text, strip_prefix(line, &config.test_line_prefix));
+
+            // Otherwise, look to see if the line begins with a keyword and if so change state
+            // (stdout/stderr) or parse an argument to the keyword (args/exit status).
+
+            // args:
+            } else if line.starts_with(&config.test_args_prefix) {
+                let args = strip_prefix(line, &config.test_args_prefix);
+                if continues(args) {
+                    state = TestParseState::ReadingArgs;
+                }
+                args_lines = vec![args.to_owned()];
+                args_included = included;
+
+            // expected stdout:
+            } else if let Some((qualifier, until, rest)) = match_block_keyword(line, &config.test_stdout_prefix) {
+                state = TestParseState::ReadingExpectedStdout;
+                expected_stdout.explicit = true;
+                expected_stdout.line = Some(line_number);
+                parse_stream_qualifier(test_path, line_number, qualifier, &mut expected_stdout)?;
+                fence = start_fence(test_path, line_number, line, until, included)?;
+                expected_stdout.until = until.map(str::to_owned);
+                // Append the remainder of the line to the expected stdout.
+                // Both expected_stdout and expected_stderr are trimmed so it
+                // has no effect if the rest of this line is empty
+                append_first_line(&mut expected_stdout.text, rest, exact);
+
+            // expected stderr:
+            } else if let Some((qualifier, until, rest)) = match_block_keyword(line, &config.test_stderr_prefix) {
+                state = TestParseState::ReadingExpectedStderr;
+                expected_stderr.explicit = true;
+                expected_stderr.line = Some(line_number);
+                parse_stream_qualifier(test_path, line_number, qualifier, &mut expected_stderr)?;
+                fence = start_fence(test_path, line_number, line, until, included)?;
+                expected_stderr.until = until.map(str::to_owned);
+                append_first_line(&mut expected_stderr.text, rest, exact);
+
+            // expected stdout contains: / excludes: / starts with: / ends with: (and the same for stderr)
+            } else if let Some((stream, kind, rest)) = substring_keywords(config)
+                .iter()
+                .find_map(|(stream, kind, keyword)| Some((*stream, *kind, line.strip_prefix(keyword)?)))
+            {
+                state = TestParseState::ReadingExpectedSubstring;
+                let mut text = String::new();
+                append_first_line(&mut text, rest, exact);
+                expected_substrings.push(ExpectedSubstring {
+                    stream,
+                    kind,
+                    text,
+                    line: line_number,
+                    included,
+                });
+
+            // expected output:
+            } else if let Some((qualifier, until, rest)) = match_block_keyword(line, &config.test_output_prefix) {
+                state = TestParseState::ReadingExpectedOutput;
+                let expected = expected_output.get_or_insert_with(ExpectedStream::default);
+                expected.line = Some(line_number);
+                parse_stream_qualifier(test_path, line_number, qualifier, expected)?;
+                fence = start_fence(test_path, line_number, line, until, included)?;
+                expected.until = until.map(str::to_owned);
+                append_first_line(&mut expected.text, rest, exact);
+
+            // expected exit status:
+            } else if line.starts_with(&config.test_exit_status_prefix) {
+                let status = strip_prefix(line, &config.test_exit_status_prefix).trim();
+                expected_exit_status = Some(status.parse().map_err(|err| {
+                    InnerTestError::ErrorParsingExitStatus(test_path.to_owned(), line_number, status.to_owned(), err)
+                })?);
+                expected_exit_status_line = Some(line_number);
+                exit_status_included = included;
+
+            // expected file "path":
+            } else if line.starts_with(&config.test_expected_file_prefix) {
+                let directive = strip_prefix(line, &config.test_expected_file_prefix);
+                let (path, rest_of_line) = parse_expected_file_path(directive).ok_or_else(|| {
+                    let reason = "expected a file path followed by ':'".to_owned();
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?;
+
+                state = TestParseState::ReadingExpectedFile;
+                let mut text = String::new();
+                append_first_line(&mut text, rest_of_line, exact);
+                expected_files.push(ExpectedFile {
+                    path,
+                    text,
+                    line: line_number,
+                    included,
+                });
+
+            // expected directory "path": golden
+            } else if line.starts_with(&config.test_expected_directory_prefix) {
+                let directive = strip_prefix(line, &config.test_expected_directory_prefix);
+                let parsed = parse_expected_file_path(directive).filter(|(_, golden)| !golden.trim().is_empty());
+                let (path, golden) = parsed.ok_or_else(|| {
+                    let reason = "expected a directory path followed by ':' and the path of its golden tree".to_owned();
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?;
+
+                let test_directory = test_path.parent().unwrap_or_else(|| Path::new(""));
+                expected_directories.push(ExpectedDirectory {
+                    path,
+                    golden: test_directory.join(golden.trim()),
+                    line: line_number,
+                });
+                append_rest(&mut rest, original, included);
+
+            // cwd:
+            } else if line.starts_with(&config.test_cwd_prefix) {
+                let directory = strip_prefix(line, &config.test_cwd_prefix).trim();
+                let test_directory = test_path.parent().unwrap_or_else(|| Path::new(""));
+                cwd = Some(test_directory.join(directory));
+                append_rest(&mut rest, original, included);
+
+            // stdin file:
+            } else if line.starts_with(&config.test_stdin_file_prefix) {
+                let path = strip_prefix(line, &config.test_stdin_file_prefix).trim();
+                let test_directory = test_path.parent().unwrap_or_else(|| Path::new(""));
+                stdin_file = Some(test_directory.join(path));
+                append_rest(&mut rest, original, included);
+
+            // args file:
+            } else if line.starts_with(&config.test_args_file_prefix) {
+                let path = strip_prefix(line, &config.test_args_file_prefix).trim();
+                let test_directory = test_path.parent().unwrap_or_else(|| Path::new(""));
+                args_file = Some(test_directory.join(path));
+                append_rest(&mut rest, original, included);
+
+            // name:
+            } else if line.starts_with(&config.test_name_prefix) {
+                let text = strip_prefix(line, &config.test_name_prefix).trim();
+                name = Some(text.to_owned()).filter(|text| !text.is_empty());
+                append_rest(&mut rest, original, included);
+
+            // description:
+            } else if line.starts_with(&config.test_description_prefix) {
+                let text = strip_prefix(line, &config.test_description_prefix).trim();
+                description = Some(text.to_owned()).filter(|text| !text.is_empty());
+                append_rest(&mut rest, original, included);
+
+            // issue:
+            } else if line.starts_with(&config.test_issue_prefix) {
+                let text = strip_prefix(line, &config.test_issue_prefix).trim();
+                issue = Some(text.to_owned()).filter(|text| !text.is_empty());
+                append_rest(&mut rest, original, included);
+
+            // requires:
+            } else if line.starts_with(&config.test_requires_prefix) {
+                let requirement = strip_prefix(line, &config.test_requires_prefix).trim();
+                let requirement = parse_requirement(requirement).ok_or_else(|| {
+                    let reason = format!("expected a program or 'env <VARIABLE>' but found '{}'", requirement);
+                    InnerTestError::ErrorParsingDirective(
+                        test_path.to_owned(),
+                        line_number,
+                        config.test_requires_prefix.clone(),
+                        reason,
+                    )
+                })?;
+                requirements.push(requirement);
+                append_rest(&mut rest, original, included);
+
+            // serial:
+            } else if line.starts_with(&config.test_serial_prefix) {
+                let names = strip_prefix(line, &config.test_serial_prefix).split(',').map(str::trim);
+                let names: Vec<String> = names.filter(|name| !name.is_empty()).map(ToOwned::to_owned).collect();
+                if names.is_empty() {
+                    serial.push(String::new());
+                }
+                serial.extend(names);
+                append_rest(&mut rest, original, included);
+
+            // sidecar files:
+            // This directive is kept in `rest` so that it is preserved when overwriting the test
+            } else if line.starts_with(&config.test_sidecar_files_prefix) {
+                let value = strip_prefix(line, &config.test_sidecar_files_prefix);
+                sidecar_files = parse_bool(test_path, line_number, &config.test_sidecar_files_prefix, value)?;
+                append_rest(&mut rest, original, included);
+
+            // binary output:
+            } else if line.starts_with(&config.test_binary_output_prefix) {
+                let value = strip_prefix(line, &config.test_binary_output_prefix);
+                binary_output = parse_bool(test_path, line_number, &config.test_binary_output_prefix, value)?;
+                append_rest(&mut rest, original, included);
+
+            // ignore whitespace:
+            } else if line.starts_with(&config.test_ignore_whitespace_prefix) {
+                let value = strip_prefix(line, &config.test_ignore_whitespace_prefix);
+                ignore_whitespace = parse_bool(test_path, line_number, &config.test_ignore_whitespace_prefix, value)?;
+                append_rest(&mut rest, original, included);
+
+            // repeat:
+            } else if line.starts_with(&config.test_repeat_prefix) {
+                let count = strip_prefix(line, &config.test_repeat_prefix).trim();
+                repeat = count.parse().map_err(|_| {
+                    let reason = format!("expected a number of runs but found '{}'", count);
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?;
+                append_rest(&mut rest, original, included);
+
+            // compare:
+            } else if line.starts_with(&config.test_compare_prefix) {
+                let value = strip_prefix(line, &config.test_compare_prefix).trim();
+                comparison = value.parse().map_err(|reason| {
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?;
+                append_rest(&mut rest, original, included);
+
+            // ignore fields:
+            } else if line.starts_with(&config.test_ignore_fields_prefix) {
+                let fields = strip_prefix(line, &config.test_ignore_fields_prefix).split(',');
+                ignored_fields.extend(fields.map(str::trim).filter(|field| !field.is_empty()).map(ToOwned::to_owned));
+                append_rest(&mut rest, original, included);
+
+            // float tolerance:
+            } else if line.starts_with(&config.test_float_tolerance_prefix) {
+                let value = strip_prefix(line, &config.test_float_tolerance_prefix);
+                float_tolerance = Some(parse_float_tolerance(value).ok_or_else(|| {
+                    let reason =
+                        "expected an absolute tolerance, a relative tolerance such as '0.1%', or both".to_owned();
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?);
+                append_rest(&mut rest, original, included);
+
+            // min similarity:
+            } else if line.starts_with(&config.test_min_similarity_prefix) {
+                let value = strip_prefix(line, &config.test_min_similarity_prefix);
+                min_similarity = Some(parse_similarity(value).ok_or_else(|| {
+                    let reason = "expected a ratio between 0 and 1 or a percentage such as '95%'".to_owned();
+                    InnerTestError::ErrorParsingDirective(test_path.to_owned(), line_number, line.to_owned(), reason)
+                })?);
+                append_rest(&mut rest, original, included);
+
+            // pty:
+            } else if line.starts_with(&config.test_pty_prefix) {
+                let value = strip_prefix(line, &config.test_pty_prefix);
+                pty = parse_bool(test_path, line_number, &config.test_pty_prefix, value)?;
+                append_rest(&mut rest, original, included);
+
+            // hermetic:
+            } else if line.starts_with(&config.test_hermetic_prefix) {
+                let value = strip_prefix(line, &config.test_hermetic_prefix);
+                hermetic = parse_bool(test_path, line_number, &config.test_hermetic_prefix, value)?;
+                append_rest(&mut rest, original, included);
+
+            // fixtures:
+            } else if line.starts_with(&config.test_fixtures_prefix) {
+                let paths = strip_prefix(line, &config.test_fixtures_prefix).split(',').map(str::trim);
+                for path in paths.filter(|path| !path.is_empty()).map(PathBuf::from) {
+                    // Fixtures keep their path relative to the test within the copy, which must stay within it
+                    if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+                        let reason = format!("'{}' must be within the directory of the test", path.display());
+                        return Err(InnerTestError::ErrorParsingDirective(
+                            test_path.to_owned(),
+                            line_number,
+                            config.test_fixtures_prefix.clone(),
+                            reason,
+                        ));
+                    }
+                    fixtures.push(path);
+                }
+                append_rest(&mut rest, original, included);
+
+            // check: / check-next: / check-not:
+            } else if let Some((kind, text)) = [
+                (CheckKind::Check, &config.test_check_prefix),
+                (CheckKind::Next, &config.test_check_next_prefix),
+                (CheckKind::Not, &config.test_check_not_prefix),
+            ]
+            .iter()
+            .copied()
+            .find_map(|(kind, keyword)| Some((kind, line.strip_prefix(keyword.as_str())?)))
+            {
+                checks.push(Check {
+                    kind,
+                    text: text.trim().to_owned(),
+                    line: line_number,
+                });
+                append_rest(&mut rest, original, included);
+
+            // send:
+            } else if line.starts_with(&config.test_send_prefix) {
+                let text = strip_prefix(line, &config.test_send_prefix).trim();
+                session.push(SessionStep::Send(text.to_owned()));
+                append_rest(&mut rest, original, included);
+
+            // expect:
+            } else if let Some((qualifier, text)) = match_keyword(line, &config.test_expect_prefix) {
+                let timeout = match qualifier {
+                    Some(qualifier) => Some(parse_timeout(qualifier).ok_or_else(|| {
+                        let reason = format!("expected a timeout such as '(30s)' but found '({})'", qualifier);
+                        InnerTestError::ErrorParsingDirective(
+                            test_path.to_owned(),
+                            line_number,
+                            line.to_owned(),
+                            reason,
+                        )
+                    })?),
+                    None => None,
+                };
+                let text = text.trim().to_owned();
+                session.push(SessionStep::Expect { text, timeout });
+                append_rest(&mut rest, original, included);
+
+            // include:
+            // The lines of the included file were already inserted after this line
+            } else if line.starts_with(&config.test_include_prefix) {
+                append_rest(&mut rest, original, included);
+
+            // exact:
+            // This was already parsed above, but is still kept in `rest`
+            } else if line.starts_with(&config.test_exact_prefix) {
+                append_rest(&mut rest, original, included);
+
+            // environments:
+            } else if line.starts_with(&config.test_environments_prefix) {
+                let names = strip_prefix(line, &config.test_environments_prefix);
+                environments = Some(parse_environments(test_path, line_number, config, names)?);
+                append_rest(&mut rest, original, included);
+
+            // before:
+            } else if line.starts_with(&config.test_before_prefix) {
+                before.push(strip_prefix(line, &config.test_before_prefix).trim().to_owned());
+                append_rest(&mut rest, original, included);
+
+            // after:
+            } else if line.starts_with(&config.test_after_prefix) {
+                after.push(strip_prefix(line, &config.test_after_prefix).trim().to_owned());
+                append_rest(&mut rest, original, included);
+
+            // filter:
+            } else if line.starts_with(&config.test_filter_prefix) {
+                let command = strip_prefix(line, &config.test_filter_prefix).trim();
+                filter = Some(command.to_owned()).filter(|command| !command.is_empty());
+                append_rest(&mut rest, original, included);
+
+            // allow diff:
+            } else if line.starts_with(&config.test_allow_diff_prefix) {
+                let pattern = strip_prefix(line, &config.test_allow_diff_prefix).trim();
+                let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|err| {
+                    InnerTestError::ErrorParsingDirective(
+                        test_path.to_owned(),
+                        line_number,
+                        line.to_owned(),
+                        err.to_string(),
+                    )
+                })?;
+                allowed_diffs.push(regex);
+                append_rest(&mut rest, original, included);
+            } else {
+                let text = strip_prefix(line, &config.test_line_prefix);
+                if let Some(keyword) = suggestions::suggest_keyword(text, &unprefixed_keywords) {
+                    warnings.push((line.to_owned(), keyword.to_owned()));
+                }
+                append_rest(&mut rest, original, included);
+            }
+        } else {
+            // Both expected_stdout and expected_stderr need a blank line at the end,
+            // the order here implicitly skips that newline.
+            if state == TestParseState::Neutral || state == TestParseState::ReadingArgs {
+                append_rest(&mut rest, original, included);
+            }
+            state = TestParseState::Neutral;
+        }
+
+        // Remember the expected output given by included files so it isn't copied into the test when overwriting
+        if included {
+            let streams =
+                std::iter::once(&mut expected_stdout).chain(Some(&mut expected_stderr)).chain(&mut expected_output);
+            for stream in streams {
+                stream.included.clone_from(&stream.text);
+            }
+        }
+    }
+
+    if let Some(fence) = fence {
+        return Err(fence.unterminated(test_path));
+    }
+
+    // Encoded blocks are compared byte for byte against their decoded contents,
+    // while escaped blocks are compared as usual once their escapes are replaced
+    let output = expected_output.as_mut().map(|expected| (&config.test_output_prefix, expected));
+    let streams = std::iter::once((&config.test_stdout_prefix, &mut expected_stdout))
+        .chain(Some((&config.test_stderr_prefix, &mut expected_stderr)))
+        .chain(output);
+    for (keyword, stream) in streams {
+        let line = stream.line.unwrap_or(0);
+        let error = |qualifier: &dyn std::fmt::Display, reason| {
+            let directive = format!("{} ({}):", keyword.trim_end_matches(':'), qualifier);
+            InnerTestError::ErrorParsingDirective(test_path.to_owned(), line, directive, reason)
+        };
+        if let Some(encoding) = stream.encoding {
+            stream.raw = encoding.decode(&stream.text).map_err(|reason| error(&encoding, reason))?;
+            stream.text = String::from_utf8_lossy(&stream.raw).into_owned();
+        } else if stream.escaped {
+            stream.text = unescape(&stream.text).map_err(|reason| error(&"escaped", reason))?;
+            stream.included = unescape(&stream.included).map_err(|reason| error(&"escaped", reason))?;
+        }
+    }
+
+    if binary_output {
+        sidecar_files = true;
+    }
+
+    // The merged output is always written inline
+    if sidecar_files && expected_output.is_none() {
+        let read = |stream| -> InnerTestResult<ExpectedStream> {
+            let raw = read_sidecar_file(test_path, stream)?;
+            let text = String::from_utf8_lossy(&raw).into_owned();
+            Ok(ExpectedStream {
+                text,
+                limit: None,
+                encoding: None,
+                escaped: false,
+                until: None,
+                raw,
+                explicit: true,
+                line: None,
+                included: String::new(),
+            })
+        };
+        expected_stdout = read("stdout")?;
+        expected_stderr = read("stderr")?;
+    }
+
+    // Normalize line endings for windows compatibility
+    if !exact {
+        let normalize = |text: &mut String| *text = config.line_endings.normalize(text).into_owned();
+        let streams =
+            std::iter::once(&mut expected_stdout).chain(Some(&mut expected_stderr)).chain(&mut expected_output);
+        for stream in streams {
+            normalize(&mut stream.text);
+            normalize(&mut stream.included);
+        }
+        for file in &mut expected_files {
+            normalize(&mut file.text);
+        }
+        for substring in &mut expected_substrings {
+            normalize(&mut substring.text);
+        }
+        for step in &mut session {
+            if let SessionStep::Expect { text, .. } = step {
+                normalize(text);
+            }
+        }
+    }
+
+    serial.sort();
+    serial.dedup();
+
+    let command_line_args = args_lines
+        .iter()
+        .map(|args| args.trim_end().strip_suffix('\\').unwrap_or(args))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(Test {
+        path: test_path.to_owned(),
+        name,
+        description,
+        issue,
+        requirements,
+        serial,
+        command_line_args,
+        expected_stdout,
+        expected_stderr,
+        expected_output,
+        expected_exit_status,
+        expected_exit_status_line,
+        expected_files,
+        expected_directories,
+        sidecar_files,
+        binary_output,
+        environments,
+        cwd,
+        stdin_file,
+        args_file,
+        before,
+        after,
+        filter,
+        has_directives,
+        warnings,
+        allowed_diffs,
+        ignore_whitespace,
+        exact,
+        comparison,
+        ignored_fields,
+        float_tolerance,
+        min_similarity,
+        expected_substrings,
+        repeat,
+        pty,
+        hermetic,
+        fixtures,
+        checks,
+        session,
+        includes,
+        args_included,
+        args_lines,
+        exit_status_included,
+        rest,
+    })
+}
@@ -0,0 +1,176 @@
+//! Running each test in its own process group so that any processes it starts are killed along
+//! with it. Otherwise a test which leaves a child running, e.g. a compiler spawned in the
+//! background, would keep spinning after the test finished and hold its output pipes open,
+//! hanging the run until it exits. This is only supported on Unix, other platforms leave any such
+//! processes running.
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Once;
+use std::thread::JoinHandle;
+
+/// The process groups of each running test, or 0 for an unused slot. This is read from a signal
+/// handler, so it can't be behind a lock. Tests beyond the number of slots still run, but aren't
+/// killed if the run is interrupted.
+static GROUPS: [AtomicI32; 256] = [const { AtomicI32::new(0) }; 256];
+
+static INSTALL_HANDLERS: Once = Once::new();
+
+/// Runs the command within a new process group, returning its output once it exits. Any processes
+/// remaining in the group at that point are killed, as are those of every running test if the run
+/// is interrupted. If `combine_output` is set, stderr is written into the same pipe as stdout.
+/// The peak resident memory of the command in bytes is returned alongside its output.
+pub(crate) fn output(mut command: Command, combine_output: bool) -> std::io::Result<(Output, u64)> {
+    isolate(&mut command);
+
+    let (child, stdout, stderr) = if combine_output {
+        let (reader, writer) = std::io::pipe()?;
+        command.stdout(writer.try_clone()?).stderr(writer);
+        let child = command.spawn()?;
+
+        // The command holds our copies of the write end, which must be closed for
+        // reading to finish once the child exits
+        drop(command);
+        (child, read_in_background(reader), None)
+    } else {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = read_in_background(child.stdout.take().unwrap());
+        let stderr = read_in_background(child.stderr.take().unwrap());
+        (child, stdout, Some(stderr))
+    };
+
+    let (status, peak_memory) = wait_and_kill_group(child)?;
+    let output = Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: match stderr {
+            Some(stderr) => join(stderr)?,
+            None => Vec::new(),
+        },
+    };
+    Ok((output, peak_memory))
+}
+
+/// Waits for the child to exit, then kills whatever is left of its process group. The group is
+/// killed even if waiting fails, so that the pipes being read from are always closed. The group
+/// can only be killed after its leader is reaped, see `ProcessGroup::drop` for the race this leaves.
+fn wait_and_kill_group(child: Child) -> std::io::Result<(ExitStatus, u64)> {
+    let group = ProcessGroup::of(&child);
+    let status = wait_measured(&child);
+    drop(group);
+    status
+}
+
+/// Waits for the child to exit with `wait4` rather than `Child::wait`, which also gives the peak
+/// resident memory of the child in bytes
+fn wait_measured(child: &Child) -> std::io::Result<(ExitStatus, u64)> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    // Safety: rusage is a plain C struct, for which all zeroes is a valid value
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    while unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) } == -1 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    // ru_maxrss is in bytes on Apple platforms, and kilobytes elsewhere
+    let peak_memory = usage.ru_maxrss.max(0) as u64;
+    let peak_memory = if cfg!(any(target_os = "macos", target_os = "ios")) {
+        peak_memory
+    } else {
+        peak_memory * 1024
+    };
+    Ok((ExitStatus::from_raw(status), peak_memory))
+}
+
+/// Makes a command start a new process group when spawned, which can then be tracked with `ProcessGroup::of`
+pub(crate) fn isolate(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+/// The process group of a running test. Every process left in the group is killed once this is dropped,
+/// or when the run is interrupted before then.
+pub(crate) struct ProcessGroup {
+    id: libc::pid_t,
+    slot: Option<&'static AtomicI32>,
+}
+
+impl ProcessGroup {
+    /// Tracks the group of a child spawned from a command given to `isolate`
+    pub(crate) fn of(child: &Child) -> ProcessGroup {
+        INSTALL_HANDLERS.call_once(install_handlers);
+
+        // The child was made the leader of its own group, so the group id is its pid
+        let id = child.id() as libc::pid_t;
+        let slot = GROUPS.iter().find(|slot| slot.compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst).is_ok());
+        ProcessGroup { id, slot }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        // Safety: killpg has no memory safety requirements. The leader of the group has usually been
+        // reaped by now, so if every other process in the group has also exited, its id is free to be
+        // reused by a new, unrelated group, which this would then kill. This is unlikely since the group
+        // is killed as soon as its leader exits, but isn't ruled out.
+        unsafe {
+            libc::killpg(self.id, libc::SIGKILL);
+        }
+        if let Some(slot) = self.slot {
+            slot.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+pub(crate) fn read_in_background(mut reader: impl Read + Send + 'static) -> JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok(output)
+    })
+}
+
+pub(crate) fn join(reader: JoinHandle<std::io::Result<Vec<u8>>>) -> std::io::Result<Vec<u8>> {
+    reader
+        .join()
+        .unwrap_or_else(|_| Err(std::io::Error::other("reading the output of the test panicked")))
+}
+
+/// Kills the process group of every running test when the run is interrupted or terminated,
+/// since they no longer share our process group and so aren't sent the signal themselves.
+/// Signals which already have a handler, e.g. one installed by a program embedding goldentests,
+/// are left alone.
+fn install_handlers() {
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP].iter().copied() {
+        // Safety: the handler only calls async-signal-safe functions
+        unsafe {
+            let handler = kill_groups_and_exit as extern "C" fn(libc::c_int);
+            let previous = libc::signal(signal, handler as libc::sighandler_t);
+            if previous != libc::SIG_DFL {
+                libc::signal(signal, previous);
+            }
+        }
+    }
+}
+
+extern "C" fn kill_groups_and_exit(signal: libc::c_int) {
+    for slot in &GROUPS {
+        let group = slot.load(Ordering::SeqCst);
+        if group != 0 {
+            unsafe {
+                libc::killpg(group, libc::SIGKILL);
+            }
+        }
+    }
+
+    // Exit as if the signal had never been handled
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
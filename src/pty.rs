@@ -0,0 +1,122 @@
+//! Running a test under a pseudo-terminal, so that programs which check `isatty` take the same
+//! code path they would when ran by hand, e.g. to print colors or progress bars. See `TestConfig::pty`.
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output, Stdio};
+
+/// Runs the command with a new pseudo-terminal as its stdin, stdout, and stderr, returning everything
+/// written to the terminal as stdout once it exits. The terminal is set up so that output matches that
+/// of a pipe as closely as possible: input isn't echoed and newlines aren't translated into "\r\n".
+/// The contents of `stdin`, if any, are typed into the terminal followed by end of input.
+pub(crate) fn output(mut command: Command, stdin: Option<Vec<u8>>) -> std::io::Result<Output> {
+    let (master, slave) = open()?;
+    command.stdin(Stdio::from(slave.try_clone()?));
+    command.stdout(Stdio::from(slave.try_clone()?));
+    command.stderr(Stdio::from(slave));
+
+    // Make the terminal the controlling terminal of the child within a new session. The session is
+    // also a new process group, so this replaces `process_group::isolate`.
+    // Safety: setsid and ioctl are async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()?;
+    let group = crate::process_group::ProcessGroup::of(&child);
+
+    // The command holds our copies of the terminal, which must be closed for
+    // reading to finish once the child exits
+    drop(command);
+
+    let mut master = File::from(master);
+    let mut writer = master.try_clone()?;
+    // This thread is never joined, since writing blocks forever if the child exits without reading everything
+    std::thread::spawn(move || {
+        // In canonical mode, end of input only ends reading once the current line is empty
+        let mut input = stdin.unwrap_or_default();
+        if !input.is_empty() && !input.ends_with(b"\n") {
+            input.push(EOF);
+        }
+        input.push(EOF);
+        let _ = writer.write_all(&input);
+    });
+
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            match master.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => output.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                // Reading fails with EIO rather than returning 0 once every copy of the terminal is closed
+                Err(error) if error.raw_os_error() == Some(libc::EIO) => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(output)
+    });
+
+    let status = child.wait();
+    drop(group);
+    let status = status?;
+
+    let stdout = reader
+        .join()
+        .unwrap_or_else(|_| Err(std::io::Error::other("reading the output of the test panicked")))?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
+/// The character which ends input on a terminal in canonical mode, ctrl-D
+const EOF: u8 = 4;
+
+/// Opens a new pseudo-terminal with an 80x24 window, returning its master and slave ends
+fn open() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut master = -1;
+    let mut slave = -1;
+    let mut size = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    // Safety: each pointer is valid for the duration of the call, and each fd opened is owned below
+    unsafe {
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::addr_of_mut!(size),
+        ) == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        let master = OwnedFd::from_raw_fd(master);
+        let slave = OwnedFd::from_raw_fd(slave);
+
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        termios.c_lflag &= !(libc::ECHO | libc::ECHONL);
+        termios.c_oflag &= !libc::ONLCR;
+        if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((master, slave))
+    }
+}
@@ -0,0 +1,100 @@
+//! Comparing how long each test took against a recorded baseline. See `TestConfig::duration_regression`.
+use crate::colors::Colorize;
+use crate::config::TestConfig;
+use crate::error::InnerTestError;
+use crate::runner::FileResults;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Maps the path of each test to its baseline duration in seconds
+type Baseline = BTreeMap<PathBuf, f64>;
+
+/// Tests faster than this are never considered to regress, since their
+/// durations are dominated by noise such as process startup
+const MIN_DURATION: Duration = Duration::from_millis(50);
+
+/// Reads a baseline file, treating it as empty if it doesn't exist yet
+fn read_baseline(path: &Path) -> Baseline {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+impl TestConfig {
+    /// Reports each test which took longer than `duration_regression` allows compared to its baseline.
+    /// If regressions fail, the first passing result of the test is replaced with the regression.
+    /// Otherwise the regression is added as a warning. Tests without a baseline are skipped.
+    pub(crate) fn check_duration_regressions(&self, files: &mut [FileResults]) {
+        let regression = match &self.duration_regression {
+            Some(regression) => regression,
+            None => return,
+        };
+
+        let baseline = read_baseline(&regression.baseline);
+        for file in files {
+            let expected = match baseline.get(&file.path).and_then(|secs| Duration::try_from_secs_f64(*secs).ok()) {
+                Some(expected) => expected,
+                None => continue,
+            };
+
+            let limit = expected.mul_f64(1.0 + regression.threshold / 100.0).max(MIN_DURATION);
+            if file.duration <= limit {
+                continue;
+            }
+
+            let error = InnerTestError::DurationRegressed {
+                path: file.path.clone(),
+                baseline: expected,
+                duration: file.duration,
+                fail: regression.fail,
+            };
+
+            if !regression.fail {
+                file.results.push((None, Err(error)));
+            } else if let Some((_, result)) = file.results.iter_mut().find(|(_, result)| result.is_ok()) {
+                *result = Err(error);
+            }
+        }
+    }
+
+    /// Writes the duration of each of the given tests to the baseline file of `duration_regression`,
+    /// keeping the baselines of any tests which weren't ran this time.
+    pub(crate) fn record_baseline(&self, files: &[FileResults]) {
+        let path = match &self.duration_regression {
+            Some(regression) => &regression.baseline,
+            None => {
+                let warning = "warning: no baseline was recorded since no duration_regression baseline path is set";
+                eprintln!("{}", warning.bright_yellow());
+                return;
+            }
+        };
+
+        let mut baseline = read_baseline(path);
+        for file in files {
+            baseline.insert(file.path.clone(), file.duration.as_secs_f64());
+        }
+
+        let write = || -> std::io::Result<()> {
+            if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::write(path, serde_json::to_string_pretty(&baseline)?)
+        };
+
+        match write() {
+            Ok(()) => summary!(
+                self,
+                "recorded the durations of {} tests to {}\n",
+                files.len(),
+                path.display()
+            ),
+            Err(error) => {
+                let warning = format!("warning: failed to write {}: {}", path.display(), error);
+                eprintln!("{}", warning.as_str().bright_yellow());
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Reports of a test run written to a file, e.g. with `--report html=report.html`
+use crate::colors::{strip_colors, Colorize};
+use crate::config::TestConfig;
+use crate::error::{Diff, InnerTestError, Mismatch};
+use crate::runner::{is_known_failure, FileResults, InnerTestResult};
+
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A report to write after running all tests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A single self-contained HTML file with the results of each test and collapsible diffs of each failure
+    Html,
+
+    /// A GitHub-flavored Markdown table of results followed by a fenced diff of each failure,
+    /// suitable for writing to `$GITHUB_STEP_SUMMARY`
+    Markdown,
+}
+
+impl std::str::FromStr for Report {
+    type Err = String;
+
+    /// Parses a report given as `<format>=<path>`, e.g. `html=report.html`
+    fn from_str(s: &str) -> Result<Report, String> {
+        let (format, path) = s.split_once('=').ok_or_else(|| format!("expected FORMAT=PATH but found '{}'", s))?;
+        let format = match format {
+            "html" => ReportFormat::Html,
+            "markdown" | "md" => ReportFormat::Markdown,
+            other => {
+                return Err(format!(
+                    "unknown report format '{}', expected 'html' or 'markdown'",
+                    other
+                ))
+            }
+        };
+        Ok(Report {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// A short description of the outcome of a single run of a test, or None for warnings which aren't runs
+fn outcome(result: &InnerTestResult<()>) -> Option<&'static str> {
+    match result {
+        Ok(()) => Some("passed"),
+        Err(InnerTestError::TestUpdated { .. }) => Some("updated"),
+        Err(InnerTestError::AllowedDifferences { .. }) => Some("passed with allowed differences"),
+        Err(InnerTestError::NeedsCanonicalization(_)) => Some("passed, needs canonicalization"),
+        Err(InnerTestError::UnknownDirective(..)) => None,
+        Err(InnerTestError::Quarantined(_)) => Some("failed, quarantined"),
+        Err(InnerTestError::Skipped(..)) => Some("skipped"),
+        Err(_) => Some("failed"),
+    }
+}
+
+/// A single run of a test file within one of its environments
+type Run<'a> = (&'a FileResults, Option<&'a str>, &'a InnerTestResult<()>);
+
+fn runs(files: &[FileResults]) -> impl Iterator<Item = Run<'_>> {
+    files
+        .iter()
+        .flat_map(|file| file.results.iter().map(move |(environment, result)| (file, environment.as_deref(), result)))
+}
+
+/// The total number of tests ran and the number of those which failed
+fn count(files: &[FileResults]) -> (usize, usize) {
+    let ran = |result: &InnerTestResult<()>| !matches!(outcome(result), None | Some("skipped"));
+    let tests = runs(files).filter(|(.., result)| ran(result)).count();
+    let failing = runs(files).filter(|(.., result)| matches!(result, Err(error) if error.is_failure())).count();
+    (tests, failing)
+}
+
+/// Each message explaining the given result along with the diff shown beneath it, if any
+fn details(result: &InnerTestResult<()>) -> Vec<(String, Option<&Diff>)> {
+    match result {
+        Ok(()) => vec![],
+        Err(error) if !error.mismatches().is_empty() => error
+            .mismatches()
+            .iter()
+            .map(|mismatch: &Mismatch| (mismatch.message.trim().to_owned(), mismatch.diff.as_ref()))
+            .collect(),
+        Err(error) => vec![(strip_colors(&error.to_string()).trim().to_owned(), None)],
+    }
+}
+
+/// The `issue:` of the given test if the result is a failure it should be shown alongside
+fn known_issue<'a>(file: &'a FileResults, result: &InnerTestResult<()>) -> Option<&'a str> {
+    match result {
+        Err(error) if is_known_failure(error) => file.issue.as_deref(),
+        _ => None,
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&#39;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { text-align: left; padding: 0.2em 1em; border-bottom: 1px solid #ddd; }
+td.duration { text-align: right; }
+.passed { color: #1a7f37; }
+.failed { color: #cf222e; }
+.updated, .warning { color: #9a6700; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+.delete { color: #cf222e; background: #ffebe9; }
+.insert { color: #1a7f37; background: #dafbe1; }
+summary { cursor: pointer; }
+.description { font-style: italic; }
+";
+
+/// The class an outcome is styled with
+fn outcome_class(outcome: &str) -> &'static str {
+    match outcome {
+        "failed" => "failed",
+        "updated" => "updated",
+        "skipped" => "warning",
+        _ => "passed",
+    }
+}
+
+fn html_diff(html: &mut String, diff: &Diff) {
+    html.push_str("<pre class=\"diff\">");
+    let differences = TextDiff::from_lines(&diff.expected, &diff.actual);
+    for change in differences.iter_all_changes() {
+        let class = match change.tag() {
+            ChangeTag::Delete => "delete",
+            ChangeTag::Insert => "insert",
+            ChangeTag::Equal => "equal",
+        };
+        let line_number = change.new_index().map_or_else(String::new, |index| (index + 1).to_string());
+        let line = change.value().trim_end_matches('\n');
+        let _ = writeln!(
+            html,
+            "<span class=\"{}\">{:>3}| {}</span>",
+            class,
+            line_number,
+            escape_html(line)
+        );
+    }
+    html.push_str("</pre>\n");
+}
+
+fn render_html(files: &[FileResults], total_time: Duration) -> String {
+    let (tests, failing) = count(files);
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Golden test report</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", HTML_STYLE);
+    html.push_str("<h1>Golden test report</h1>\n");
+    let _ = writeln!(
+        html,
+        "<p>Ran {} tests with <span class=\"passed\">{} passing</span> and <span class=\"failed\">{} failing</span> in {:.2}s</p>",
+        tests,
+        tests - failing,
+        failing,
+        total_time.as_secs_f64()
+    );
+
+    html.push_str("<table>\n<tr><th>Test</th><th>Environment</th><th>Result</th><th>Duration</th></tr>\n");
+    for (file, environment, result) in runs(files) {
+        if let Some(outcome) = outcome(result) {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td class=\"duration\">{:.2}s</td></tr>",
+                escape_html(&file.title()),
+                escape_html(environment.unwrap_or_default()),
+                outcome_class(outcome),
+                outcome,
+                file.duration.as_secs_f64()
+            );
+        }
+    }
+    html.push_str("</table>\n");
+
+    let mut failures = runs(files).filter(|(.., result)| result.is_err()).peekable();
+    if failures.peek().is_some() {
+        html.push_str("<h2>Details</h2>\n");
+    }
+
+    for (file, environment, result) in failures {
+        let (class, outcome) = match outcome(result) {
+            Some(outcome) => (outcome_class(outcome), outcome),
+            None => ("warning", "warning"),
+        };
+        let environment = environment.map_or_else(String::new, |environment| format!(" [{}]", environment));
+        let open = if class == "failed" { " open" } else { "" };
+        let _ = writeln!(
+            html,
+            "<details{}><summary><span class=\"{}\">{}</span> {}{}</summary>",
+            open,
+            class,
+            outcome,
+            escape_html(&file.title()),
+            escape_html(&environment)
+        );
+
+        if let Some(description) = &file.description {
+            let _ = writeln!(html, "<p class=\"description\">{}</p>", escape_html(description));
+        }
+
+        if let Some(issue) = known_issue(file, result) {
+            let issue = escape_html(issue);
+            if issue.starts_with("http://") || issue.starts_with("https://") {
+                let _ = writeln!(html, "<p>Issue: <a href=\"{}\">{}</a></p>", issue, issue);
+            } else {
+                let _ = writeln!(html, "<p>Issue: {}</p>", issue);
+            }
+        }
+
+        for (message, diff) in details(result) {
+            match diff {
+                Some(diff) => {
+                    let _ = writeln!(html, "<p>{}</p>", escape_html(&message));
+                    html_diff(&mut html, diff);
+                }
+                None => {
+                    let _ = writeln!(html, "<pre>{}</pre>", escape_html(&message));
+                }
+            }
+        }
+        html.push_str("</details>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Escapes text within a cell of a Markdown table
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// A code fence longer than any run of backticks within the given text, so the text can't end it early
+fn code_fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn markdown_diff(markdown: &mut String, diff: &Diff) {
+    let differences = TextDiff::from_lines(&diff.expected, &diff.actual);
+    let mut lines = String::new();
+    for change in differences.iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+            ChangeTag::Equal => ' ',
+        };
+        let _ = writeln!(lines, "{}{}", sign, change.value().trim_end_matches('\n'));
+    }
+
+    let fence = code_fence(&lines);
+    let _ = write!(markdown, "{}diff\n{}{}\n\n", fence, lines, fence);
+}
+
+fn render_markdown(files: &[FileResults], total_time: Duration) -> String {
+    let (tests, failing) = count(files);
+
+    let mut markdown = String::new();
+    markdown.push_str("## Golden test report\n\n");
+    let _ = writeln!(
+        markdown,
+        "Ran {} tests with {} passing and {} failing in {:.2}s\n",
+        tests,
+        tests - failing,
+        failing,
+        total_time.as_secs_f64()
+    );
+
+    markdown.push_str("| Test | Environment | Result | Duration |\n| --- | --- | --- | ---: |\n");
+    for (file, environment, result) in runs(files) {
+        if let Some(outcome) = outcome(result) {
+            let icon = match outcome_class(outcome) {
+                "failed" => ":x:",
+                "updated" => ":pencil2:",
+                "warning" => ":fast_forward:",
+                _ => ":white_check_mark:",
+            };
+            let _ = writeln!(
+                markdown,
+                "| `{}` | {} | {} {} | {:.2}s |",
+                escape_markdown_cell(&file.title()),
+                escape_markdown_cell(environment.unwrap_or_default()),
+                icon,
+                outcome,
+                file.duration.as_secs_f64()
+            );
+        }
+    }
+
+    let mut failures = runs(files).filter(|(.., result)| result.is_err()).peekable();
+    if failures.peek().is_some() {
+        markdown.push_str("\n### Details\n\n");
+    }
+
+    for (file, environment, result) in failures {
+        let outcome = outcome(result).unwrap_or("warning");
+        let environment = environment.map_or_else(String::new, |environment| format!(" [{}]", environment));
+        let _ = writeln!(
+            markdown,
+            "<details><summary>{}: <code>{}</code>{}</summary>\n",
+            outcome,
+            escape_html(&file.title()),
+            escape_html(&environment)
+        );
+
+        if let Some(description) = &file.description {
+            let _ = writeln!(markdown, "_{}_\n", description);
+        }
+
+        if let Some(issue) = known_issue(file, result) {
+            let _ = writeln!(markdown, "Issue: {}\n", issue);
+        }
+
+        for (message, diff) in details(result) {
+            match diff {
+                Some(diff) => {
+                    let _ = writeln!(markdown, "{}\n", message);
+                    markdown_diff(&mut markdown, diff);
+                }
+                None => {
+                    let fence = code_fence(&message);
+                    let _ = write!(markdown, "{}\n{}\n{}\n\n", fence, message, fence);
+                }
+            }
+        }
+        markdown.push_str("</details>\n\n");
+    }
+    markdown
+}
+
+impl TestConfig {
+    /// Writes each report in `reports`, warning about any which can't be written
+    pub(crate) fn write_reports(&self, files: &[FileResults], total_time: Duration) {
+        for report in &self.reports {
+            let contents = match report.format {
+                ReportFormat::Html => render_html(files, total_time),
+                ReportFormat::Markdown => render_markdown(files, total_time),
+            };
+
+            if let Err(error) = std::fs::write(&report.path, contents) {
+                let warning = format!("warning: failed to write {}: {}", report.path.display(), error);
+                eprintln!("{}", warning.as_str().bright_yellow());
+            }
+        }
+    }
+}
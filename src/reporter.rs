@@ -0,0 +1,144 @@
+//! Callbacks invoked as tests run, so embedders can plug in their own progress UI, logging, or
+//! metrics. See `TestConfig::reporters`.
+use crate::colors::strip_colors;
+use crate::error::InnerTestError;
+
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Receives events as tests are ran. Each method does nothing by default so implementors only need
+/// to override the events they are interested in. Tests may be ran in parallel, so `on_test_start`
+/// and `on_test_complete` can be called from multiple threads at once.
+pub trait Reporter: Send + Sync {
+    /// Called before running the test at the given path
+    fn on_test_start(&self, _path: &Path) {}
+
+    /// Called after each run of a test within an environment (or once if there are no environments)
+    /// with its result. Warnings about the test, such as a mistyped keyword, are given as results as
+    /// well. `duration` is how long the whole test file took to run within each environment.
+    fn on_test_complete(
+        &self,
+        _path: &Path,
+        _environment: Option<&str>,
+        _result: &Result<(), InnerTestError>,
+        _duration: Duration,
+    ) {
+    }
+
+    /// Called once after every test has been ran and the results have been printed
+    fn on_run_complete(&self, _summary: &RunSummary) {}
+}
+
+/// The totals of a finished run of tests
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RunSummary {
+    pub total: usize,
+    pub passing: usize,
+    pub failing: usize,
+
+    /// The number of tests whose expected output was overwritten. These are counted as passing.
+    pub updated: usize,
+
+    /// The time taken to run every test
+    pub duration: Duration,
+}
+
+/// Writes each event to stdout as a single line of JSON, for tools such as editor plugins to show the
+/// progress and results of a run. Enabled by `MessageFormat::Json`. Each line is one of:
+///
+/// ```json
+/// {"type": "test", "event": "started", "path": "tests/add.ant"}
+/// {"type": "test", "event": "finished", "path": "tests/add.ant", "environment": null, "outcome": "failed",
+///  "duration": 0.012, "message": "...", "mismatches": [{"message": "...", "line": 4}]}
+/// {"type": "run", "event": "finished", "total": 10, "passing": 9, "failing": 1, "updated": 0, "duration": 0.5}
+/// ```
+///
+/// The `outcome` of a test is one of "passed", "failed", "updated", "allowed", "quarantined", "skipped",
+/// "notice", or "warning". `message` is the plain text of the failure or warning, and is null for passing tests.
+pub struct JsonMessages;
+
+impl JsonMessages {
+    fn write(&self, message: Value) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", message).and_then(|()| stdout.flush());
+    }
+}
+
+impl Reporter for JsonMessages {
+    fn on_test_start(&self, path: &Path) {
+        self.write(test_started(path));
+    }
+
+    fn on_test_complete(
+        &self,
+        path: &Path,
+        environment: Option<&str>,
+        result: &Result<(), InnerTestError>,
+        duration: Duration,
+    ) {
+        self.write(test_finished(path, environment, result, duration));
+    }
+
+    fn on_run_complete(&self, summary: &RunSummary) {
+        self.write(run_finished(summary));
+    }
+}
+
+/// The JSON message for a test which started, see `JsonMessages`
+pub(crate) fn test_started(path: &Path) -> Value {
+    json!({ "type": "test", "event": "started", "path": path })
+}
+
+/// The JSON message for a test which finished within the given environment, see `JsonMessages`
+pub(crate) fn test_finished(
+    path: &Path,
+    environment: Option<&str>,
+    result: &Result<(), InnerTestError>,
+    duration: Duration,
+) -> Value {
+    let outcome = match result {
+        Ok(()) => "passed",
+        Err(InnerTestError::TestUpdated { .. }) => "updated",
+        Err(InnerTestError::AllowedDifferences { .. }) => "allowed",
+        Err(InnerTestError::Quarantined(_)) => "quarantined",
+        Err(InnerTestError::Skipped(..)) => "skipped",
+        Err(InnerTestError::NeedsCanonicalization(_)) => "notice",
+        Err(error) if !error.is_failure() => "warning",
+        Err(_) => "failed",
+    };
+    let message = result.as_ref().err().map(|error| strip_colors(&error.to_string()).trim().to_owned());
+    let mismatches: Vec<Value> = match result {
+        Ok(()) => Vec::new(),
+        Err(error) => error
+            .mismatches()
+            .iter()
+            .map(|mismatch| json!({ "message": mismatch.message.trim(), "line": mismatch.line }))
+            .collect(),
+    };
+
+    json!({
+        "type": "test",
+        "event": "finished",
+        "path": path,
+        "environment": environment,
+        "outcome": outcome,
+        "duration": duration.as_secs_f64(),
+        "message": message,
+        "mismatches": mismatches,
+    })
+}
+
+/// The JSON message for the end of a run, see `JsonMessages`
+pub(crate) fn run_finished(summary: &RunSummary) -> Value {
+    json!({
+        "type": "run",
+        "event": "finished",
+        "total": summary.total,
+        "passing": summary.passing,
+        "failing": summary.failing,
+        "updated": summary.updated,
+        "duration": summary.duration.as_secs_f64(),
+    })
+}
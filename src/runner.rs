@@ -1,345 +1,2383 @@
-use crate::config::TestConfig;
-use crate::diff_printer::DiffPrinter;
-use crate::error::{InnerTestError, TestError, TestResult};
+use crate::artifacts::{Failure, SavedOutput};
+use crate::command_runner::Invocation;
+use crate::config::{Comparison, Environment, MessageFormat, TestConfig, TestOrder};
+use crate::diff_printer::hex_dump;
+use crate::error::{Diff, InnerTestError, Mismatch, TestError, TestResult};
+use crate::incremental::Incremental;
+use crate::lint::lint_test;
+use crate::parser::{
+    append_line, escape, parse_test, sidecar_path, CheckKind, Encoding, ExpectedDirectory, ExpectedFile,
+    ExpectedStream, LineLimit, Requirement, SubstringKind, Test,
+};
+use crate::reporter::{JsonMessages, Reporter, RunSummary};
+use crate::session::run_session;
+use crate::shell;
+use crate::state::Results;
+use crate::structured::{self, Difference};
+use crate::template::{TempDir, Template};
+use crate::terminal;
+use crate::tolerance::apply_tolerance;
 
-use colored::Colorize;
-use similar::TextDiff;
+use crate::colors::Colorize;
+use regex::Regex;
+use similar::{ChangeTag, TextDiff};
 
 #[cfg(feature = "parallel")]
-use rayon::iter::IntoParallelIterator;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+#[cfg(feature = "progress-bar")]
+use indicatif::{ProgressBar, ProgressStyle};
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+pub(crate) type InnerTestResult<T> = Result<T, InnerTestError>;
+
+/// The result of each run of a test, paired with the name of the environment it was ran within, if any
+pub(crate) type RunResults = Vec<(Option<String>, InnerTestResult<()>)>;
+
+/// The message and diffed text of each mismatch of a failed test, ignoring the test's path
+type FailureKey<'a> = Vec<(&'a str, Option<(&'a str, &'a str)>)>;
+
+/// The results of running a single test file within each of its environments
+pub(crate) struct FileResults {
+    pub path: PathBuf,
+
+    /// The `name:`, `description:`, and `issue:` of the test, if it could be parsed
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub issue: Option<String>,
+
+    pub duration: Duration,
+    pub results: RunResults,
+}
+
+impl FileResults {
+    /// The name of the test followed by its path, or only its path if it has no name
+    pub(crate) fn title(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} — {}", name, self.path.display()),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    pub(crate) fn passed(&self) -> bool {
+        self.results.iter().all(|(_, result)| !matches!(result, Err(error) if error.is_failure()))
+    }
+}
+
+/// The locks named by the `serial:` directive of each test, created when they're first used
+#[derive(Default)]
+struct SerialLocks(Mutex<HashMap<String, Arc<Mutex<()>>>>);
+
+impl SerialLocks {
+    /// The lock with the given name, shared by every test naming it
+    fn get(&self, name: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        locks.entry(name.to_owned()).or_default().clone()
+    }
+}
+
+/// The output of a single run of a test, normalized to the form it is compared in
+struct ActualOutput {
+    output: Output,
+    stdout: String,
+    stderr: String,
+
+    /// The contents of each file in `Test::expected_files`, or None if the file does not exist
+    files: Vec<Option<String>>,
+
+    /// The raw contents of each directory in `Test::expected_directories`, or None if the directory does not exist
+    directories: Vec<Option<DirectoryTree>>,
+}
+
+/// The contents of each file within a directory, keyed by its path relative to the directory
+type DirectoryTree = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Reads every file within the given directory, or returns None if it isn't a readable directory
+fn read_directory_tree(directory: &Path) -> Option<DirectoryTree> {
+    let mut tree = DirectoryTree::new();
+    let mut directories = vec![PathBuf::new()];
+    while let Some(relative) = directories.pop() {
+        for entry in std::fs::read_dir(directory.join(&relative)).ok()? {
+            let entry = entry.ok()?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type().ok()?.is_dir() {
+                directories.push(path);
+            } else {
+                tree.insert(path, std::fs::read(entry.path()).ok()?);
+            }
+        }
+    }
+    Some(tree)
+}
+
+/// Replaces the given directory with one containing exactly the files of the given tree
+fn write_directory_tree(directory: &Path, tree: &DirectoryTree) -> std::io::Result<()> {
+    if directory.exists() {
+        std::fs::remove_dir_all(directory)?;
+    }
+    std::fs::create_dir_all(directory)?;
+    for (path, contents) in tree {
+        let path = directory.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// Compares the directory produced by a test against its golden tree, file by file
+fn check_directory(
+    config: &TestConfig,
+    expected: &ExpectedDirectory,
+    produced: &DirectoryTree,
+    test: &Test,
+    errors: &mut Vec<Mismatch>,
+    allowed: &mut Vec<Mismatch>,
+) {
+    let golden = read_directory_tree(&expected.golden).unwrap_or_default();
+    let paths: BTreeSet<&PathBuf> = golden.keys().chain(produced.keys()).collect();
+    for relative in paths {
+        let path = Path::new(&expected.path).join(relative);
+        let message = match (golden.get(relative), produced.get(relative)) {
+            (Some(golden), Some(produced)) => {
+                if std::str::from_utf8(golden).is_ok() && std::str::from_utf8(produced).is_ok() {
+                    let name = format!("file \"{}\"", path.display());
+                    let expected = ExpectedStream {
+                        text: config.normalize_output(golden, test.exact),
+                        line: Some(expected.line),
+                        ..Default::default()
+                    };
+                    let produced = config.normalize_output(produced, test.exact);
+                    check_for_differences_in_stream(config, &name, &produced, &expected, test, errors, allowed);
+                    continue;
+                } else if golden == produced {
+                    continue;
+                }
+                format!("Binary file \"{}\" differs from the golden tree\n", path.display())
+            }
+            (Some(_), None) => format!("Expected file \"{}\" to exist after running the test\n", path.display()),
+            (None, Some(_)) => format!(
+                "Unexpected file \"{}\" which isn't in the golden tree {}\n",
+                path.display(),
+                expected.golden.display()
+            ),
+            (None, None) => unreachable!("each path is within one of the trees"),
+        };
+        errors.push(Mismatch::from(message).at_line(Some(expected.line)));
+    }
+}
+
+/// Checks that the given program exists and is executable, searching the `PATH` for it if it is
+/// a bare name, e.g. `python`
+fn check_executable(program: &Path) -> Result<(), String> {
+    let candidates: Vec<PathBuf> = if program.components().count() > 1 {
+        vec![program.to_owned()]
+    } else {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let directories = std::env::split_paths(&path).map(|directory| directory.join(program));
+        std::iter::once(program.to_owned()).chain(directories).collect()
+    };
+
+    let mut found = false;
+    for candidate in candidates {
+        let candidate = if cfg!(windows) && candidate.extension().is_none() {
+            candidate.with_extension("exe")
+        } else {
+            candidate
+        };
+
+        if let Ok(metadata) = std::fs::metadata(&candidate) {
+            found = true;
+            if metadata.is_file() && is_executable(&metadata) {
+                return Ok(());
+            }
+        }
+    }
+
+    Err(if found {
+        "not executable"
+    } else {
+        "no such file, or not found in PATH"
+    }
+    .to_owned())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Warnings are reported alongside the results of each test but aren't tests themselves
+pub(crate) fn is_warning(result: &InnerTestResult<()>) -> bool {
+    matches!(
+        result,
+        Err(InnerTestError::UnknownDirective(..) | InnerTestError::DurationRegressed { fail: false, .. })
+    )
+}
+
+/// Copies the test and each of its fixtures into a new temporary directory, keeping the path of
+/// each fixture relative to the test
+fn copy_to_hermetic_dir(test: &Test) -> InnerTestResult<TempDir> {
+    let dir = TempDir::new().map_err(|err| InnerTestError::IoError(test.path.clone(), err))?;
+    let test_directory = test.path.parent().unwrap_or_else(|| Path::new(""));
+    let file_name = test.path.file_name().unwrap_or_default();
+
+    let copies = std::iter::once((test.path.clone(), dir.path().join(file_name)));
+    let fixtures = test.fixtures.iter().map(|fixture| (test_directory.join(fixture), dir.path().join(fixture)));
+    for (from, to) in copies.chain(fixtures) {
+        copy_recursively(&from, &to).map_err(|err| InnerTestError::IoError(from, err))?;
+    }
+    Ok(dir)
+}
+
+/// True if the given path, relative to the directory a test is ran in, names something within that directory
+fn is_within_run_directory(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().all(|component| matches!(component, std::path::Component::Normal(_)))
+}
+
+/// Copies the given file, or directory along with everything within it, creating any missing parent directories
+fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Describes the first `requires:` condition of the test which isn't met within the given environment, if any
+fn unmet_requirement(test: &Test, environment: Option<&Environment>) -> Option<String> {
+    test.requirements.iter().find_map(|requirement| match requirement {
+        Requirement::Program(program) => check_executable(Path::new(program))
+            .err()
+            .map(|_| format!("`{}`, which isn't on the PATH", program)),
+        Requirement::EnvVar(variable) => {
+            let in_environment =
+                environment.is_some_and(|environment| environment.env.iter().any(|(name, _)| name == variable));
+            let is_set = in_environment || std::env::var_os(variable).is_some();
+            (!is_set).then(|| format!("the {} environment variable, which isn't set", variable))
+        }
+    })
+}
+
+/// True if the given error is a failure, even one which is quarantined, so that the `issue:` of its
+/// test should be shown alongside it
+pub(crate) fn is_known_failure(error: &InnerTestError) -> bool {
+    error.is_failure() || matches!(error, InnerTestError::Quarantined(_))
+}
+
+/// Lists each test which newly fails, newly passes, or is new compared to the last run. Tests
+/// which weren't ran this time, e.g. because of `--incremental`, aren't mentioned.
+fn report_comparison(config: &TestConfig, previous: &Results, files: &[FileResults]) {
+    let mut newly_failing = Vec::new();
+    let mut newly_passing = Vec::new();
+    let mut new_tests = Vec::new();
+    for file in files {
+        match (previous.get(&file.path), file.passed()) {
+            (None, _) => new_tests.push(&file.path),
+            (Some(true), false) => newly_failing.push(&file.path),
+            (Some(false), true) => newly_passing.push(&file.path),
+            _ => (),
+        }
+    }
+
+    if newly_failing.is_empty() && newly_passing.is_empty() && new_tests.is_empty() {
+        summary!(config, "no changes compared to the last run\n");
+        return;
+    }
+
+    summary!(config, "compared to the last run:");
+    let groups = [
+        ("newly failing", newly_failing, "-".red()),
+        ("newly passing", newly_passing, "+".green()),
+        ("new", new_tests, "*".cyan()),
+    ];
+    for (name, mut tests, marker) in groups {
+        if !tests.is_empty() {
+            tests.sort();
+            summary!(config, "  {} {}:", tests.len(), name);
+            for test in tests {
+                summary!(config, "    {} {}", marker, test.display());
+            }
+        }
+    }
+    summary!(config);
+}
+
+/// Reads the arguments within the file given by `args file:`
+fn read_args_file(path: &Path) -> InnerTestResult<Vec<String>> {
+    let contents = std::fs::read_to_string(path).map_err(|err| InnerTestError::IoError(path.to_owned(), err))?;
+    shlex::split(&contents).ok_or_else(|| InnerTestError::ErrorParsingArgs(path.to_owned(), contents.trim().to_owned()))
+}
+
+/// Finds each test within `directory`, skipping files and directories matching any of the `ignore`
+/// patterns. See `TestConfig::ignore_patterns`.
+pub(crate) fn find_tests(directory: &Path, ignore: &[glob::Pattern]) -> (Vec<PathBuf>, Vec<InnerTestError>) {
+    find_tests_within(directory, directory, ignore)
+}
+
+fn find_tests_within(root: &Path, directory: &Path, ignore: &[glob::Pattern]) -> (Vec<PathBuf>, Vec<InnerTestError>) {
+    let mut tests = vec![];
+    let mut errors = vec![];
+
+    let read_dir = match std::fs::read_dir(directory) {
+        Ok(dir) => dir,
+        Err(err) => return (tests, vec![InnerTestError::IoError(directory.to_owned(), err)]),
+    };
+
+    for entry in read_dir {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => {
+                errors.push(InnerTestError::IoError(directory.to_owned(), err));
+                continue;
+            }
+        };
+
+        if is_ignored(root, &path, ignore) {
+            continue;
+        } else if path.is_dir() {
+            let (mut more_tests, mut more_errors) = find_tests_within(root, &path, ignore);
+            tests.append(&mut more_tests);
+            errors.append(&mut more_errors);
+        } else if !is_sidecar_file(&path) {
+            tests.push(path);
+        }
+    }
+
+    (tests, errors)
+}
+
+/// True if the given path, relative to `root`, matches any of the `ignore` patterns. Patterns
+/// without a `/` may also match the name of the file or directory alone.
+fn is_ignored(root: &Path, path: &Path, ignore: &[glob::Pattern]) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let name = path.file_name().map(Path::new);
+    ignore.iter().any(|pattern| {
+        let matches_name = !pattern.as_str().contains('/') && name.is_some_and(|name| pattern.matches_path(name));
+        matches_name || pattern.matches_path(relative)
+    })
+}
+
+/// A file is a sidecar file if it ends in `.stdout` or `.stderr` and the test it belongs to exists
+fn is_sidecar_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) if extension == "stdout" || extension == "stderr" => path.with_extension("").is_file(),
+        _ => false,
+    }
+}
+
+/// The contents of a sidecar file holding the given expected text output
+fn sidecar_contents(output: &str, exact: bool) -> String {
+    if exact {
+        return output.to_owned();
+    }
+
+    let output = output.trim();
+    if output.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", output)
+    }
+}
+
+/// Writes the expected output of a stream to its sidecar file, removing the file instead
+/// if the output is empty.
+fn write_sidecar_file(test_path: &Path, stream: &str, contents: &[u8]) -> std::io::Result<()> {
+    let path = sidecar_path(test_path, stream);
+    if !contents.is_empty() {
+        std::fs::write(path, contents)
+    } else if path.exists() {
+        std::fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+/// Orders the given tests such that those which took the longest last time are ran first,
+/// pairing each test with its index in the original list. Packing the longest tests first
+/// avoids a few slow tests being started last and leaving the rest of the threads idle.
+/// Tests without a recorded duration keep their original order after all the others.
+fn schedule_longest_first(tests: Vec<PathBuf>, durations: &BTreeMap<PathBuf, Duration>) -> Vec<(usize, PathBuf)> {
+    let mut schedule: Vec<_> = tests.into_iter().enumerate().collect();
+    schedule.sort_by_key(|(_, test)| std::cmp::Reverse(durations.get(test).copied().unwrap_or_default()));
+    schedule
+}
+
+/// Shuffles the given items with a xorshift generator, so that the same seed always gives the same order
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    // xorshift never leaves a state of zero
+    let mut state = seed | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Returns `keyword` with the qualifier of the given stream, if any, inserted before its trailing ':',
+/// followed by the terminator of the block if it is fenced
+fn qualified_keyword(keyword: &str, expected: &ExpectedStream) -> String {
+    let qualifier = match (expected.limit, expected.encoding) {
+        (Some(limit), _) => Some(limit.to_string()),
+        (None, Some(encoding)) => Some(encoding.to_string()),
+        (None, None) if expected.escaped => Some("escaped".to_owned()),
+        (None, None) => None,
+    };
+    let base = match (keyword.strip_suffix(':'), qualifier) {
+        (Some(base), Some(qualifier)) => format!("{} ({})", base, qualifier),
+        (Some(base), None) => base.to_owned(),
+        (None, _) => return keyword.to_owned(),
+    };
+    match &expected.until {
+        Some(terminator) => format!("{} until: {}", base, terminator),
+        None => format!("{}:", base),
+    }
+}
+
+/// The lines of a block of expected output as they should be written within a test. Outside of exact
+/// mode, leading and trailing whitespace is stripped since it is ignored when comparing anyway.
+fn block_lines(text: &str, exact: bool) -> Vec<&str> {
+    if exact {
+        text.strip_suffix('\n').unwrap_or(text).split('\n').collect()
+    } else {
+        text.trim().split('\n').collect()
+    }
+}
+
+fn write_expected_output_for_stream(
+    file: &mut impl Write,
+    prefix: &str,
+    marker: &str,
+    expected: &str,
+    exact: bool,
+) -> std::io::Result<()> {
+    if exact && expected.is_empty() {
+        return Ok(());
+    }
+
+    let lines = block_lines(expected, exact);
+    match lines.len() {
+        // Don't write if there's nothing to write
+        0 => Ok(()),
+        1 if lines[0].is_empty() && !exact => Ok(()),
+        // If the line is short and nice, write that line
+        1 if !lines[0].is_empty() && lines[0].len() < 80 => {
+            write!(file, "{} ", marker)?;
+            file.write_all(lines[0].as_bytes())?;
+            writeln!(file)?;
+            writeln!(file)
+        }
+        // Otherwise we write it more longform
+        _ => {
+            writeln!(file, "{}", marker)?;
+            for line in lines {
+                file.write_all(prefix.as_bytes())?;
+                file.write_all(line.as_bytes())?;
+                writeln!(file)?;
+            }
+            writeln!(file)
+        }
+    }
+}
+
+/// Writes the given output as an encoded block. Unlike other blocks, the keyword is written even if
+/// the output is empty so that the stream stays encoded when it is overwritten again.
+fn write_encoded_output_for_stream(
+    file: &mut impl Write,
+    prefix: &str,
+    marker: &str,
+    output: &[u8],
+    encoding: Encoding,
+    until: Option<&str>,
+) -> std::io::Result<()> {
+    writeln!(file, "{}", marker)?;
+    for line in encoding.encode(output) {
+        writeln!(file, "{}{}", prefix, line)?;
+    }
+    if let Some(terminator) = until {
+        writeln!(file, "{}{}", prefix, terminator)?;
+    }
+    writeln!(file)
+}
+
+/// Writes the given output as a fenced block ending in its terminator. Blank lines within the
+/// output are written as the line prefix alone. Like encoded blocks, the keyword is always written.
+fn write_fenced_output_for_stream(
+    file: &mut impl Write,
+    prefix: &str,
+    marker: &str,
+    expected: &str,
+    exact: bool,
+    terminator: &str,
+) -> std::io::Result<()> {
+    writeln!(file, "{}", marker)?;
+    let is_empty = if exact {
+        expected.is_empty()
+    } else {
+        expected.trim().is_empty()
+    };
+    if !is_empty {
+        for line in block_lines(expected, exact) {
+            if line.is_empty() {
+                writeln!(file, "{}", prefix.trim_end())?;
+            } else {
+                file.write_all(prefix.as_bytes())?;
+                file.write_all(line.as_bytes())?;
+                writeln!(file)?;
+            }
+        }
+    }
+    writeln!(file, "{}{}", prefix, terminator)?;
+    writeln!(file)
+}
+
+/// Writes the expected block of a single stream in the same form it was given in, keeping any
+/// encoding, escapes, or fence of the block. `output` is the normalized output of the stream and
+/// `raw` is its original bytes, which encoded blocks are written from.
+fn write_expected_block(
+    file: &mut impl Write,
+    config: &TestConfig,
+    keyword: &str,
+    expected: &ExpectedStream,
+    output: &str,
+    raw: &[u8],
+    exact: bool,
+) -> std::io::Result<()> {
+    let prefix = &config.test_line_prefix;
+    let marker = qualified_keyword(keyword, expected);
+    let until = expected.until.as_deref();
+    if let Some(encoding) = expected.encoding {
+        return write_encoded_output_for_stream(file, prefix, &marker, raw, encoding, until);
+    }
+
+    let output = limit_output(output, expected.limit, exact);
+    let output = escape_if_needed(without_included(&output, expected), expected);
+    match until {
+        Some(terminator) => write_fenced_output_for_stream(file, prefix, &marker, &output, exact, terminator),
+        None => write_expected_output_for_stream(file, prefix, &marker, &output, exact),
+    }
+}
+
+/// Escapes the given output if it is written within an escaped block
+fn escape_if_needed<'a>(output: &'a str, expected: &ExpectedStream) -> std::borrow::Cow<'a, str> {
+    if expected.escaped {
+        escape(output).into()
+    } else {
+        output.into()
+    }
+}
+
+/// Removes the start of the given output which is already expected by an included file, since
+/// it is written in the included file rather than the test itself
+fn without_included<'a>(output: &'a str, expected: &ExpectedStream) -> &'a str {
+    if expected.included.is_empty() {
+        return output;
+    }
+
+    // The included text ends in a newline, which is trimmed from the output if nothing follows it
+    let included = expected.included.as_str();
+    let remainder = output.strip_prefix(included).or_else(|| output.strip_prefix(included.trim_end()));
+    remainder.unwrap_or(output)
+}
+
+/// Renders the contents a test file would have if it were overwritten with the given output
+fn render_test(config: &TestConfig, actual: &ActualOutput, test: &Test) -> Vec<u8> {
+    let mut file = Vec::new();
+    // Writing to a Vec cannot fail
+    write_test(&mut file, config, actual, test).unwrap();
+    file
+}
+
+fn write_test(file: &mut impl Write, config: &TestConfig, actual: &ActualOutput, test: &Test) -> std::io::Result<()> {
+    file.write_all(test.rest.trim_end().as_bytes())?;
+    writeln!(file)?;
+    writeln!(file)?;
+
+    if !test.command_line_args.is_empty() && !test.args_included {
+        if let Some((first, continued)) = test.args_lines.split_first() {
+            writeln!(file, "{} {}", config.test_args_prefix, first.trim())?;
+            for args in continued {
+                writeln!(file, "{}{}", config.test_line_prefix, args.trim_end())?;
+            }
+        }
+    }
+
+    // An exit status given by an included file only needs to be overridden if it differs, and
+    // otherwise one is only written if it differs from the default
+    let expected_status = if test.exit_status_included {
+        test.expected_exit_status
+    } else {
+        Some(config.default_exit_status.unwrap_or(0))
+    };
+    if expected_status != actual.output.status.code() {
+        writeln!(
+            file,
+            "{} {}",
+            config.test_exit_status_prefix,
+            actual.output.status.code().unwrap_or(0)
+        )?;
+    }
+
+    if let Some(expected) = &test.expected_output {
+        let (output, raw) = (&actual.stdout, &actual.output.stdout);
+        write_expected_block(
+            file,
+            config,
+            &config.test_output_prefix,
+            expected,
+            output,
+            raw,
+            test.exact,
+        )?;
+    } else if !test.sidecar_files {
+        for (keyword, name, output, raw, expected) in [
+            (
+                &config.test_stdout_prefix,
+                "stdout",
+                &actual.stdout,
+                &actual.output.stdout,
+                &test.expected_stdout,
+            ),
+            (
+                &config.test_stderr_prefix,
+                "stderr",
+                &actual.stderr,
+                &actual.output.stderr,
+                &test.expected_stderr,
+            ),
+        ] {
+            if !test.is_partially_checked(name, expected) {
+                write_expected_block(file, config, keyword, expected, output, raw, test.exact)?;
+            }
+        }
+    }
+
+    // Substrings are assertions rather than a record of the output, so they are kept as written
+    for substring in test.expected_substrings.iter().filter(|substring| !substring.included) {
+        let marker = substring.keyword(config);
+        write_expected_output_for_stream(file, &config.test_line_prefix, marker, substring.text.trim(), false)?;
+    }
+
+    let files = test.expected_files.iter().zip(&actual.files).filter(|(expected, _)| !expected.included);
+    for (expected, contents) in files {
+        // Keep the previous expectation if the file wasn't created so that it isn't lost
+        let contents = contents.as_deref().unwrap_or(&expected.text);
+        let marker = format!("{} \"{}\":", config.test_expected_file_prefix, expected.path);
+
+        // Always write the longform version so the block is never mistaken for an empty file
+        writeln!(file, "{}", marker)?;
+        if !(test.exact && contents.is_empty()) {
+            for line in block_lines(contents, test.exact) {
+                writeln!(file, "{}{}", config.test_line_prefix, line)?;
+            }
+        }
+        writeln!(file)?;
+    }
+    Ok(())
+}
+
+fn overwrite_test(test_path: &Path, config: &TestConfig, actual: &ActualOutput, test: &Test) -> std::io::Result<()> {
+    // Maybe copy the file so we don't remove it if we fail here?
+    std::fs::write(test_path, render_test(config, actual, test))?;
+
+    if test.expected_output.is_some() {
+        // The merged output was already written to the test itself
+    } else if test.binary_output {
+        write_sidecar_file(test_path, "stdout", &actual.output.stdout)?;
+        write_sidecar_file(test_path, "stderr", &actual.output.stderr)?;
+    } else if test.sidecar_files {
+        write_sidecar_file(
+            test_path,
+            "stdout",
+            sidecar_contents(&actual.stdout, test.exact).as_bytes(),
+        )?;
+        write_sidecar_file(
+            test_path,
+            "stderr",
+            sidecar_contents(&actual.stderr, test.exact).as_bytes(),
+        )?;
+    }
+
+    // Keep the previous golden tree if the directory wasn't created so that it isn't lost
+    for (expected, produced) in test.expected_directories.iter().zip(&actual.directories) {
+        if let Some(produced) = produced {
+            write_directory_tree(&expected.golden, produced)?;
+        }
+    }
+    Ok(())
+}
+
+/// A passing test still needs canonicalization if the golden text of one of its expected blocks or
+/// sidecar files differs from the scrubbed actual output `--overwrite` would replace it with, e.g.
+/// because it was written before a scrubber was added and only passes thanks to `ignore whitespace`
+/// or an allowed diff. Only the golden text is compared, not the layout of the test file itself. This
+/// is only checked when scrubbers are configured, and skips blocks using the ignored line marker since
+/// overwriting those is always expected to differ. Binary and encoded output is never scrubbed.
+fn needs_canonicalization(config: &TestConfig, actual: &ActualOutput, test: &Test) -> bool {
+    if config.scrubbers.is_empty() || test.binary_output {
+        return false;
+    }
+
+    let marker = config.ignore_line_marker.as_str();
+    let trim = |text: &str| {
+        if test.exact {
+            text.to_owned()
+        } else {
+            text.trim().to_owned()
+        }
+    };
+    let differs = |expected: &ExpectedStream, output: &str| {
+        let uses_marker = expected.text.lines().any(|line| line == marker);
+        !uses_marker
+            && expected.encoding.is_none()
+            && trim(&limit_output(output, expected.limit, test.exact)) != trim(&expected.text)
+    };
+
+    match &test.expected_output {
+        // The merged output is captured entirely in stdout
+        Some(expected) => differs(expected, &actual.stdout),
+        None => [
+            ("stdout", &test.expected_stdout, &actual.stdout),
+            ("stderr", &test.expected_stderr, &actual.stderr),
+        ]
+        .iter()
+        .any(|(name, expected, output)| !test.is_partially_checked(name, expected) && differs(expected, output)),
+    }
+}
+
+fn limit_output(output: &str, limit: Option<LineLimit>, exact: bool) -> std::borrow::Cow<'_, str> {
+    match limit {
+        Some(limit) if exact => limit.apply(output),
+        Some(limit) => limit.apply(output.trim()),
+        None => output.into(),
+    }
+}
+
+/// Returns true if each line of `actual` matches `expected`, where any line of `expected`
+/// equal to `marker` matches any number of arbitrary lines in `actual`.
+fn matches_with_ignored_lines(expected: &[&str], actual: &[&str], marker: &str) -> bool {
+    let (mut e, mut a) = (0, 0);
+    // The most recent marker in `expected` and the line in `actual` it matches up to
+    let mut backtrack = None;
+
+    while a < actual.len() {
+        if e < expected.len() && expected[e] == marker {
+            backtrack = Some((e, a));
+            e += 1;
+        } else if e < expected.len() && expected[e] == actual[a] {
+            e += 1;
+            a += 1;
+        } else if let Some((marker_index, matched_up_to)) = backtrack {
+            // Let the last marker consume one more line and try again from there
+            backtrack = Some((marker_index, matched_up_to + 1));
+            e = marker_index + 1;
+            a = matched_up_to + 1;
+        } else {
+            return false;
+        }
+    }
+
+    expected[e..].iter().all(|line| *line == marker)
+}
+
+/// Replace each ignored line marker in `expected` with the lines of `actual` it most likely
+/// corresponds to. This keeps the diff shown for a failing test focused on the lines that
+/// actually differ rather than on the markers themselves.
+fn expand_ignored_lines(expected: &[&str], actual: &[&str], marker: &str) -> String {
+    let mut expanded = String::new();
+    let mut a = 0;
+
+    for (e, line) in expected.iter().enumerate() {
+        if *line == marker {
+            // If the next expected line can't be found, leave room for the remaining expected lines
+            let remaining = expected.len() - e - 1;
+            let fallback = actual.len().saturating_sub(remaining).max(a);
+            let end = match expected.get(e + 1) {
+                Some(next) => actual[a..].iter().position(|line| line == next).map_or(fallback, |i| a + i),
+                None => actual.len(),
+            };
+            for line in &actual[a..end] {
+                append_line(&mut expanded, line);
+            }
+            a = end;
+        } else {
+            append_line(&mut expanded, line);
+            a = (a + 1).min(actual.len());
+        }
+    }
+
+    expanded
+}
+
+/// Collapses each run of spaces and tabs into a single space and removes trailing whitespace from each line
+fn collapse_whitespace(text: &str) -> String {
+    let lines = text.lines().map(|line| {
+        let words: Vec<&str> = line.split([' ', '\t']).filter(|word| !word.is_empty()).collect();
+        let indent = if line.starts_with([' ', '\t']) { " " } else { "" };
+        format!("{}{}", indent, words.join(" "))
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+/// True if every line which differs matches one of the given "allow diff:" patterns
+fn differences_are_allowed<'a>(differences: &TextDiff<'a, 'a, '_, str>, allowed_diffs: &[Regex]) -> bool {
+    !allowed_diffs.is_empty()
+        && differences.iter_all_changes().filter(|change| change.tag() != ChangeTag::Equal).all(|change| {
+            let line = change.value().trim_end_matches('\n');
+            allowed_diffs.iter().any(|regex| regex.is_match(line))
+        })
+}
+
+/// Diff the given "stream" and expected contents of the stream. Differences are pushed
+/// to `allowed` rather than `errors` if each changed line is allowed by `allowed_diffs`
+fn check_for_differences_in_stream(
+    config: &TestConfig,
+    name: &str,
+    output: &str,
+    expected: &ExpectedStream,
+    test: &Test,
+    errors: &mut Vec<Mismatch>,
+    allowed: &mut Vec<Mismatch>,
+) {
+    let trim = |text: &str| {
+        if test.exact {
+            text.to_owned()
+        } else {
+            text.trim().to_owned()
+        }
+    };
+    let mut output = trim(&limit_output(output, expected.limit, test.exact));
+    let mut expected_text = trim(&expected.text);
+
+    // Stderr is for diagnostics, so it is still compared as text
+    if test.comparison != Comparison::Text && name != "stderr" {
+        let expected_line = expected.line;
+        let difference = structured::compare(test.comparison, &expected_text, &output, &test.ignored_fields);
+        let format = test.comparison.format_name();
+        match difference {
+            Ok(()) => (),
+            Err(Difference::Invalid { actual: false, error }) => {
+                let message = format!("Expected {} is not valid {}: {}\n", name, format, error);
+                errors.push(Mismatch::from(message).at_line(expected.line));
+            }
+            Err(Difference::Invalid { actual: true, error }) => {
+                let message = format!("Actual {} is not valid {}: {}", name, format, error);
+                let diff = config.diff(test, name, expected_text, output);
+                errors.push(Mismatch::with_diff(message, diff).at_line(expected.line));
+            }
+            Err(Difference::Differs {
+                paths,
+                expected,
+                actual,
+            }) => {
+                let message = format!(
+                    "Actual {} differs from expected {} as {} at {}:",
+                    name,
+                    name,
+                    format,
+                    paths.join(", ")
+                );
+                let diff = config.diff(test, name, expected, actual);
+                errors.push(Mismatch::with_diff(message, diff).at_line(expected_line));
+            }
+        }
+        return;
+    }
+
+    if test.ignore_whitespace {
+        output = collapse_whitespace(&output);
+        expected_text = collapse_whitespace(&expected_text);
+    }
+
+    let marker = config.ignore_line_marker.as_str();
+    if expected_text.lines().any(|line| line == marker) {
+        let expected_lines: Vec<&str> = expected_text.lines().collect();
+        let actual_lines: Vec<&str> = output.lines().collect();
+        if matches_with_ignored_lines(&expected_lines, &actual_lines, marker) {
+            return;
+        }
+        expected_text = expand_ignored_lines(&expected_lines, &actual_lines, marker);
+    }
+
+    if !test.exact {
+        expected_text = expected_text.trim().to_owned();
+    }
+
+    if let Some(tolerance) = test.float_tolerance {
+        output = apply_tolerance(&expected_text, &output, tolerance);
+    }
+
+    let differences = TextDiff::from_lines(&expected_text, &output);
+    let similarity = differences.ratio();
+    if similarity != 1.0 {
+        let similar_enough = test.min_similarity.is_some_and(|min_similarity| similarity >= min_similarity);
+        let destination = if similar_enough || differences_are_allowed(&differences, &test.allowed_diffs) {
+            allowed
+        } else {
+            errors
+        };
+        let qualifier = expected.limit.map_or_else(String::new, |limit| format!(" ({})", limit));
+        let message = format!(
+            "Actual {}{} differs from expected {} ({:.1}% similar):",
+            name,
+            qualifier,
+            name,
+            similarity * 100.0
+        );
+        let diff = config.diff(test, name, expected_text, output);
+        destination.push(Mismatch::with_diff(message, diff).at_line(expected.line));
+    }
+}
+
+/// Groups the indices of failed tests whose differences are all identical, e.g. because a version banner
+/// they all print changed, so that the differences can be printed once rather than for every test.
+/// Only groups of more than one test are returned, in the order they were first failed in.
+fn group_identical_failures(outputs: &[&(Option<String>, InnerTestResult<()>)]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<FailureKey, usize> = HashMap::new();
+
+    for (index, (_, result)) in outputs.iter().enumerate() {
+        if let Err(InnerTestError::TestFailed { errors, .. }) = result {
+            let key = errors
+                .iter()
+                .map(|mismatch| {
+                    let diff = mismatch.diff.as_ref();
+                    (
+                        mismatch.message.as_str(),
+                        diff.map(|diff| (diff.expected.as_str(), diff.actual.as_str())),
+                    )
+                })
+                .collect();
+            let group = *group_of.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(index);
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Compare the raw bytes of a stream, showing any differences as a diff of their hex dumps
+fn check_for_binary_differences_in_stream(
+    config: &TestConfig,
+    name: &str,
+    output: &[u8],
+    expected: &[u8],
+    test: &Test,
+    errors: &mut Vec<Mismatch>,
+) {
+    if output != expected {
+        let message = format!("Actual {} differs from expected {} (binary):", name, name);
+        let (expected, output) = (hex_dump(expected), hex_dump(output));
+        errors.push(Mismatch::with_diff(message, config.diff(test, name, expected, output)));
+    }
+}
+
+fn check_exit_status(output: &Output, test: &Test, errors: &mut Vec<Mismatch>) {
+    if let Some(expected_status) = test.expected_exit_status {
+        let message = match output.status.code() {
+            Some(actual_status) if actual_status == expected_status => return,
+            Some(actual_status) => format!(
+                "Expected an exit status of {} but process returned {}\n",
+                expected_status, actual_status,
+            ),
+            None => format!(
+                "Expected an exit status of {} but process was terminated by signal instead\n",
+                expected_status
+            ),
+        };
+        errors.push(Mismatch::from(message).at_line(test.expected_exit_status_line));
+    }
+}
+
+/// Checks each `check:`, `check-next:`, and `check-not:` of the test against stdout in order, like
+/// FileCheck. Only the first failing check is reported, since the checks after it can't be placed.
+fn check_ordered(test: &Test, stdout: &str, errors: &mut Vec<Mismatch>) {
+    // Where the search for the next check starts, which is just after the previous match, if any
+    let mut position = 0;
+    let mut matched_any = false;
+    let mut excluded = Vec::new();
+
+    let mut fail = |check: &crate::parser::Check, problem: &str| {
+        let message = format!("Actual stdout {}:\n{}\n", problem, check.text);
+        errors.push(Mismatch::from(message).at_line(Some(check.line)));
+    };
+
+    for check in &test.checks {
+        let start = match check.kind {
+            CheckKind::Not => {
+                excluded.push(check);
+                continue;
+            }
+            CheckKind::Check => match stdout[position..].find(&check.text) {
+                Some(offset) => position + offset,
+                None if matched_any => return fail(check, "does not contain this text after the previous check"),
+                None => return fail(check, "does not contain"),
+            },
+            CheckKind::Next => {
+                // The first line if nothing was matched yet, otherwise the line after the previous match
+                let line_start = match stdout[position..].find('\n') {
+                    Some(offset) if matched_any => position + offset + 1,
+                    _ if matched_any => stdout.len(),
+                    _ => 0,
+                };
+                let line_end = stdout[line_start..].find('\n').map_or(stdout.len(), |offset| line_start + offset);
+                match stdout[line_start..line_end].find(&check.text) {
+                    Some(offset) => line_start + offset,
+                    None => return fail(check, "does not contain this text on the line after the previous check"),
+                }
+            }
+        };
+
+        if let Some(not) = excluded.drain(..).find(|not| stdout[position..start].contains(&not.text)) {
+            return fail(not, "contains excluded text between checks");
+        }
+        position = start + check.text.len();
+        matched_any = true;
+    }
+
+    if let Some(not) = excluded.into_iter().find(|not| stdout[position..].contains(&not.text)) {
+        fail(not, "contains excluded text after the last check");
+    }
+}
+
+fn check_for_differences(config: &TestConfig, path: &Path, actual: &ActualOutput, test: &Test) -> InnerTestResult<()> {
+    let mut errors = vec![];
+    let mut allowed = vec![];
+    let output = &actual.output;
+    check_exit_status(output, test, &mut errors);
+
+    if let Some(expected) = &test.expected_output {
+        // The merged output is captured entirely in stdout
+        if expected.encoding.is_some() {
+            check_for_binary_differences_in_stream(config, "output", &output.stdout, &expected.raw, test, &mut errors);
+        } else {
+            let output = &actual.stdout;
+            check_for_differences_in_stream(config, "output", output, expected, test, &mut errors, &mut allowed);
+        }
+    } else if test.binary_output {
+        check_for_binary_differences_in_stream(
+            config,
+            "stdout",
+            &output.stdout,
+            &test.expected_stdout.raw,
+            test,
+            &mut errors,
+        );
+        check_for_binary_differences_in_stream(
+            config,
+            "stderr",
+            &output.stderr,
+            &test.expected_stderr.raw,
+            test,
+            &mut errors,
+        );
+    } else {
+        for (name, output, raw, expected) in [
+            ("stdout", &actual.stdout, &output.stdout, &test.expected_stdout),
+            ("stderr", &actual.stderr, &output.stderr, &test.expected_stderr),
+        ] {
+            if test.is_partially_checked(name, expected) {
+                continue;
+            } else if expected.encoding.is_some() {
+                check_for_binary_differences_in_stream(config, name, raw, &expected.raw, test, &mut errors);
+            } else {
+                let (errors, allowed) = (&mut errors, &mut allowed);
+                check_for_differences_in_stream(config, name, output, expected, test, errors, allowed);
+            }
+        }
+    }
+
+    for substring in &test.expected_substrings {
+        let output = if substring.stream == "stdout" {
+            &actual.stdout
+        } else {
+            &actual.stderr
+        };
+        if !substring.matches(output) {
+            let problem = match substring.kind {
+                SubstringKind::Contains => "does not contain",
+                SubstringKind::Excludes => "contains excluded text",
+                SubstringKind::StartsWith => "does not start with",
+                SubstringKind::EndsWith => "does not end with",
+            };
+            let text = substring.text.trim();
+            let message = format!("Actual {} {}:\n{}\n", substring.stream, problem, text);
+            errors.push(Mismatch::from(message).at_line(Some(substring.line)));
+        }
+    }
+
+    check_ordered(test, &actual.stdout, &mut errors);
+
+    for (expected, contents) in test.expected_files.iter().zip(&actual.files) {
+        match contents {
+            Some(contents) => {
+                let name = format!("file \"{}\"", expected.path);
+                let expected = ExpectedStream {
+                    text: expected.text.clone(),
+                    line: Some(expected.line),
+                    ..Default::default()
+                };
+                check_for_differences_in_stream(config, &name, contents, &expected, test, &mut errors, &mut allowed);
+            }
+            None => {
+                let message = format!("Expected file \"{}\" to exist after running the test\n", expected.path);
+                errors.push(Mismatch::from(message).at_line(Some(expected.line)))
+            }
+        }
+    }
+
+    for (expected, produced) in test.expected_directories.iter().zip(&actual.directories) {
+        match produced {
+            Some(produced) => check_directory(config, expected, produced, test, &mut errors, &mut allowed),
+            None => {
+                let message = format!(
+                    "Expected directory \"{}\" to exist after running the test\n",
+                    expected.path
+                );
+                errors.push(Mismatch::from(message).at_line(Some(expected.line)))
+            }
+        }
+    }
+
+    let path = path.to_owned();
+    if !errors.is_empty() {
+        Err(InnerTestError::TestFailed { path, errors })
+    } else if !allowed.is_empty() {
+        Err(InnerTestError::AllowedDifferences { path, errors: allowed })
+    } else {
+        Ok(())
+    }
+}
+
+/// Iterate over the given values in parallel. Each value is handed out in order as soon as a
+/// thread is free so earlier values are started first, but results may complete in any order.
 #[cfg(feature = "parallel")]
-use rayon::iter::ParallelIterator;
+fn into_iter<T>(value: T) -> impl ParallelIterator<Item = T::Item>
+where
+    T: IntoIterator,
+    T::IntoIter: Send,
+    T::Item: Send,
+{
+    value.into_iter().par_bridge()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn into_iter<T: IntoIterator>(value: T) -> T::IntoIter {
+    value.into_iter()
+}
+
+/// Runs `f` within a thread pool of at most `max_jobs` threads, so that `into_iter` runs at most
+/// that many tests at once. A new pool is built rather than configuring the global pool, which
+/// can only be done once per process and may already have been done by the caller.
+#[cfg(feature = "parallel")]
+fn with_max_jobs<R: Send>(max_jobs: Option<usize>, f: impl FnOnce() -> R + Send) -> R {
+    let pool = max_jobs.and_then(|jobs| rayon::ThreadPoolBuilder::new().num_threads(jobs).build().ok());
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn with_max_jobs<R>(_max_jobs: Option<usize>, f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+impl TestConfig {
+    /// The width output should be fit within, if known
+    pub(crate) fn output_width(&self) -> Option<usize> {
+        self.width.or_else(terminal::detect_width)
+    }
+
+    /// The differences between the expected and actual contents of a stream of the given test
+    fn diff(&self, test: &Test, stream: &str, expected: String, actual: String) -> Diff {
+        Diff {
+            expected,
+            actual,
+            path: test.path.clone(),
+            stream: stream.to_owned(),
+            format: self.diff_format,
+            width: self.output_width(),
+        }
+    }
+
+    /// The contents of each directory in `Test::expected_directories`, or None for each directory that does not exist
+    fn read_expected_directories(&self, test: &Test) -> Vec<Option<DirectoryTree>> {
+        test.expected_directories
+            .iter()
+            .map(|directory| read_directory_tree(&test.resolve_path(&directory.path)))
+            .collect()
+    }
+
+    /// The contents of each file in `Test::expected_files`, or None for each file that does not exist
+    fn read_expected_files(&self, test: &Test) -> Vec<Option<String>> {
+        let read = |file: &ExpectedFile| std::fs::read(test.resolve_path(&file.path)).ok();
+        test.expected_files
+            .iter()
+            .map(|file| read(file).map(|contents| self.normalize_output(&contents, test.exact)))
+            .collect()
+    }
+
+    /// The path of each file within the given directory, relative to it. The state directory is
+    /// left out since goldentests itself writes to it while tests run.
+    fn list_files(&self, directory: &Path) -> BTreeSet<PathBuf> {
+        let state_directory = self.state_directory.as_deref().and_then(|state| std::path::absolute(state).ok());
+        let mut files = BTreeSet::new();
+        let mut directories = vec![PathBuf::new()];
+        while let Some(relative) = directories.pop() {
+            let entries = match std::fs::read_dir(directory.join(&relative)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = relative.join(entry.file_name());
+                if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    files.insert(path);
+                } else if state_directory != std::path::absolute(entry.path()).ok() {
+                    directories.push(path);
+                }
+            }
+        }
+        files
+    }
+
+    /// Each file within `directory` which wasn't in `files_before` and isn't expected by the test
+    /// or allowed by `stray_file_allowlist`, see `forbid_stray_files`
+    fn stray_files(&self, test: &Test, directory: &Path, files_before: &BTreeSet<PathBuf>) -> Vec<PathBuf> {
+        let expected: Vec<&Path> = test.expected_files.iter().map(|file| Path::new(&file.path)).collect();
+        let files_after = self.list_files(directory);
+        files_after
+            .difference(files_before)
+            .filter(|file| !expected.contains(&file.as_path()))
+            .filter(|file| !test.expected_directories.iter().any(|directory| file.starts_with(&directory.path)))
+            .filter(|file| !self.stray_file_allowlist.iter().any(|pattern| pattern.matches_path(file)))
+            .cloned()
+            .collect()
+    }
+
+    /// Converts the raw output of a stream into the form it is compared and overwritten in: line
+    /// endings are normalized unless `exact` is set and each scrubber is applied in order.
+    fn normalize_output(&self, stream: &[u8], exact: bool) -> String {
+        let output = String::from_utf8_lossy(stream);
+        let mut output = if exact {
+            output.into_owned()
+        } else {
+            self.line_endings.normalize(&output).into_owned()
+        };
+        for (regex, replacement) in &self.scrubbers {
+            output = regex.replace_all(&output, replacement.as_str()).into_owned();
+        }
+        output
+    }
+
+    /// Pipes a stream of the test's output through its `filter:` command, if it has one. The
+    /// command is ran within the same directory and environment variables as the test's binary.
+    fn filter_output<'a>(
+        &self,
+        test: &Test,
+        env: &[(String, String)],
+        stream: &'a [u8],
+    ) -> InnerTestResult<Cow<'a, [u8]>> {
+        let filter = match &test.filter {
+            Some(filter) => filter,
+            None => return Ok(Cow::Borrowed(stream)),
+        };
+
+        let mut command = shell::shell_command(filter);
+        if let Some(cwd) = &test.cwd {
+            command.current_dir(cwd);
+        }
+        command.envs(env.iter().map(|(key, value)| (key, value)));
+
+        shell::pipe_through(&mut command, stream)
+            .map(Cow::Owned)
+            .map_err(|reason| InnerTestError::ShellCommandFailed(test.path.clone(), filter.clone(), reason))
+    }
+
+    /// The environments the given test should be ran within. If no environments are configured,
+    /// the test is ran once outside of any environment, represented by `None`. A test which none of
+    /// the configured environments apply to is an error, since it would otherwise never be ran.
+    pub(crate) fn environments_for(&self, test: &Test) -> InnerTestResult<Vec<Option<&Environment>>> {
+        if self.environments.is_empty() {
+            return Ok(vec![None]);
+        }
+
+        let relative_path = test.path.strip_prefix(&self.test_path).unwrap_or(&test.path);
+        let environments: Vec<_> = self
+            .environments
+            .iter()
+            .filter(|environment| match &test.environments {
+                Some(names) => names.contains(&environment.name),
+                None => environment.applies_to(relative_path),
+            })
+            .map(Some)
+            .collect();
+
+        if environments.is_empty() {
+            return Err(InnerTestError::NoEnvironments(test.path.clone()));
+        }
+        Ok(environments)
+    }
+
+    /// Arranges the given tests in the order they should be ran and reported in
+    fn order_tests(&self, tests: &mut [PathBuf]) {
+        match self.order {
+            TestOrder::Sorted => tests.sort(),
+            TestOrder::Random => {
+                let seed = self.seed.unwrap_or_else(|| {
+                    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+                    now.map_or(0, |time| time.as_nanos() as u64)
+                });
+                summary!(self, "shuffling tests with seed {}", format!("{}", seed).cyan());
+                tests.sort();
+                shuffle(tests, seed);
+            }
+            TestOrder::Discovery => (),
+        }
+    }
+
+    /// Runs the `setup` command, if any, reporting an error if it fails
+    pub(crate) fn run_setup(&self) -> TestResult<()> {
+        if let Some(setup) = &self.setup {
+            if let Err(reason) = shell::run_to_completion(&mut shell::shell_command(setup)) {
+                let error = TestError::SetupFailed(setup.clone(), reason);
+                eprintln!("{}", error.to_string().red());
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs each `before:` or `after:` command line in order within the same directory and
+    /// environment variables as the test's binary, stopping at the first which fails
+    fn run_shell_commands<'a>(
+        &self,
+        test: &Test,
+        env: &[(String, String)],
+        command_lines: impl Iterator<Item = &'a String>,
+    ) -> InnerTestResult<()> {
+        for command_line in command_lines {
+            let mut command = shell::shell_command(command_line);
+            if let Some(cwd) = &test.cwd {
+                command.current_dir(cwd);
+            }
+            command.envs(env.iter().map(|(key, value)| (key, value)));
+
+            shell::run_to_completion(&mut command).map_err(|reason| {
+                InnerTestError::ShellCommandFailed(test.path.clone(), command_line.clone(), reason)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The runtime command and its arguments to run a wasm binary with. The runtime is given access
+    /// to the directory the binary is ran in and the directory of the test file, so the test file can
+    /// be read from within the sandbox, along with the environment variables of the environment.
+    fn wasm_runtime_args(&self, environment: Option<&Environment>, test_path: &Path) -> Vec<String> {
+        let mut args = self.wasm_runtime.clone();
+        args.push("--dir=.".to_owned());
+
+        // The test path is only absolute when the test changes directories with "cwd:"
+        if let Some(directory) = test_path.parent().filter(|_| test_path.is_absolute()) {
+            args.push(format!("--dir={}", directory.display()));
+        }
+
+        for (key, value) in environment.map_or(&[][..], |environment| &environment.env) {
+            args.push(format!("--env={}={}", key, value));
+        }
+        args
+    }
+
+    /// Runs the given test once and checks its output. When `overwrite` is set,
+    /// a failing test is updated with the new output rather than failing.
+    fn run_test(&self, test: &Test, environment: Option<&Environment>, overwrite: bool) -> InnerTestResult<()> {
+        // In hermetic mode the binary is ran within a copy of the test, which is removed when this returns
+        let hermetic_dir = if test.hermetic {
+            Some(copy_to_hermetic_dir(test)?)
+        } else {
+            None
+        };
+        let hermetic_test;
+        let test = match &hermetic_dir {
+            Some(dir) => {
+                let test_directory = test.path.parent().unwrap_or_else(|| Path::new(""));
+                let cwd = match &test.cwd {
+                    Some(cwd) => {
+                        cwd.strip_prefix(test_directory).map_or_else(|_| cwd.clone(), |cwd| dir.path().join(cwd))
+                    }
+                    None => dir.path().to_owned(),
+                };
+                hermetic_test = Test {
+                    cwd: Some(cwd),
+                    ..test.clone()
+                };
+                &hermetic_test
+            }
+            None => test,
+        };
+
+        let file = &test.path;
+        let io_error = |err| InnerTestError::IoError(file.clone(), err);
+
+        // Relative paths would be resolved relative to the new working directory
+        // of the binary, so make them absolute first if the test changes it.
+        let absolute = |path: &Path| -> InnerTestResult<PathBuf> {
+            let is_relative_path = path.is_relative() && path.components().count() > 1;
+            if test.cwd.is_some() && is_relative_path {
+                std::path::absolute(path).map_err(|err| InnerTestError::IoError(file.clone(), err))
+            } else {
+                Ok(path.to_owned())
+            }
+        };
+
+        let test_path = match &hermetic_dir {
+            Some(dir) => Ok(dir.path().join(file.file_name().unwrap_or_default())),
+            None if test.cwd.is_some() => std::path::absolute(file),
+            None => Ok(file.clone()),
+        };
+        let test_path = test_path.map_err(io_error)?;
+
+        let file_args = match &test.args_file {
+            Some(args_file) => read_args_file(args_file)?,
+            None => Vec::new(),
+        };
+
+        let mut template = Template::new(&test_path);
+
+        // The invocation of the given binary for this test, so that a `reference_binary` can be ran the same way
+        let mut invocation_for = |binary_path: &Path| -> InnerTestResult<Invocation> {
+            // The command wrapper runs outermost, e.g. `valgrind` around an environment's emulator
+            let environment_wrapper = environment.map_or(&[][..], |environment| &environment.wrapper);
+            let mut program = vec![];
+            for wrapper in [&self.command_wrapper[..], environment_wrapper] {
+                if let Some((wrapper, wrapper_args)) = wrapper.split_first() {
+                    program.push(absolute(Path::new(wrapper))?.to_string_lossy().into_owned());
+                    program.extend(wrapper_args.iter().cloned());
+                }
+            }
+            if binary_path.extension().is_some_and(|extension| extension == "wasm") {
+                program.extend(self.wasm_runtime_args(environment, &test_path));
+            }
+            program.push(absolute(binary_path)?.to_string_lossy().into_owned());
+
+            // In shell mode every word is quoted except for the test's own arguments, which are
+            // given to the shell as written so that they may use pipes or redirection
+            let word = |arg: String| if self.shell { shell::quote(&arg) } else { arg };
+
+            let environment_args = environment.map_or(&[][..], |environment| &environment.args);
+            let environment_args: Vec<_> = self.base_args.iter().chain(environment_args).collect();
+            let test_args = test.command_line_args.trim();
+
+            let mut args = vec![];
+            for arg in environment_args.iter().copied().chain(&file_args) {
+                args.push(word(template.expand(arg).map_err(io_error)?));
+            }
+
+            if self.shell {
+                args.push(template.expand_quoted(test_args).map_err(io_error)?);
+            } else if !test_args.is_empty() {
+                // Avoid pushing an empty '' arg at the beginning
+                let test_args = shlex::split(test_args)
+                    .ok_or_else(|| InnerTestError::ErrorParsingArgs(file.clone(), test_args.to_owned()))?;
+                for arg in test_args {
+                    args.push(template.expand(&arg).map_err(io_error)?);
+                }
+            }
+
+            // The test path is passed last unless the arguments place it elsewhere via `{file}`
+            let mentions_file =
+                environment_args.iter().copied().chain(&file_args).any(|arg| Template::mentions_file(arg));
+            if !mentions_file && !Template::mentions_file(test_args) {
+                args.push(word(test_path.to_string_lossy().into_owned()));
+            }
+
+            let (program, args) = if self.shell {
+                let program = program.into_iter().map(word);
+                let (shell, flag) = shell::system_shell();
+                (
+                    shell.to_owned(),
+                    vec![flag.to_owned(), program.chain(args).collect::<Vec<_>>().join(" ")],
+                )
+            } else {
+                let mut program = program.into_iter();
+                (program.next().unwrap(), program.chain(args).collect())
+            };
+
+            // A clean environment only keeps the allowed variables of our own environment
+            let mut env = Vec::new();
+            if self.clean_env {
+                let allowed = self.env_allowlist.iter().filter_map(|key| Some((key.clone(), std::env::var(key).ok()?)));
+                env.extend(allowed);
+            }
+            env.extend(environment.map_or(&[][..], |environment| &environment.env).iter().cloned());
+
+            if let Some(key) = &self.tmpdir_env_var {
+                let tmpdir = template.tmpdir().map_err(io_error)?;
+                env.push((key.clone(), tmpdir.to_string_lossy().into_owned()));
+            }
+
+            Ok(Invocation {
+                program,
+                args,
+                current_dir: test.cwd.clone(),
+                env,
+                clean_env: self.clean_env,
+                stdin: test.stdin_file.clone(),
+                combine_output: test.expected_output.is_some(),
+                limits: self.limits,
+                pty: test.pty,
+            })
+        };
+        let invocation = invocation_for(&self.binary_path)?;
+
+        // Remove any expected files and directories left over from a previous run so they can't pass the
+        // test. Paths such as ".", "../Cargo.toml", or absolute paths aren't removed, so a mistyped path
+        // can't remove the run directory itself or anything outside of it.
+        for file in test.expected_files.iter().filter(|file| is_within_run_directory(&file.path)) {
+            let _ = std::fs::remove_file(test.resolve_path(&file.path));
+        }
+        for directory in test.expected_directories.iter().filter(|directory| is_within_run_directory(&directory.path)) {
+            let _ = std::fs::remove_dir_all(test.resolve_path(&directory.path));
+        }
+
+        let run_directory = test.cwd.clone().unwrap_or_else(|| PathBuf::from("."));
+        let files_before = self.forbid_stray_files.then(|| self.list_files(&run_directory));
+
+        self.run_shell_commands(test, &invocation.env, self.before_each.iter().chain(&test.before))?;
+
+        let run_command = |invocation: &Invocation| {
+            let (output, session_failure) = if test.session.is_empty() {
+                (self.command_runner.run_measured(invocation), None)
+            } else {
+                let normalize = |output: &[u8]| self.normalize_output(output, test.exact);
+                match run_session(invocation, &test.session, self.session_step_timeout, &normalize) {
+                    Ok((output, failure)) => (Ok((output, None)), failure),
+                    Err(error) => (Err(error), None),
+                }
+            };
+            let (output, peak_memory) = output.map_err(|err| {
+                // Report a missing stdin file as a problem with the file rather than the command
+                match &invocation.stdin {
+                    Some(stdin) if !stdin.exists() => InnerTestError::IoError(stdin.clone(), err),
+                    _ => InnerTestError::CommandError(file.clone(), invocation.to_string(), err),
+                }
+            })?;
+            match invocation.limits.exceeded(&output.status, peak_memory) {
+                Some(limit) => Err(InnerTestError::ResourceLimitExceeded(test.path.clone(), limit)),
+                None => Ok((output, session_failure)),
+            }
+        };
+
+        // Everything between the `before:` and `after:` commands is ran within this, so that the
+        // `after:` commands still run to tear down the test when running it fails part of the way through
+        let mut run = || -> InnerTestResult<_> {
+            let (output, session_failure) = run_command(&invocation)?;
+
+            let filter = |stream: &[u8]| -> InnerTestResult<String> {
+                let stream = self.filter_output(test, &invocation.env, stream)?;
+                Ok(self.normalize_output(&stream, test.exact))
+            };
+
+            let stdout = filter(&output.stdout)?;
+            let stderr = filter(&output.stderr)?;
+            let actual = ActualOutput {
+                output,
+                stdout,
+                stderr,
+                files: self.read_expected_files(test),
+                directories: self.read_expected_directories(test),
+            };
+
+            let status =
+                |output: &Output| output.status.code().map_or_else(|| "a signal".to_owned(), |code| code.to_string());
+
+            // Run the binary again for each repeat, failing if its output ever differs from the first run
+            let mut nondeterminism = Vec::new();
+            for run in 2..=test.repeat {
+                let (output, _) = run_command(&invocation)?;
+                for (name, first, output) in [
+                    ("stdout", &actual.stdout, &output.stdout),
+                    ("stderr", &actual.stderr, &output.stderr),
+                ] {
+                    let output = filter(output)?;
+                    if output != *first {
+                        let message = format!("Actual {} differs between run 1 and run {}:", name, run);
+                        nondeterminism.push(Mismatch::with_diff(
+                            message,
+                            self.diff(test, name, first.clone(), output),
+                        ));
+                    }
+                }
+
+                if output.status.code() != actual.output.status.code() {
+                    let message = format!(
+                        "Exit status differs between run 1 ({}) and run {} ({})\n",
+                        status(&actual.output),
+                        run,
+                        status(&output)
+                    );
+                    nondeterminism.push(message.into());
+                }
+
+                if !nondeterminism.is_empty() {
+                    break;
+                }
+            }
+
+            // Run the reference binary the same way, failing if its output differs from the tested binary's
+            let mut reference_differences = Vec::new();
+            if let Some(reference_binary) = &self.reference_binary {
+                let (output, _) = run_command(&invocation_for(reference_binary)?)?;
+                for (name, actual, reference) in [
+                    ("stdout", &actual.stdout, &output.stdout),
+                    ("stderr", &actual.stderr, &output.stderr),
+                ] {
+                    let reference = filter(reference)?;
+                    if reference != *actual {
+                        let message = format!("Actual {} differs from that of the reference binary:", name);
+                        reference_differences.push(Mismatch::with_diff(
+                            message,
+                            self.diff(test, name, reference, actual.clone()),
+                        ));
+                    }
+                }
+
+                if output.status.code() != actual.output.status.code() {
+                    let message = format!(
+                        "Expected an exit status of {} like the reference binary but process returned {}\n",
+                        status(&output),
+                        status(&actual.output)
+                    );
+                    reference_differences.push(message.into());
+                }
+            }
 
-#[cfg(feature = "progress-bar")]
-use indicatif::ProgressBar;
+            Ok((actual, session_failure, nondeterminism, reference_differences))
+        };
+        let result = run();
+        let teardown = self.run_shell_commands(test, &invocation.env, test.after.iter().chain(&self.after_each));
+        let (actual, session_failure, nondeterminism, reference_differences) = result?;
+        teardown?;
 
-use std::fs::File;
-use std::io::{Write, Read};
-use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+        // A failed session step is reported alone, since the output after it is cut short
+        if let Some(failure) = session_failure {
+            let error = match failure.expected {
+                Some((expected, received)) => {
+                    Mismatch::with_diff(failure.message, self.diff(test, "stdout", expected, received))
+                }
+                None => failure.message.into(),
+            };
+            return Err(InnerTestError::TestFailed {
+                path: test.path.clone(),
+                errors: vec![error],
+            });
+        }
 
-type InnerTestResult<T> = Result<T, InnerTestError>;
+        if !nondeterminism.is_empty() {
+            return Err(InnerTestError::TestFailed {
+                path: test.path.clone(),
+                errors: nondeterminism,
+            });
+        }
 
-struct Test {
-    path: PathBuf,
-    command_line_args: String,
-    expected_stdout: String,
-    expected_stderr: String,
-    expected_exit_status: Option<i32>,
-    rest: String,
-}
+        if let Some(files_before) = files_before {
+            let stray_files = self.stray_files(test, &run_directory, &files_before);
+            if !stray_files.is_empty() {
+                let mut message = "Created unexpected files:\n".to_owned();
+                for file in stray_files {
+                    message += &format!("  {}\n", file.display());
+                }
+                return Err(InnerTestError::TestFailed {
+                    path: test.path.clone(),
+                    errors: vec![message.into()],
+                });
+            }
+        }
 
-#[derive(PartialEq)]
-enum TestParseState {
-    Neutral,
-    ReadingExpectedStdout,
-    ReadingExpectedStderr,
-}
+        // The expected output within the test is ignored when comparing against a reference binary,
+        // so there is nothing to overwrite either
+        let overwrite = overwrite && self.reference_binary.is_none();
+        let mut differences = if self.reference_binary.is_none() {
+            check_for_differences(self, &test.path, &actual, test)
+        } else if reference_differences.is_empty() {
+            Ok(())
+        } else {
+            Err(InnerTestError::TestFailed {
+                path: test.path.clone(),
+                errors: reference_differences,
+            })
+        };
 
-/// Expects that the given directory is an existing path
-fn find_tests(directory: &Path) -> (Vec<PathBuf>, Vec<InnerTestError>) {
-    let mut tests = vec![];
-    let mut errors = vec![];
+        if differences.is_ok() && self.reference_binary.is_none() && needs_canonicalization(self, &actual, test) {
+            differences = Err(InnerTestError::NeedsCanonicalization(test.path.clone()));
+        }
 
-    let read_dir = match std::fs::read_dir(directory) {
-        Ok(dir) => dir,
-        Err(err) => return (tests, vec![InnerTestError::IoError(directory.to_owned(), err)]),
-    };
+        let environment_name = environment.map(|environment| environment.name.as_str());
+        if let (Err(InnerTestError::TestFailed { errors, .. }), false) = (&mut differences, overwrite) {
+            let failure = Failure {
+                test_path: &test.path,
+                environment: environment_name,
+                stdout: &actual.output.stdout,
+                stderr: &actual.output.stderr,
+                exit_status: actual.output.status.code(),
+                errors,
+            };
+            self.run_failure_hook(&failure);
 
-    for entry in read_dir {
-        let path = match entry {
-            Ok(entry) => entry.path(),
-            Err(err) => {
-                errors.push(InnerTestError::IoError(directory.to_owned(), err));
-                continue;
+            if self.save_actual {
+                let note = self.save_actual_output(&failure);
+                errors.push(note);
             }
-        };
+        } else if self.save_actual {
+            self.remove_artifacts(&test.path, environment_name);
+        }
 
-        if path.is_dir() {
-            let (mut more_tests, mut more_errors) = find_tests(&path);
-            tests.append(&mut more_tests);
-            errors.append(&mut more_errors);
-        } else {
-            tests.push(path);
+        if overwrite {
+            let errors = match differences {
+                Err(InnerTestError::TestFailed { errors, .. }) => errors,
+                Err(InnerTestError::NeedsCanonicalization(_)) => {
+                    vec!["Canonicalized expected output".to_owned().into()]
+                }
+                _ => return differences,
+            };
+
+            overwrite_test(file, self, &actual, test).map_err(|err| InnerTestError::IoError(file.to_owned(), err))?;
+
+            return Err(InnerTestError::TestUpdated {
+                path: test.path.clone(),
+                errors,
+            });
         }
+        differences
     }
 
-    (tests, errors)
-}
+    /// Each test to run: the files given by `test_files` if set, otherwise each file found within
+    /// `test_path` which isn't ignored. Listed tests which don't exist are still returned so that
+    /// they fail when ran, like any other test which can't be read.
+    pub(crate) fn find_tests(&self) -> (Vec<PathBuf>, Vec<InnerTestError>) {
+        let test_files = match &self.test_files {
+            Some(test_files) => test_files,
+            None => return find_tests(&self.test_path, &self.ignore_patterns),
+        };
 
-fn strip_prefix<'a>(s: &'a str, prefix: &str) -> &'a str {
-    s.strip_prefix(prefix).unwrap_or(s)
-}
-
-fn append_line(s: &mut String, line: &str) {
-    *s += line;
-    *s += "\n";
-}
-
-fn parse_test(test_path: &Path, config: &TestConfig) -> InnerTestResult<Test> {
-    let mut command_line_args = String::new();
-    let mut expected_stdout = String::new();
-    let mut expected_stderr = String::new();
-    let mut expected_exit_status = None;
-    let mut rest = String::new();
-
-    let mut file = File::open(test_path).map_err(|err| InnerTestError::IoError(test_path.to_owned(), err))?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .map_err(|err| InnerTestError::IoError(test_path.to_owned(), err))?;
-
-    let mut state = TestParseState::Neutral;
-    for line in contents.lines() {
-        if line.starts_with(&config.test_line_prefix) {
-            // If we're currently reading stdout or stderr, append the line to the expected output
-            if state == TestParseState::ReadingExpectedStdout {
-                append_line(&mut expected_stdout, strip_prefix(line, &config.test_line_prefix))
-            } else if state == TestParseState::ReadingExpectedStderr {
-                append_line(&mut expected_stderr, strip_prefix(line, &config.test_line_prefix));
-
-            // Otherwise, look to see if the line begins with a keyword and if so change state
-            // (stdout/stderr) or parse an argument to the keyword (args/exit status).
-
-            // args:
-            } else if line.starts_with(&config.test_args_prefix) {
-                command_line_args = strip_prefix(line, &config.test_args_prefix).to_string();
-
-            // expected stdout:
-            } else if line.starts_with(&config.test_stdout_prefix) {
-                state = TestParseState::ReadingExpectedStdout;
-                // Append the remainder of the line to the expected stdout.
-                // Both expected_stdout and expected_stderr are trimmed so it
-                // has no effect if the rest of this line is empty
-                append_line(&mut expected_stdout, strip_prefix(line, &config.test_stdout_prefix));
-
-            // expected stderr:
-            } else if line.starts_with(&config.test_stderr_prefix) {
-                state = TestParseState::ReadingExpectedStderr;
-                append_line(&mut expected_stderr, strip_prefix(line, &config.test_stderr_prefix));
-
-            // expected exit status:
-            } else if line.starts_with(&config.test_exit_status_prefix) {
-                let status = strip_prefix(line, &config.test_exit_status_prefix).trim();
-                expected_exit_status = Some(status.parse().map_err(|err| {
-                    InnerTestError::ErrorParsingExitStatus(test_path.to_owned(), status.to_owned(), err)
-                })?);
+        let (mut tests, mut errors) = (Vec::new(), Vec::new());
+        for path in test_files {
+            if path.is_dir() {
+                let (mut more_tests, mut more_errors) = find_tests(path, &self.ignore_patterns);
+                tests.append(&mut more_tests);
+                errors.append(&mut more_errors);
             } else {
-                append_line(&mut rest, line);
-            }
-        } else {
-            // Both expected_stdout and expected_stderr need a blank line at the end,
-            // the order here implicitly skips that newline.
-            if state == TestParseState::Neutral {
-                append_line(&mut rest, line);
+                tests.push(path.clone());
             }
-            state = TestParseState::Neutral;
         }
+        (tests, errors)
     }
 
-    // Remove \r from strings for windows compatibility. This means we
-    // also can't test for any string containing "\r" unless this check
-    // is improved to be more clever (e.g. only removing at the end of a line).
-    let expected_stdout = expected_stdout.replace("\r", "");
-    let expected_stderr = expected_stderr.replace("\r", "");
+    /// Bootstraps a suite of golden tests from a directory of raw inputs. Each file within
+    /// `test_path` which doesn't contain any test directives yet is ran once and has its actual
+    /// output written as its expected output, either inline or to sidecar files if `sidecar_files`
+    /// is set. Files which already contain directives are left untouched.
+    pub fn generate_tests(&self) -> TestResult<()> {
+        self.run_setup()?;
+        let (mut inputs, path_errors) = self.find_tests();
+        inputs.sort();
 
-    Ok(Test {
-        path: test_path.to_owned(),
-        command_line_args,
-        expected_stdout,
-        expected_stderr,
-        expected_exit_status,
-        rest,
-    })
-}
+        let mut results: Vec<_> = with_max_jobs(self.max_jobs, || {
+            into_iter(inputs.into_iter().enumerate())
+                .map(|(index, file)| (index, self.generate_test(&file)))
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
 
-fn write_expected_output_for_stream(file: &mut File, prefix: &str, marker: &str, expected: &[u8]) -> std::io::Result<()> {
-    // Doesn't handle \r correctly!
-    // Strip leading and trailing newlines from the output
-    let expected_stdout = String::from_utf8_lossy(expected).replace("\r", "");
-    let lines: Vec<&str> = expected_stdout.trim().split('\n').collect();
-    match lines.len() {
-        // Don't write if there's nothing to write
-        0 => Ok(()),
-        1 if lines[0].len() == 0 => Ok(()),
-        // If the line is short and nice, write that line
-        1 if lines[0].len() < 80 => {
-            write!(file, "{} ", marker)?;
-            file.write_all(expected)?;
-            writeln!(file, "")
+        let width = self.output_width();
+        let (mut generated, mut skipped) = (0, 0);
+        for error in &path_errors {
+            eprintln!("{}", error.formatted(width, self.location_format));
         }
-        // Otherwise we write it more longform
-        _ => {
-            writeln!(file, "{}", marker)?;
-            for line in lines {
-                file.write_all(prefix.as_bytes())?;
-                file.write_all(line.as_bytes())?;
-                writeln!(file, "")?;
+
+        let mut failures = path_errors;
+        for (_, result) in results {
+            match result {
+                Ok(true) => generated += 1,
+                Ok(false) => skipped += 1,
+                Err(error) => {
+                    eprintln!("{}", error.formatted(width, self.location_format));
+                    failures.push(error);
+                }
+            }
+        }
+
+        println!(
+            "generated {} {} tests with {} and {}\n",
+            generated,
+            "golden".bright_yellow(),
+            format!("{} skipped", skipped).cyan(),
+            format!("{} failing", failures.len()).red(),
+        );
+
+        if !failures.is_empty() {
+            Err(TestError::TestErrors(failures))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The path of each test file within `test_path`, in the order they are reported in, without
+    /// running or parsing any of them. Fails if any part of `test_path` couldn't be read, printing
+    /// each path which couldn't be.
+    pub fn list_tests(&self) -> TestResult<Vec<PathBuf>> {
+        let (mut tests, path_errors) = self.find_tests();
+        if !path_errors.is_empty() {
+            let width = self.output_width();
+            for error in &path_errors {
+                eprintln!("{}", error.formatted(width, self.location_format));
             }
-            writeln!(file, "")
+            return Err(TestError::TestErrors(path_errors));
         }
+        tests.sort();
+        Ok(tests)
     }
-}
 
-fn overwrite_test(test_path: &PathBuf, config: &TestConfig, output: &Output, test: &Test) -> std::io::Result<()> {
-    // Maybe copy the file so we don't remove it if we fail here?
-    let mut file = File::create(test_path)?;
+    /// Checks that the suite is runnable without running anything: the binary must exist and be
+    /// executable, the test path must exist, and every test must parse. Any problems with the
+    /// tests are printed and returned, along with warnings for lines which look like mistyped
+    /// keywords. Setup commands aren't ran, so a binary built by `setup` may not exist yet.
+    pub fn check_tests(&self) -> TestResult<()> {
+        for binary_path in std::iter::once(&self.binary_path).chain(&self.reference_binary) {
+            // wasm binaries are ran by the wasm runtime instead, so only need to exist
+            if binary_path.extension().is_some_and(|extension| extension == "wasm") {
+                if !binary_path.is_file() {
+                    return Err(TestError::InvalidBinary(binary_path.clone(), "no such file".to_owned()));
+                }
+            } else {
+                check_executable(binary_path)
+                    .map_err(|reason| TestError::InvalidBinary(binary_path.clone(), reason))?;
+            }
+        }
 
-    file.write_all(test.rest.trim_end().as_bytes())?;
-    writeln!(file, "")?;
-    writeln!(file, "")?;
+        if !self.test_path.exists() {
+            return Err(TestError::MissingTests(self.test_path.clone()));
+        }
+
+        let (mut tests, path_errors) = self.find_tests();
+        tests.sort();
+        let total = tests.len();
+
+        let mut problems = path_errors;
+        for test in tests {
+            problems.extend(self.check_test(&test));
+        }
 
-    if !test.command_line_args.is_empty() {
-        writeln!(file, "{} {}", config.test_args_prefix, test.command_line_args.trim())?;
+        let width = self.output_width();
+        for problem in &problems {
+            eprintln!("{}", problem.formatted(width, self.location_format));
+        }
+
+        let failures: Vec<_> = problems.into_iter().filter(InnerTestError::is_failure).collect();
+        println!(
+            "checked {} {} tests with {}\n",
+            total,
+            "golden".bright_yellow(),
+            format!("{} problems", failures.len()).red(),
+        );
+
+        if !failures.is_empty() {
+            Err(TestError::TestErrors(failures))
+        } else {
+            Ok(())
+        }
     }
 
-    if Some(0) != output.status.code() {
-        writeln!(
-            file,
-            "{} {}",
-            config.test_exit_status_prefix,
-            output.status.code().unwrap_or(0)
-        )?;
+    /// Parses a test without running it, returning each problem with it
+    fn check_test(&self, file: &Path) -> Vec<InnerTestError> {
+        let test = match parse_test(file, self) {
+            Ok(test) => test,
+            Err(error) => return vec![error],
+        };
+
+        let mut problems: Vec<_> = test
+            .warnings
+            .iter()
+            .map(|(line, keyword)| InnerTestError::UnknownDirective(test.path.clone(), line.clone(), keyword.clone()))
+            .collect();
+
+        if self.strict_discovery && !test.has_directives {
+            problems.push(InnerTestError::NoDirectives(test.path.clone()));
+        }
+
+        let args = test.command_line_args.trim();
+        if !self.shell && shlex::split(args).is_none() {
+            problems.push(InnerTestError::ErrorParsingArgs(test.path.clone(), args.to_owned()));
+        }
+
+        if let Some(Err(error)) = test.args_file.as_deref().map(read_args_file) {
+            problems.push(error);
+        }
+
+        let test_directory = test.path.parent().unwrap_or_else(|| Path::new(""));
+        for fixture in test.fixtures.iter().map(|fixture| test_directory.join(fixture)) {
+            if let Err(error) = std::fs::metadata(&fixture) {
+                problems.push(InnerTestError::IoError(fixture, error));
+            }
+        }
+        problems
     }
 
-    write_expected_output_for_stream(
-        &mut file,
-        &config.test_line_prefix,
-        &config.test_stdout_prefix,
-        &output.stdout,
-    )?;
-    write_expected_output_for_stream(
-        &mut file,
-        &config.test_line_prefix,
-        &config.test_stderr_prefix,
-        &output.stderr,
-    )
-}
+    /// Lints each test without running anything, printing and returning each suspicious line
+    /// found by `lint::lint_test`.
+    pub fn lint_tests(&self) -> TestResult<()> {
+        let (mut tests, path_errors) = self.find_tests();
+        tests.sort();
+        let total = tests.len();
 
-/// Diff the given "stream" and expected contents of the stream.
-/// Returns non-zero on error.
-fn check_for_differences_in_stream(name: &str, stream: &[u8], expected: &str, errors: &mut Vec<String>) {
-    let output_string = String::from_utf8_lossy(stream).replace("\r", "");
-    let output = output_string.trim();
-    let expected = expected.trim();
+        let mut lints = path_errors;
+        for test in tests {
+            lints.extend(lint_test(&test, self));
+        }
 
-    let differences = TextDiff::from_lines(expected, output);
-    if differences.ratio() != 1.0 {
-        errors.push(format!(
-            "Actual {} differs from expected {}:\n{}",
-            name,
-            name,
-            DiffPrinter(differences)
-        ));
+        let width = self.output_width();
+        for lint in &lints {
+            eprintln!("{}", lint.formatted(width, self.location_format));
+        }
+
+        println!(
+            "linted {} {} tests with {}\n",
+            total,
+            "golden".bright_yellow(),
+            format!("{} problems", lints.len()).red(),
+        );
+
+        if !lints.is_empty() {
+            Err(TestError::TestErrors(lints))
+        } else {
+            Ok(())
+        }
     }
-}
 
-fn check_exit_status(output: &Output, expected_status: Option<i32>, errors: &mut Vec<String>) {
-    if let Some(expected_status) = expected_status {
-        if let Some(actual_status) = output.status.code() {
-            if expected_status != actual_status {
-                errors.push(format!(
-                    "Expected an exit status of {} but process returned {}\n",
-                    expected_status, actual_status,
-                ));
+    /// Writes the actual output saved by `save_actual` on the last run as the expected output of
+    /// each test it was saved for, without running any tests. If a filter is given, only tests
+    /// whose path contains it are promoted. The saved output of each promoted test is removed.
+    pub fn promote_tests(&self, filter: Option<&str>) -> TestResult<()> {
+        let (mut tests, path_errors) = self.find_tests();
+        tests.retain(|test| filter.is_none_or(|filter| test.to_string_lossy().contains(filter)));
+        tests.sort();
+
+        let width = self.output_width();
+        for error in &path_errors {
+            eprintln!("{}", error.formatted(width, self.location_format));
+        }
+
+        let mut promoted = 0;
+        let mut failures = path_errors;
+        for test in tests {
+            match self.promote_test(&test) {
+                Ok(true) => {
+                    println!("promoted {}", test.display());
+                    promoted += 1;
+                }
+                Ok(false) => (),
+                Err(error) => {
+                    eprintln!("{}", error.formatted(width, self.location_format));
+                    failures.push(error);
+                }
             }
+        }
+
+        println!(
+            "promoted {} {} tests with {}\n",
+            promoted,
+            "golden".bright_yellow(),
+            format!("{} failing", failures.len()).red(),
+        );
+
+        if !failures.is_empty() {
+            Err(TestError::TestErrors(failures))
         } else {
-            errors.push(format!(
-                "Expected an exit status of {} but process was terminated by signal instead\n",
-                expected_status
-            ));
+            Ok(())
         }
     }
-}
 
-fn check_for_differences(path: &Path, output: &Output, test: &Test) -> InnerTestResult<()> {
-    let mut errors = vec![];
-    check_exit_status(output, test.expected_exit_status, &mut errors);
-    check_for_differences_in_stream("stdout", &output.stdout, &test.expected_stdout, &mut errors);
-    check_for_differences_in_stream("stderr", &output.stderr, &test.expected_stderr, &mut errors);
+    /// Overwrites the given test with its saved actual output. Returns false if there was none.
+    /// If the test failed in multiple environments, the output of the first is used.
+    fn promote_test(&self, file: &Path) -> InnerTestResult<bool> {
+        let test = parse_test(file, self)?;
+        let io_error = |error| InnerTestError::IoError(file.to_owned(), error);
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        let path = path.to_owned();
-        Err(InnerTestError::TestFailed { path, errors })
+        for environment in self.environments_for(&test)? {
+            let name = environment.map(|environment| environment.name.as_str());
+            if let Some(saved) = self.read_saved_output(file, name).map_err(io_error)? {
+                let SavedOutput {
+                    stdout,
+                    stderr,
+                    exit_status,
+                } = saved;
+                // Expected files aren't saved, so they are promoted as they were left by the last run
+                let actual = ActualOutput {
+                    stdout: self.normalize_output(&stdout, test.exact),
+                    stderr: self.normalize_output(&stderr, test.exact),
+                    output: Output {
+                        status: exit_status,
+                        stdout,
+                        stderr,
+                    },
+                    files: self.read_expected_files(&test),
+                    directories: self.read_expected_directories(&test),
+                };
+
+                overwrite_test(file, self, &actual, &test).map_err(io_error)?;
+                self.remove_artifacts(file, name);
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
-}
 
-#[cfg(feature = "parallel")]
-fn into_iter<T: IntoParallelIterator>(value: T) -> T::Iter {
-    value.into_par_iter()
-}
+    /// Writes the actual output of the given input file as its expected output. Returns false
+    /// if the file was skipped because it already contains test directives.
+    fn generate_test(&self, file: &Path) -> InnerTestResult<bool> {
+        let test = parse_test(file, self)?;
+        if test.has_directives {
+            return Ok(false);
+        }
 
-#[cfg(not(feature = "parallel"))]
-fn into_iter<T: IntoIterator>(value: T) -> T::IntoIter {
-    value.into_iter()
-}
+        // Inputs are only ran once, so the output of the first environment is used
+        let environment = self.environments_for(&test)?.into_iter().next().flatten();
+        match self.run_test(&test, environment, true) {
+            Ok(()) | Err(InnerTestError::TestUpdated { .. }) => Ok(true),
+            Err(error) => Err(error),
+        }
+    }
 
-impl TestConfig {
-    fn test_all(&self, test_sources: Vec<PathBuf>) -> Vec<InnerTestResult<()>> {
-        #[cfg(feature = "progress-bar")]
-        let progress = ProgressBar::new(test_sources.len() as u64);
+    /// Each of `reporters`, along with the reporter writing JSON messages if `message_format` asks for them
+    fn all_reporters(&self) -> impl Iterator<Item = &dyn Reporter> {
+        let json = (self.message_format == MessageFormat::Json).then_some(&JsonMessages as &dyn Reporter);
+        self.reporters.iter().map(Box::as_ref).chain(json)
+    }
 
-        let results = into_iter(test_sources)
-            .map(|file| {
-                #[cfg(feature = "progress-bar")]
-                progress.inc(1);
-                let test = parse_test(&file, self)?;
-                let mut args = vec![];
+    /// Runs each test within each of its environments, returning the results of each test file
+    pub(crate) fn test_all(&self, test_sources: Vec<PathBuf>) -> Vec<FileResults> {
+        #[cfg(feature = "progress-bar")]
+        let progress = match self.message_format {
+            // JSON messages already report progress to whatever is reading them
+            MessageFormat::Json => ProgressBar::hidden(),
+            // The message is the name of the most recently finished test
+            MessageFormat::Human => ProgressBar::new(test_sources.len() as u64)
+                .with_style(ProgressStyle::with_template("{wide_bar} {pos}/{len} {msg}").unwrap()),
+        };
 
-                // Avoid pushing an empty '' arg at the beginning
-                let trimmed_args = test.command_line_args.trim();
-                if !trimmed_args.is_empty() {
-                    args = shlex::split(trimmed_args)
-                        .ok_or_else(|| InnerTestError::ErrorParsingArgs(file.clone(), trimmed_args.to_owned()))?;
-                }
+        // A random order is meant to change which tests run before which, so keep it as is
+        let schedule = if self.order == TestOrder::Random {
+            test_sources.into_iter().enumerate().collect()
+        } else {
+            schedule_longest_first(test_sources, &self.load_durations())
+        };
 
-                args.push(test.path.to_string_lossy().to_string());
+        let locks = SerialLocks::default();
+        let mut results: Vec<(usize, FileResults)> = with_max_jobs(self.max_jobs, || {
+            into_iter(schedule)
+                .map(|(index, path)| {
+                    for reporter in self.all_reporters() {
+                        reporter.on_test_start(&path);
+                    }
 
-                let mut command = Command::new(&self.binary_path);
-                command.args(args);
-                let output =
-                    command.output().map_err(|err| InnerTestError::CommandError(file.clone(), command, err))?;
+                    let start = Instant::now();
+                    let (test, results) = self.test_file(&path, &locks);
+                    let duration = start.elapsed();
+                    let (name, description, issue) = match test {
+                        Some(test) => (test.name, test.description, test.issue),
+                        None => (None, None, None),
+                    };
 
-                let differences = check_for_differences(&test.path, &output, &test);
-                if self.overwrite_tests {
-                    if let Err(InnerTestError::TestFailed { path, errors }) = differences {
-                        overwrite_test(&file, self, &output, &test)
-                            .map_err(|err| InnerTestError::IoError(file.to_owned(), err))?;
+                    #[cfg(feature = "progress-bar")]
+                    {
+                        progress.set_message(name.clone().unwrap_or_else(|| path.display().to_string()));
+                        progress.inc(1);
+                    }
 
-                        return Err(InnerTestError::TestUpdated { path, errors });
+                    for reporter in self.all_reporters() {
+                        for (environment, result) in &results {
+                            reporter.on_test_complete(&path, environment.as_deref(), result, duration);
+                        }
                     }
-                }
-                differences
-            })
-            .collect();
+                    (
+                        index,
+                        FileResults {
+                            path,
+                            name,
+                            description,
+                            issue,
+                            duration,
+                            results,
+                        },
+                    )
+                })
+                .collect()
+        });
 
         #[cfg(feature = "progress-bar")]
         progress.finish_and_clear();
-        results
+
+        // Report the results in the original order rather than the order they finished in
+        results.sort_by_key(|(index, _)| *index);
+        self.save_durations(results.iter().map(|(_, file)| (file.path.as_path(), file.duration)));
+        results.into_iter().map(|(_, file)| file).collect()
+    }
+
+    /// Lists the `count` slowest of the given tests followed by the total time taken
+    fn report_durations(&self, count: usize, files: &[FileResults], total: Duration) {
+        let mut files: Vec<_> = files.iter().collect();
+        files.sort_by_key(|file| std::cmp::Reverse(file.duration));
+        let count = count.min(files.len());
+
+        summary!(self, "slowest {} of {} test files:", count, files.len());
+        for file in &files[..count] {
+            summary!(
+                self,
+                "  {} {}",
+                format!("{:>8.2}s", file.duration.as_secs_f64()).cyan(),
+                file.path.display()
+            );
+        }
+        summary!(self, "finished in {:.2}s\n", total.as_secs_f64());
+    }
+
+    /// Prints the number of passing and failing tests within each directory, relative to `test_path`
+    fn report_directories(&self, files: &[FileResults]) {
+        let mut directories: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for file in files {
+            let relative_path = file.path.strip_prefix(&self.test_path).unwrap_or(&file.path);
+            let directory = match relative_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                Some(parent) => format!("{}/", parent.display()),
+                None => "./".to_owned(),
+            };
+
+            let (total, failing) = directories.entry(directory).or_default();
+            for (_, result) in file.results.iter().filter(|(_, result)| !is_warning(result)) {
+                *total += 1;
+                *failing += matches!(result, Err(error) if error.is_failure()) as usize;
+            }
+        }
+
+        let width = directories.keys().map(|directory| directory.chars().count()).max().unwrap_or(0);
+        summary!(self, "by directory:");
+        for (directory, (total, failing)) in directories {
+            let failing_text = format!("{} failing", failing);
+            summary!(
+                self,
+                "  {:<width$}  {} and {}",
+                directory,
+                format!("{} passing", total - failing).green(),
+                if failing > 0 {
+                    failing_text.red()
+                } else {
+                    failing_text.normal()
+                },
+                width = width
+            );
+        }
+        summary!(self);
+    }
+
+    /// Parses the given test file and runs it within each of its environments, returning
+    /// the parsed test along with the results
+    fn test_file(&self, file: &Path, locks: &SerialLocks) -> (Option<Test>, RunResults) {
+        let test = match parse_test(file, self) {
+            Ok(test) => test,
+            Err(error) => return (None, vec![(None, Err(error))]),
+        };
+
+        if self.strict_discovery && !test.has_directives {
+            let error = InnerTestError::NoDirectives(test.path.clone());
+            return (Some(test), vec![(None, Err(error))]);
+        }
+
+        // The names are sorted, so tests holding several of the same locks always take them in the same order
+        let locks: Vec<_> = test.serial.iter().map(|name| locks.get(name)).collect();
+        let _guards: Vec<_> = locks.iter().map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner)).collect();
+
+        let warnings = test.warnings.iter().map(|(line, keyword)| {
+            let error = InnerTestError::UnknownDirective(test.path.clone(), line.clone(), keyword.clone());
+            (None, Err(error))
+        });
+        let mut results: Vec<_> = warnings.collect();
+
+        // Only the first environment may overwrite a test so that multiple
+        // environments don't race to write to the same file.
+        let environments = match self.environments_for(&test) {
+            Ok(environments) => environments,
+            Err(error) => {
+                results.push((None, Err(error)));
+                return (Some(test), results);
+            }
+        };
+        results.extend(environments.into_iter().enumerate().map(|(i, environment)| {
+            let overwrite = self.should_overwrite() && i == 0;
+            let name = environment.map(|environment| environment.name.clone());
+            let result = match unmet_requirement(&test, environment) {
+                Some(reason) => Err(InnerTestError::Skipped(test.path.clone(), reason)),
+                None => self.run_test(&test, environment, overwrite),
+            };
+            (name, result)
+        }));
+        (Some(test), results)
     }
 
     /// Recurse through all the files in self.path, parse them all,
     /// and run the target program with the arguments specified in the file.
     pub fn run_tests(&self) -> TestResult<()> {
-        let (tests, path_errors) = find_tests(&self.test_path);
-        let outputs = self.test_all(tests);
+        self.run_setup()?;
+        let (tests, path_errors) = self.find_tests();
+        self.run_found_tests(tests, path_errors)
+    }
+
+    /// Runs exactly the given test files, in the same way as `run_tests` but without searching
+    /// `test_path` or using `test_files`, e.g. to run tests one at a time while bisecting. Options
+    /// which select tests, such as `rerun_failed` and `incremental`, still apply.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let config = TestConfig::new("python", "examples", "# ")?;
+    /// for test in ["examples/multiline.py", "examples/contains.py"] {
+    ///     config.run_files(&[test.into()])?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_files(&self, files: &[PathBuf]) -> TestResult<()> {
+        self.run_setup()?;
+        self.run_found_tests(files.to_vec(), Vec::new())
+    }
+
+    /// Runs the given tests, reporting the given errors from finding them alongside their results
+    fn run_found_tests(&self, mut tests: Vec<PathBuf>, path_errors: Vec<InnerTestError>) -> TestResult<()> {
+        self.order_tests(&mut tests);
+
+        if self.rerun_failed {
+            let failures = self.load_failures();
+            if tests.iter().any(|test| failures.contains(test)) {
+                tests.retain(|test| failures.contains(test));
+            }
+        }
+
+        let mut incremental = self.incremental.then(|| Incremental::new(self));
+        let unchanged_tests = match &incremental {
+            Some(incremental) => {
+                let count = tests.len();
+                tests.retain(|test| !incremental.is_unchanged(self, test));
+                count - tests.len()
+            }
+            None => 0,
+        };
 
+        let start = Instant::now();
+        let mut files = self.test_all(tests);
+        let total_time = start.elapsed();
+
+        if self.record_baseline {
+            self.record_baseline(&files);
+        } else {
+            self.check_duration_regressions(&mut files);
+        }
+        self.save_failures(files.iter().map(|file| (file.path.as_path(), !file.passed())));
+        let previous_results = self.compare.then(|| self.load_results());
+        self.save_results(files.iter().map(|file| (file.path.as_path(), file.passed())));
+
+        if let Some(incremental) = &mut incremental {
+            for file in &files {
+                // Skipped tests haven't passed, so they're ran again once their requirements are met
+                let skipped = file.results.iter().any(|(_, result)| matches!(result, Err(InnerTestError::Skipped(..))));
+                incremental.record(self, &file.path, file.passed() && !skipped);
+            }
+            incremental.save(self);
+        }
+
+        // Quarantined tests are still recorded as failing above so that they keep being ran
+        for file in files.iter_mut().filter(|file| self.is_quarantined(&file.path)) {
+            for (_, result) in &mut file.results {
+                if result.as_ref().is_err_and(InnerTestError::is_failure) {
+                    if let Err(error) = std::mem::replace(result, Ok(())) {
+                        *result = Err(InnerTestError::Quarantined(Box::new(error)));
+                    }
+                }
+            }
+        }
+
+        let outputs: Vec<_> = files.iter().flat_map(|file| &file.results).collect();
+        // The test file of each output, so that failures can be shown with the name of their test
+        let output_files: Vec<&FileResults> =
+            files.iter().flat_map(|file| file.results.iter().map(move |_| file)).collect();
+
+        let width = self.output_width();
         for error in path_errors {
-            eprintln!("{}", error);
+            eprintln!("{}", error.formatted(width, self.location_format));
         }
 
-        let total_tests = outputs.len();
+        let groups = group_identical_failures(&outputs);
+        let grouped: HashSet<usize> = groups.iter().flatten().copied().collect();
+
+        let is_skipped = |result: &InnerTestResult<()>| matches!(result, Err(InnerTestError::Skipped(..)));
+        let total_tests = outputs.iter().filter(|(_, result)| !is_warning(result) && !is_skipped(result)).count();
         let mut failing_tests = 0;
         let mut can_be_fixed_with_overwrite_tests = 0;
         let mut updated_tests = 0;
-        for result in &outputs {
+        let mut noncanonical_tests = 0;
+        let mut allowed_difference_tests = 0;
+        let mut quarantined_tests = 0;
+        let mut skipped_tests = 0;
+        for (index, (environment, result)) in outputs.iter().enumerate() {
             match result {
                 Ok(_) => {}
                 Err(InnerTestError::TestUpdated { .. }) => {
                     updated_tests += 1;
                 }
 
+                Err(InnerTestError::NeedsCanonicalization(_)) => {
+                    noncanonical_tests += 1;
+                }
+
+                Err(InnerTestError::AllowedDifferences { .. }) => {
+                    allowed_difference_tests += 1;
+                }
+
+                Err(InnerTestError::Quarantined(_)) => {
+                    quarantined_tests += 1;
+                }
+
+                Err(InnerTestError::Skipped(..)) => {
+                    skipped_tests += 1;
+                }
+
+                Err(InnerTestError::UnknownDirective(..) | InnerTestError::DurationRegressed { fail: false, .. }) => {}
+
                 Err(InnerTestError::TestFailed { .. }) => {
                     can_be_fixed_with_overwrite_tests += 1;
                     failing_tests += 1;
@@ -348,20 +2386,63 @@ impl TestConfig {
                 Err(
                     InnerTestError::IoError(_, _)
                     | InnerTestError::CommandError(_, _, _)
-                    | InnerTestError::ErrorParsingExitStatus(_, _, _)
-                    | InnerTestError::ErrorParsingArgs(_, _),
+                    | InnerTestError::ResourceLimitExceeded(_, _)
+                    | InnerTestError::ShellCommandFailed(_, _, _)
+                    | InnerTestError::NoDirectives(_)
+                    | InnerTestError::NoEnvironments(_)
+                    | InnerTestError::Lint(_, _, _)
+                    | InnerTestError::DurationRegressed { fail: true, .. }
+                    | InnerTestError::ErrorParsingExitStatus(..)
+                    | InnerTestError::ErrorParsingArgs(_, _)
+                    | InnerTestError::ErrorParsingQualifier(..)
+                    | InnerTestError::ErrorParsingDirective(..),
                 ) => {
                     failing_tests += 1;
                 }
             }
 
             if let Err(err) = result {
-                eprintln!("{}", err)
+                if grouped.contains(&index) {
+                    continue;
+                }
+                let file = output_files[index];
+                let mut message = err.formatted(width, self.location_format).named(file.name.as_deref()).to_string();
+                if let Some(issue) = file.issue.as_ref().filter(|_| is_known_failure(err)) {
+                    message = format!("{}\n{} {}\n", message.trim_end(), "issue:".cyan(), issue);
+                }
+                match environment {
+                    Some(environment) => eprintln!("{} {}", format!("[{}]", environment).cyan(), message),
+                    None => eprintln!("{}", message),
+                }
+            }
+        }
+
+        for group in &groups {
+            eprintln!(
+                "{} tests failed with the same differences:",
+                group.len().to_string().red()
+            );
+            for &index in group {
+                let (environment, _) = outputs[index];
+                let file = output_files[index];
+                let mut path = file.title().bright_yellow().to_string();
+                if let Some(issue) = &file.issue {
+                    path = format!("{} ({} {})", path, "issue:".cyan(), issue);
+                }
+                match environment {
+                    Some(environment) => eprintln!("  {} {}", format!("[{}]", environment).cyan(), path),
+                    None => eprintln!("  {}", path),
+                }
+            }
+            let (_, result) = outputs[group[0]];
+            for mismatch in result.as_ref().unwrap_err().mismatches() {
+                eprintln!("{}", mismatch);
             }
         }
 
-        if !self.overwrite_tests {
-            println!(
+        if !self.should_overwrite() {
+            summary!(
+                self,
                 "ran {} {} tests with {} and {}\n",
                 total_tests,
                 "golden".bright_yellow(),
@@ -369,7 +2450,8 @@ impl TestConfig {
                 format!("{} failing", failing_tests).red(),
             );
         } else {
-            println!(
+            summary!(
+                self,
                 "ran {} {} tests with {}, {} and {}\n",
                 total_tests,
                 "golden".bright_yellow(),
@@ -379,12 +2461,110 @@ impl TestConfig {
             );
         }
 
-        if can_be_fixed_with_overwrite_tests > 0 {
-            println!("Looks like you have failing tests. Review the output of each and fix any unexpected differences. When finished, you can use the --overwrite flag to automatically write the new output to the {} failing test file(s)", can_be_fixed_with_overwrite_tests);
+        for environment in &self.environments {
+            let results = outputs.iter().filter(|(name, _)| name.as_ref() == Some(&environment.name));
+            let (total, failing) = results.fold((0, 0), |(total, failing), (_, result)| {
+                let failed = matches!(result, Err(error) if error.is_failure());
+                (total + 1, failing + failed as usize)
+            });
+
+            summary!(
+                self,
+                "{}: {} and {}",
+                format!("[{}]", environment.name).cyan(),
+                format!("{} passing", total - failing).green(),
+                format!("{} failing", failing).red(),
+            );
+        }
+
+        if !self.environments.is_empty() {
+            summary!(self);
+        }
+
+        if let Some(previous_results) = &previous_results {
+            report_comparison(self, previous_results, &files);
+        }
+
+        if self.summary_by_directory {
+            self.report_directories(&files);
+        }
+
+        if let Some(count) = self.report_time {
+            self.report_durations(count, &files, total_time);
+        }
+
+        self.write_reports(&files, total_time);
+
+        let wrap = |text: String| match width {
+            Some(width) => terminal::wrap_words(&text, terminal::clamp_width(width)),
+            None => text,
+        };
+
+        // Failures against a reference binary can't be fixed by overwriting the expected output
+        if can_be_fixed_with_overwrite_tests > 0 && self.reference_binary.is_none() {
+            let how = match &self.overwrite_env_var {
+                Some(var) => format!("use the --overwrite flag or set {}=1", var),
+                None => "use the --overwrite flag".to_owned(),
+            };
+            summary!(self, "{}", wrap(format!("Looks like you have failing tests. Review the output of each and fix any unexpected differences. When finished, you can {} to automatically write the new output to the {} failing test file(s)", how, can_be_fixed_with_overwrite_tests)));
+        }
+
+        if noncanonical_tests > 0 {
+            summary!(self, "{}", wrap(format!("{} passing test(s) would be rewritten by --overwrite because their expected output is not in canonical form. Consider running with --overwrite to canonicalize them so future diffs aren't confusing", noncanonical_tests)));
+        }
+
+        if allowed_difference_tests > 0 {
+            summary!(self, "{}", wrap(format!("{} passing test(s) only passed because their differences were allowed by an \"allow diff:\" directive", allowed_difference_tests)));
+        }
+
+        if quarantined_tests > 0 {
+            summary!(
+                self,
+                "{}",
+                wrap(format!(
+                    "{} quarantined test(s) failed, which doesn't fail the run",
+                    quarantined_tests
+                ))
+            );
+        }
+
+        if skipped_tests > 0 {
+            summary!(
+                self,
+                "{}",
+                wrap(format!(
+                    "{} test(s) were skipped because a \"requires:\" condition wasn't met",
+                    skipped_tests
+                ))
+            );
+        }
+
+        if unchanged_tests > 0 {
+            summary!(
+                self,
+                "{}",
+                wrap(format!(
+                    "{} test(s) were skipped because they passed on their last run and haven't changed since",
+                    unchanged_tests
+                ))
+            );
+        }
+
+        let summary = RunSummary {
+            total: total_tests,
+            passing: total_tests - failing_tests,
+            failing: failing_tests,
+            updated: updated_tests,
+            duration: total_time,
+        };
+        for reporter in self.all_reporters() {
+            reporter.on_run_complete(&summary);
         }
 
         if failing_tests != 0 {
-            Err(TestError::TestErrors)
+            let results = files.into_iter().flat_map(|file| file.results);
+            let failures = results.filter_map(|(_, result)| result.err()).filter(InnerTestError::is_failure);
+            Err(TestError::TestErrors(failures.collect()))
         } else {
             Ok(())
         }
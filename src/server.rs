@@ -0,0 +1,233 @@
+//! A long-running server which editors and other tools send requests to list and run tests to, so
+//! that they don't start a new process and find every test again for each request. See `TestConfig::serve`.
+use crate::config::TestConfig;
+use crate::error::{TestError, TestResult};
+use crate::parser::parse_test;
+use crate::reporter::{self, Reporter, RunSummary};
+use crate::runner::is_warning;
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Where responses and notifications are written, shared with the threads tests are ran on
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+// Error codes defined by JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// The code and message of a request which failed
+struct RequestError(i64, String);
+
+/// Writes a single message to the output on a line of its own
+fn send(output: &Output, message: Value) {
+    let mut output = output.lock().unwrap_or_else(|error| error.into_inner());
+    let _ = writeln!(output, "{}", message).and_then(|()| output.flush());
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Sends a notification as each test starts and finishes during a `run` request
+struct Notifications(Output);
+
+impl Reporter for Notifications {
+    fn on_test_start(&self, path: &Path) {
+        send(&self.0, notification("test/started", reporter::test_started(path)));
+    }
+
+    fn on_test_complete(
+        &self,
+        path: &Path,
+        environment: Option<&str>,
+        result: &Result<(), crate::error::InnerTestError>,
+        duration: Duration,
+    ) {
+        let finished = reporter::test_finished(path, environment, result, duration);
+        send(&self.0, notification("test/finished", finished));
+    }
+}
+
+/// The metadata of a test given by `list`, which is reused until the test is modified
+struct CachedTest {
+    modified: SystemTime,
+    metadata: Value,
+}
+
+/// What the server remembers between requests
+#[derive(Default)]
+struct Server {
+    /// Every test found, if they have been found yet
+    tests: Option<Vec<PathBuf>>,
+    cache: HashMap<PathBuf, CachedTest>,
+}
+
+impl Server {
+    /// The tests found on the first request, or found again if `refresh` is set
+    fn tests(&mut self, config: &TestConfig, refresh: bool) -> Result<Vec<PathBuf>, RequestError> {
+        if refresh || self.tests.is_none() {
+            let tests = config.list_tests().map_err(|error| RequestError(SERVER_ERROR, error.to_string()))?;
+            self.tests = Some(tests);
+        }
+        Ok(self.tests.clone().unwrap_or_default())
+    }
+
+    fn list(&mut self, config: &TestConfig, params: &Value) -> Result<Value, RequestError> {
+        let refresh = params.get("refresh").and_then(Value::as_bool).unwrap_or(false);
+        let tests = self.tests(config, refresh)?;
+        Ok(tests.iter().map(|path| self.metadata(config, path)).collect())
+    }
+
+    /// The path of the test and the name of each environment it runs within, or the
+    /// reason it couldn't be parsed or has no environment to run within
+    fn metadata(&mut self, config: &TestConfig, path: &Path) -> Value {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        if let Some(cached) = self.cache.get(path).filter(|cached| Some(cached.modified) == modified) {
+            return cached.metadata.clone();
+        }
+
+        let metadata = match parse_test(path, config).and_then(|test| config.environments_for(&test)) {
+            Ok(environments) => {
+                let environments = environments.into_iter().flatten();
+                let environments: Vec<&str> = environments.map(|environment| environment.name.as_str()).collect();
+                json!({ "path": path, "environments": environments })
+            }
+            Err(error) => {
+                let error = crate::colors::strip_colors(&error.to_string()).trim().to_owned();
+                json!({ "path": path, "error": error })
+            }
+        };
+
+        if let Some(modified) = modified {
+            let cached = CachedTest {
+                modified,
+                metadata: metadata.clone(),
+            };
+            self.cache.insert(path.to_owned(), cached);
+        }
+        metadata
+    }
+
+    fn run(&mut self, config: &TestConfig, params: &Value) -> Result<Value, RequestError> {
+        let tests = match params.get("tests") {
+            None | Some(Value::Null) => self.tests(config, false)?,
+            Some(Value::Array(tests)) => tests
+                .iter()
+                .map(|test| test.as_str().map(PathBuf::from))
+                .collect::<Option<_>>()
+                .ok_or_else(|| RequestError(INVALID_PARAMS, "expected each test to be a path".to_owned()))?,
+            Some(_) => {
+                let message = "expected 'tests' to be an array of paths".to_owned();
+                return Err(RequestError(INVALID_PARAMS, message));
+            }
+        };
+
+        // The setup command usually builds the binary, which may have changed since the last run
+        config.run_setup().map_err(|error| RequestError(SERVER_ERROR, error.to_string()))?;
+
+        let start = Instant::now();
+        let files = config.test_all(tests);
+        let mut summary = RunSummary {
+            total: 0,
+            passing: 0,
+            failing: 0,
+            updated: 0,
+            duration: start.elapsed(),
+        };
+        for (_, result) in files.iter().flat_map(|file| &file.results).filter(|(_, result)| !is_warning(result)) {
+            summary.total += 1;
+            match result {
+                Err(error) if error.is_failure() => summary.failing += 1,
+                Err(crate::error::InnerTestError::TestUpdated { .. }) => {
+                    summary.updated += 1;
+                    summary.passing += 1;
+                }
+                _ => summary.passing += 1,
+            }
+        }
+        Ok(reporter::run_finished(&summary))
+    }
+}
+
+impl TestConfig {
+    /// Serves requests to list and run tests, read from `input` as JSON-RPC 2.0 with one message per line,
+    /// until `input` ends or an `exit` request is received. Responses and notifications are written to
+    /// `output` one per line. Tests are only found once, and the directives of each test are only parsed
+    /// again once it is modified. The methods are:
+    ///
+    /// - `list`: responds with the `path` and `environments` of each test, or an `error` if it couldn't be
+    ///   parsed. Pass `{"refresh": true}` to find the tests again, e.g. after adding one.
+    /// - `run`: runs the tests given by `{"tests": [...]}`, or every test, sending a `test/started` and
+    ///   `test/finished` notification for each in the format of `reporter::JsonMessages`. Responds with the
+    ///   totals of the run once every test has finished.
+    /// - `exit`: responds with null and stops the server.
+    ///
+    /// ```rust
+    /// # use goldentests::{TestConfig, TestResult};
+    /// # fn main() -> TestResult<()> {
+    /// let config = TestConfig::new("python", "examples", "# ")?;
+    /// let requests = r#"{"jsonrpc": "2.0", "id": 1, "method": "list"}"#;
+    /// config.serve(requests.as_bytes(), std::io::sink())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve(mut self, input: impl BufRead, output: impl Write + Send + 'static) -> TestResult<()> {
+        let output: Output = Arc::new(Mutex::new(output));
+        self.reporters.push(Box::new(Notifications(output.clone())));
+        let mut server = Server::default();
+
+        for line in input.lines() {
+            let line = line.map_err(|error| TestError::IoError(PathBuf::from("<input>"), error))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(error) => {
+                    send(
+                        &output,
+                        response(Value::Null, Err(RequestError(PARSE_ERROR, error.to_string()))),
+                    );
+                    continue;
+                }
+            };
+
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            let method = request.get("method").and_then(Value::as_str);
+            let result = match method {
+                Some("list") => server.list(&self, &params),
+                Some("run") => server.run(&self, &params),
+                Some("exit") => Ok(Value::Null),
+                Some(other) => Err(RequestError(METHOD_NOT_FOUND, format!("unknown method '{}'", other))),
+                None => Err(RequestError(INVALID_REQUEST, "expected a method".to_owned())),
+            };
+
+            // Requests without an id are notifications, which aren't responded to
+            if let Some(id) = request.get("id") {
+                send(&output, response(id.clone(), result));
+            }
+            if method == Some("exit") {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn response(id: Value, result: Result<Value, RequestError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RequestError(code, message)) => {
+            json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+        }
+    }
+}
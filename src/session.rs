@@ -0,0 +1,182 @@
+//! Running a test as an interactive session with the binary, for testing REPLs and other programs
+//! which read their input a line at a time. Each `send:` line is written to the stdin of the binary,
+//! and each `expect:` waits until its stdout contains the given text. See `TestConfig::test_send_prefix`.
+use crate::command_runner::Invocation;
+use crate::parser::SessionStep;
+
+use std::io::{Read, Write};
+use std::process::{Child, ExitStatus, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// The first step of a session which failed. Later steps are not ran.
+pub(crate) struct SessionFailure {
+    pub message: String,
+
+    /// The text an `expect:` step waited for, along with the stdout received since the previous
+    /// `expect:`, if the failure was in an `expect:` step
+    pub expected: Option<(String, String)>,
+}
+
+/// Runs the binary of the invocation locally, stepping through the session as it runs. After the last
+/// step, stdin is closed and the binary must exit within `timeout`. The binary is killed once any step
+/// fails. `normalize` is applied to stdout before searching it for the text of each `expect:`.
+pub(crate) fn run_session(
+    invocation: &Invocation,
+    steps: &[SessionStep],
+    timeout: Duration,
+    normalize: &dyn Fn(&[u8]) -> String,
+) -> std::io::Result<(Output, Option<SessionFailure>)> {
+    let mut command = invocation.command()?;
+    command.stdin(Stdio::piped());
+    #[cfg(unix)]
+    crate::process_group::isolate(&mut command);
+
+    let (mut child, stdout, stderr) = if invocation.combine_output {
+        let (reader, writer) = std::io::pipe()?;
+        command.stdout(writer.try_clone()?).stderr(writer);
+        let child = command.spawn()?;
+
+        // The command holds our copies of the write end, which must be closed for
+        // reading to finish once the child exits
+        drop(command);
+        (child, read_chunks(reader), None)
+    } else {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn()?;
+        let stdout = read_chunks(child.stdout.take().unwrap());
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr = std::thread::spawn(move || {
+            let mut output = Vec::new();
+            stderr.read_to_end(&mut output).map(|_| output)
+        });
+        (child, stdout, Some(stderr))
+    };
+
+    #[cfg(unix)]
+    let group = crate::process_group::ProcessGroup::of(&child);
+
+    let mut stdin = child.stdin.take();
+    let mut received = Vec::new();
+    let mut failure = None;
+
+    // The position in the normalized stdout after the text of the last `expect:`
+    let mut cursor = 0;
+
+    for step in steps {
+        match step {
+            SessionStep::Send(line) => {
+                // A binary which already exited can't be written to, which is reported by the
+                // next `expect:` or the exit status instead
+                if let Some(stdin) = &mut stdin {
+                    let _ = writeln!(stdin, "{}", line).and_then(|()| stdin.flush());
+                }
+            }
+            SessionStep::Expect {
+                text,
+                timeout: step_timeout,
+            } => {
+                let step_timeout = step_timeout.unwrap_or(timeout);
+                let deadline = Instant::now() + step_timeout;
+                loop {
+                    let output = normalize(&received);
+                    let rest = output.get(cursor..).unwrap_or_default();
+                    if let Some(index) = rest.find(text.as_str()) {
+                        cursor += index + text.len();
+                        break;
+                    }
+
+                    match stdout.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(chunk) => received.extend(chunk),
+                        Err(error) => {
+                            let message = match error {
+                                RecvTimeoutError::Timeout => {
+                                    format!("Timed out after {:?} waiting for stdout to contain:", step_timeout)
+                                }
+                                RecvTimeoutError::Disconnected => "Stdout ended before it contained:".to_owned(),
+                            };
+                            let expected = Some((text.clone(), rest.to_owned()));
+                            failure = Some(SessionFailure { message, expected });
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if failure.is_some() {
+            break;
+        }
+    }
+
+    // Closing stdin tells the binary its input has ended, which most REPLs exit on
+    drop(stdin);
+    let status = match failure {
+        Some(_) => None,
+        None => wait_until(&mut child, Instant::now() + timeout)?,
+    };
+    let status = match status {
+        Some(status) => status,
+        None => {
+            if failure.is_none() {
+                let message = format!("Did not exit within {:?} after its stdin was closed\n", timeout);
+                failure = Some(SessionFailure {
+                    message,
+                    expected: None,
+                });
+            }
+            let _ = child.kill();
+            child.wait()?
+        }
+    };
+
+    // Kill anything the binary left running so that the output pipes are closed
+    #[cfg(unix)]
+    drop(group);
+
+    received.extend(stdout.iter().flatten());
+    let stderr = match stderr {
+        Some(stderr) => stderr.join().unwrap_or_else(|_| Ok(Vec::new()))?,
+        None => Vec::new(),
+    };
+
+    let output = Output {
+        status,
+        stdout: received,
+        stderr,
+    };
+    Ok((output, failure))
+}
+
+/// Sends each chunk read to the returned receiver, which disconnects once the reader is closed
+fn read_chunks(mut reader: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => {
+                    if sender.send(buffer[..length].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+    receiver
+}
+
+/// Waits for the child to exit, returning None if it is still running at the deadline
+fn wait_until(child: &mut Child, deadline: Instant) -> std::io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,77 @@
+//! Running user-provided command lines through the system shell.
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// The system shell and the flag it is given before a command line to run
+pub(crate) fn system_shell() -> (&'static str, &'static str) {
+    if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    }
+}
+
+/// Creates a command which runs the given command line through the system shell
+pub(crate) fn shell_command(command_line: &str) -> Command {
+    let (shell, flag) = system_shell();
+    let mut command = Command::new(shell);
+    command.arg(flag).arg(command_line);
+    command
+}
+
+/// Quotes the given word so that the shell passes it through as a single argument
+pub(crate) fn quote(word: &str) -> String {
+    // Quoting only fails for words containing nul bytes, which can't be passed as an argument anyway
+    shlex::try_quote(word).map_or_else(|_| word.to_owned(), |quoted| quoted.into_owned())
+}
+
+/// Runs the given command to completion. If it couldn't be started or exits unsuccessfully,
+/// returns the reason why along with anything it wrote to stderr.
+pub(crate) fn run_to_completion(command: &mut Command) -> Result<(), String> {
+    let output = command.stdin(Stdio::null()).output().map_err(|err| err.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(failure_reason(&output))
+}
+
+/// Runs the given command with `input` piped into its stdin, returning its stdout. Fails in the
+/// same way as `run_to_completion`.
+pub(crate) fn pipe_through(command: &mut Command, input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    // The input is written from another thread so a command which writes output before reading
+    // all of its input can't deadlock. A command which doesn't read its input at all is fine too.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    let _ = writer.join();
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(failure_reason(&output))
+    }
+}
+
+/// Why the given command failed, along with anything it wrote to stderr
+fn failure_reason(output: &Output) -> String {
+    let mut reason = match output.status.code() {
+        Some(code) => format!("exited with status {}", code),
+        None => "terminated by signal".to_owned(),
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        reason += &format!(":\n{}", stderr.trim_end());
+    }
+    reason
+}
@@ -0,0 +1,118 @@
+//! State persisted between test runs within `TestConfig::state_directory`.
+//!
+//! This state is only ever used as a hint, so any errors while reading it are ignored
+//! and it is treated as missing instead.
+use crate::colors::Colorize;
+use crate::config::TestConfig;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Maps the path of each test to how many seconds it took to run
+type Durations = BTreeMap<PathBuf, f64>;
+
+const DURATIONS_FILE: &str = "durations.json";
+/// Maps the path of each test to whether it passed the last time it was ran
+pub(crate) type Results = BTreeMap<PathBuf, bool>;
+
+const FAILURES_FILE: &str = "last-failures.json";
+const RESULTS_FILE: &str = "last-results.json";
+
+impl TestConfig {
+    fn state_file(&self, name: &str) -> Option<PathBuf> {
+        self.state_directory.as_ref().map(|directory| directory.join(name))
+    }
+
+    /// Reads the state file with the given name, treating it as empty if it can't be read
+    pub(crate) fn read_state<T: DeserializeOwned + Default>(&self, name: &str) -> T {
+        self.state_file(name)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the state file with the given name, warning if it can't be written
+    pub(crate) fn write_state<T: Serialize>(&self, name: &str, state: &T) {
+        let path = match self.state_file(name) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let write = || -> std::io::Result<()> {
+            if let Some(directory) = path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::write(&path, serde_json::to_string_pretty(state)?)
+        };
+
+        if let Err(error) = write() {
+            let warning = format!("warning: failed to write {}: {}", path.display(), error);
+            eprintln!("{}", warning.as_str().bright_yellow());
+        }
+    }
+
+    /// Returns how long each test took to run the last time it was ran
+    pub(crate) fn load_durations(&self) -> BTreeMap<PathBuf, Duration> {
+        let durations = self.read_state::<Durations>(DURATIONS_FILE).into_iter();
+        durations.filter_map(|(path, secs)| Some((path, Duration::try_from_secs_f64(secs).ok()?))).collect()
+    }
+
+    /// Records how long each of the given tests took to run, keeping the durations
+    /// of any tests which weren't ran this time.
+    pub(crate) fn save_durations<'a>(&self, new_durations: impl Iterator<Item = (&'a Path, Duration)>) {
+        if self.state_directory.is_none() {
+            return;
+        }
+
+        let mut durations: Durations = self.read_state(DURATIONS_FILE);
+        for (test, duration) in new_durations {
+            durations.insert(test.to_owned(), duration.as_secs_f64());
+        }
+        self.write_state(DURATIONS_FILE, &durations);
+    }
+
+    /// Returns each test which failed on the last run
+    pub(crate) fn load_failures(&self) -> BTreeSet<PathBuf> {
+        self.read_state(FAILURES_FILE)
+    }
+
+    /// Records whether each of the given tests failed, keeping any failures
+    /// of tests which weren't ran this time.
+    pub(crate) fn save_failures<'a>(&self, results: impl Iterator<Item = (&'a Path, bool)>) {
+        if self.state_directory.is_none() {
+            return;
+        }
+
+        let mut failures = self.load_failures();
+        for (test, failed) in results {
+            if failed {
+                failures.insert(test.to_owned());
+            } else {
+                failures.remove(test);
+            }
+        }
+        self.write_state(FAILURES_FILE, &failures);
+    }
+
+    /// Returns whether each test passed the last time it was ran
+    pub(crate) fn load_results(&self) -> Results {
+        self.read_state(RESULTS_FILE)
+    }
+
+    /// Records whether each of the given tests passed, keeping the results of tests which
+    /// weren't ran this time.
+    pub(crate) fn save_results<'a>(&self, new_results: impl Iterator<Item = (&'a Path, bool)>) {
+        if self.state_directory.is_none() {
+            return;
+        }
+
+        let mut results = self.load_results();
+        for (test, passed) in new_results {
+            results.insert(test.to_owned(), passed);
+        }
+        self.write_state(RESULTS_FILE, &results);
+    }
+}
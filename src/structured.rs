@@ -0,0 +1,133 @@
+//! Comparing output as structured data rather than as text, so that a test doesn't fail because of
+//! differences in formatting or key order alone. See `TestConfig::comparison`.
+use crate::config::Comparison;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// How expected and actual output compared as structured data differ
+pub(crate) enum Difference {
+    /// The expected or actual output couldn't be parsed
+    Invalid { actual: bool, error: String },
+
+    /// Both were parsed but differ at each of the given paths. `expected` and `actual` are
+    /// the parsed output printed in a canonical form, to be shown as a diff.
+    Differs {
+        paths: Vec<String>,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Parses the expected and actual output with the given comparison, then compares them after
+/// removing each ignored field. YAML and TOML are compared as their equivalent JSON. An ignored field is either a JSON pointer such as `/build/id`,
+/// removing only that field, or a key name such as `timestamp`, removing that key from every object.
+///
+/// Output containing several documents, e.g. JSON lines, is compared as an array of them.
+pub(crate) fn compare(
+    comparison: Comparison,
+    expected: &str,
+    actual: &str,
+    ignored: &[String],
+) -> Result<(), Difference> {
+    let parse = |text, actual| {
+        let mut value = parse(comparison, text).map_err(|error| Difference::Invalid { actual, error })?;
+        for field in ignored {
+            remove_field(&mut value, field);
+        }
+        Ok(value)
+    };
+    let expected = parse(expected, false)?;
+    let actual = parse(actual, true)?;
+
+    let mut paths = Vec::new();
+    find_differences(&expected, &actual, &mut String::new(), &mut paths);
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let print = |value: &Value| serde_json::to_string_pretty(value).unwrap_or_default();
+    Err(Difference::Differs {
+        paths,
+        expected: print(&expected),
+        actual: print(&actual),
+    })
+}
+
+/// Parses every document within the text, returning the document itself if there is exactly one
+fn parse(comparison: Comparison, text: &str) -> Result<Value, String> {
+    let mut documents: Vec<Value> = match comparison {
+        Comparison::Text => unreachable!("text output is never parsed"),
+        Comparison::Json => serde_json::Deserializer::from_str(text)
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .map_err(|error| error.to_string())?,
+        Comparison::Yaml => serde_yaml::Deserializer::from_str(text)
+            .map(Value::deserialize)
+            .collect::<Result<_, _>>()
+            .map_err(|error| error.to_string())?,
+
+        // A TOML file is always a single table
+        Comparison::Toml => vec![toml::from_str(text).map_err(|error| error.to_string())?],
+    };
+
+    if documents.len() == 1 {
+        Ok(documents.pop().unwrap())
+    } else {
+        Ok(Value::Array(documents))
+    }
+}
+
+fn remove_field(value: &mut Value, field: &str) {
+    if field.starts_with('/') {
+        let (parent, key) = field.rsplit_once('/').unwrap();
+        let key = key.replace("~1", "/").replace("~0", "~");
+        if let Some(Value::Object(object)) = value.pointer_mut(parent) {
+            object.remove(&key);
+        }
+        return;
+    }
+
+    match value {
+        Value::Object(object) => {
+            object.remove(field);
+            object.values_mut().for_each(|value| remove_field(value, field));
+        }
+        Value::Array(array) => array.iter_mut().for_each(|value| remove_field(value, field)),
+        _ => (),
+    }
+}
+
+/// Pushes the JSON pointer of each place the two values differ. Objects and arrays of the same length
+/// are compared element by element so that only the innermost differences are reported.
+fn find_differences(expected: &Value, actual: &Value, path: &mut String, differences: &mut Vec<String>) {
+    let mut within = |path: &mut String, key: &str, expected: Option<&Value>, actual: Option<&Value>| {
+        let length = path.len();
+        path.push('/');
+        path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+        match (expected, actual) {
+            (Some(expected), Some(actual)) => find_differences(expected, actual, path, differences),
+            _ => differences.push(path.clone()),
+        }
+        path.truncate(length);
+    };
+
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let added = actual.keys().filter(|key| !expected.contains_key(*key));
+            for key in expected.keys().chain(added) {
+                within(path, key, expected.get(key), actual.get(key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                within(path, &index.to_string(), Some(expected), Some(actual));
+            }
+        }
+        _ if expected != actual => {
+            let path = if path.is_empty() { "/" } else { path.as_str() };
+            differences.push(path.to_owned());
+        }
+        _ => (),
+    }
+}
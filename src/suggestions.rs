@@ -0,0 +1,45 @@
+//! "Did you mean" suggestions for mistyped keywords.
+
+/// The number of single character insertions, deletions, or substitutions needed to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != *b_char) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Lowercases the text and removes any whitespace before a ':' or between words,
+/// so `Expected  stdout :` is compared the same as `expected stdout:`
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").replace(" :", ":").to_lowercase()
+}
+
+/// If the start of `text` up to its first ':' is close to one of the given keywords (which
+/// should each end in ':') without matching it exactly, returns the closest keyword.
+pub(crate) fn suggest_keyword<'a>(text: &str, keywords: &[&'a str]) -> Option<&'a str> {
+    let candidate = normalize(&text[..=text.find(':')?]);
+
+    // Skip lines such as `# expected stdout:` which are deliberately commented out
+    if !candidate.starts_with(char::is_alphabetic) {
+        return None;
+    }
+
+    // Allow roughly one typo for every five characters
+    let max_distance = |keyword: &str| 1.max(keyword.len() / 5);
+
+    keywords
+        .iter()
+        .filter(|keyword| keyword.ends_with(':'))
+        .map(|keyword| (edit_distance(&candidate, &normalize(keyword)), *keyword))
+        .filter(|(distance, keyword)| *distance <= max_distance(keyword))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, keyword)| keyword)
+}
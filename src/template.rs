@@ -0,0 +1,90 @@
+//! Expansion of the `{file}`, `{dir}`, `{stem}`, and `{tmpdir}` placeholders within test arguments.
+use crate::shell;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Used to give each temporary directory a unique name within this process
+static TMPDIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A uniquely named directory within the system's temporary directory, removed when this is dropped
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        let id = TMPDIR_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!("goldentests-{}-{}", std::process::id(), id);
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path)?;
+        Ok(TempDir(path))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The values placeholders expand to for a single run of a test. The temporary directory
+/// is only created once `{tmpdir}` or `tmpdir` is used and is removed when this is dropped.
+pub(crate) struct Template<'a> {
+    file: &'a Path,
+    tmpdir: Option<TempDir>,
+}
+
+impl<'a> Template<'a> {
+    /// `file` is the path of the test as it is given to the binary
+    pub(crate) fn new(file: &'a Path) -> Self {
+        Template { file, tmpdir: None }
+    }
+
+    /// True if the given argument refers to the test file, in which case the
+    /// test path isn't passed to the binary as the last argument
+    pub(crate) fn mentions_file(arg: &str) -> bool {
+        arg.contains("{file}")
+    }
+
+    /// Replaces each placeholder within the given argument
+    pub(crate) fn expand(&mut self, arg: &str) -> std::io::Result<String> {
+        self.expand_with(arg, |value| value.to_owned())
+    }
+
+    /// Replaces each placeholder within the given shell command line, quoting each value
+    pub(crate) fn expand_quoted(&mut self, command_line: &str) -> std::io::Result<String> {
+        self.expand_with(command_line, shell::quote)
+    }
+
+    fn expand_with(&mut self, arg: &str, quote: impl Fn(&str) -> String) -> std::io::Result<String> {
+        let mut arg = arg.replace("{file}", &quote(&self.file.to_string_lossy()));
+
+        if arg.contains("{dir}") {
+            let dir = self.file.parent().unwrap_or_else(|| Path::new(""));
+            arg = arg.replace("{dir}", &quote(&dir.to_string_lossy()));
+        }
+
+        if arg.contains("{stem}") {
+            let stem = self.file.file_stem().unwrap_or_default();
+            arg = arg.replace("{stem}", &quote(&stem.to_string_lossy()));
+        }
+
+        if arg.contains("{tmpdir}") {
+            let tmpdir = self.tmpdir()?;
+            arg = arg.replace("{tmpdir}", &quote(&tmpdir.to_string_lossy()));
+        }
+
+        Ok(arg)
+    }
+
+    /// The temporary directory of this run of the test, created on first use
+    pub(crate) fn tmpdir(&mut self) -> std::io::Result<&Path> {
+        if self.tmpdir.is_none() {
+            self.tmpdir = Some(TempDir::new()?);
+        }
+        Ok(self.tmpdir.as_ref().unwrap().path())
+    }
+}
@@ -0,0 +1,64 @@
+//! Helpers for fitting output within the width of the terminal
+use std::borrow::Cow;
+
+/// The narrowest width output will be fit within, even if the terminal is narrower
+const MIN_WIDTH: usize = 40;
+
+/// Detects the width of the terminal stderr is connected to, falling back to the `COLUMNS`
+/// environment variable. Returns None if the width is unknown, e.g. when stderr is redirected.
+pub fn detect_width() -> Option<usize> {
+    if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size_of(std::io::stderr()) {
+        return Some(width as usize);
+    }
+    std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok())
+}
+
+/// Clamps a requested width so output is never squeezed into an unreadably narrow column
+pub fn clamp_width(width: usize) -> usize {
+    width.max(MIN_WIDTH)
+}
+
+/// Shortens the given string to at most `max_width` characters by replacing its middle
+/// with an ellipsis. This keeps both the start and end of paths, which are usually the most
+/// identifying parts: `tests/codegen/…/shifts.ant`.
+pub fn shorten_middle(s: &str, max_width: usize) -> Cow<'_, str> {
+    let length = s.chars().count();
+    if length <= max_width || max_width < 3 {
+        return s.into();
+    }
+
+    let kept = max_width - 1;
+    let start: String = s.chars().take(kept - kept / 2).collect();
+    let end: String = s.chars().skip(length - kept / 2).collect();
+    format!("{}…{}", start, end).into()
+}
+
+/// Wraps the given text onto multiple lines of at most `width` characters, breaking at spaces
+/// where possible. Words longer than `width` are left on their own line.
+pub fn wrap_words(text: &str, width: usize) -> String {
+    let mut wrapped = String::new();
+    let mut line_length = 0;
+
+    for word in text.split(' ') {
+        let word_length = word.chars().count();
+        if line_length > 0 && line_length + 1 + word_length > width {
+            wrapped.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            wrapped.push(' ');
+            line_length += 1;
+        }
+        wrapped += word;
+        line_length += word_length;
+    }
+    wrapped
+}
+
+/// Splits a line into chunks of at most `width` characters each. An empty line yields one empty chunk.
+pub fn split_at_width(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars.chunks(width.max(1)).map(|chunk| chunk.iter().collect()).collect()
+}
@@ -0,0 +1,63 @@
+//! Comparing the numbers within expected and actual output with a tolerance, so that numerical
+//! programs can be tested across platforms whose floating point output differs in the last few
+//! digits. See `TestConfig::float_tolerance`.
+use crate::config::FloatTolerance;
+
+use regex::Regex;
+use similar::{DiffOp, TextDiff};
+use std::sync::OnceLock;
+
+fn number_regex() -> &'static Regex {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    NUMBER.get_or_init(|| Regex::new(r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?").unwrap())
+}
+
+/// Returns the actual output with each number that is within the tolerance of the corresponding
+/// number in the expected output replaced by the expected number as written, so that only numbers
+/// outside of the tolerance show up as differences. Lines are paired up by diffing the expected and
+/// actual output, and a pair of lines is only changed if the two are identical apart from their numbers.
+pub(crate) fn apply_tolerance(expected: &str, actual: &str, tolerance: FloatTolerance) -> String {
+    let expected_lines: Vec<&str> = expected.split_inclusive('\n').collect();
+    let mut actual_lines: Vec<String> = actual.split_inclusive('\n').map(ToOwned::to_owned).collect();
+
+    for op in TextDiff::from_lines(expected, actual).ops() {
+        if let DiffOp::Replace {
+            old_index,
+            old_len,
+            new_index,
+            new_len,
+        } = *op
+        {
+            for offset in 0..old_len.min(new_len) {
+                let actual = &mut actual_lines[new_index + offset];
+                if let Some(line) = align_line(expected_lines[old_index + offset], actual, tolerance) {
+                    *actual = line;
+                }
+            }
+        }
+    }
+    actual_lines.concat()
+}
+
+/// Replaces each number in the actual line within the tolerance of the expected one,
+/// or returns None if the lines differ in anything other than their numbers
+fn align_line(expected: &str, actual: &str, tolerance: FloatTolerance) -> Option<String> {
+    let regex = number_regex();
+    if regex.split(expected).ne(regex.split(actual)) {
+        return None;
+    }
+
+    let mut line = String::new();
+    let mut end = 0;
+    for (expected, number) in regex.find_iter(expected).zip(regex.find_iter(actual)) {
+        line.push_str(&actual[end..number.start()]);
+        let within = match (expected.as_str().parse(), number.as_str().parse()) {
+            (Ok(expected), Ok(number)) => tolerance.allows(expected, number),
+            _ => false,
+        };
+        line.push_str(if within { expected.as_str() } else { number.as_str() });
+        end = number.end();
+    }
+    line.push_str(&actual[end..]);
+    Some(line)
+}
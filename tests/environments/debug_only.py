@@ -0,0 +1,5 @@
+print(__debug__)
+
+# Running with -O would make __debug__ False
+# environments: default
+# expected stdout: True
@@ -0,0 +1,5 @@
+import os
+
+print(os.environ["GREETING"])
+
+# expected stdout: hello
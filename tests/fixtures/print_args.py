@@ -0,0 +1,4 @@
+# Stands in for a wrapper or runtime such as valgrind or wasmtime by printing the arguments it was given
+import sys
+
+print(sys.argv[1:])
@@ -0,0 +1,16 @@
+# Writes an artifact next to itself, which would dirty the repository if this weren't ran hermetically
+import os
+import sys
+
+directory = os.path.dirname(sys.argv[0])
+with open(os.path.join(directory, 'artifact.txt'), 'w') as artifact:
+    artifact.write('written by the test')
+
+print(os.getcwd() == os.path.abspath(directory))
+print(open('data/input.txt').read().strip())
+
+# hermetic: true
+# fixtures: data/
+# expected stdout:
+# True
+# # fixture contents
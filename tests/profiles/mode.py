@@ -0,0 +1,5 @@
+import sys
+
+print("mode:", sys._xoptions["mode"])
+
+# expected stdout: mode: release
@@ -0,0 +1,8 @@
+import uuid
+
+print("created session", uuid.uuid4())
+print("object at", hex(id(print)))
+
+# expected stdout:
+# created session <uuid>
+# object at <pointer>
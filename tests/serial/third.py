@@ -0,0 +1,13 @@
+# Each test creates the same marker file, which fails if another test holding the lock is running
+import os
+import time
+
+marker = os.open('target/serial.marker', os.O_CREAT | os.O_EXCL)
+time.sleep(0.2)
+os.close(marker)
+os.remove('target/serial.marker')
+print('done')
+
+# serial: marker
+# expected exit status: 0
+# expected stdout: done
@@ -0,0 +1,9 @@
+import sys
+
+print("hello")
+print("world", file=sys.stderr)
+
+# args: {file} 2>&1 | tr a-z A-Z | sort
+# expected stdout:
+# HELLO
+# WORLD
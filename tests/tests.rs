@@ -1,7 +1,832 @@
+use goldentests::command_runner::{CommandRunner, Invocation, LocalRunner};
+use goldentests::config::DurationRegression;
+use goldentests::error::{InnerTestError, TestError};
+use goldentests::reporter::{Reporter, RunSummary};
 use goldentests::{TestConfig, TestResult};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Creates a fresh directory of tests within `target` holding the given files, along with a config
+/// running them with python which doesn't persist any state between runs
+fn fixture(name: &str, files: &[(&str, &str)]) -> TestResult<(PathBuf, TestConfig)> {
+    let directory = Path::new("target").join(name);
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    for (path, contents) in files {
+        let path = directory.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    let mut config = TestConfig::new("python", &directory, "# ")?;
+    config.state_directory = None;
+    Ok((directory, config))
+}
 
 #[test]
 fn run_goldentests_example() -> TestResult<()> {
     let config = TestConfig::new("python", "examples", "# ")?;
     config.run_tests()
 }
+
+#[test]
+fn run_scrub_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/scrub", "# ")?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.run_tests()
+}
+
+#[test]
+fn run_environment_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/environments", "# ")?;
+    config.load_config_file("tests/environments.toml")?;
+    config.run_tests()
+}
+
+#[test]
+fn empty_environment_lists_are_rejected() -> TestResult<()> {
+    let test = "print('hello')\n# environments: ,\n# expected stdout: hello\n";
+    let (directory, mut config) = fixture("empty-environments", &[("test.py", test)])?;
+    config.load_config_file("tests/environments.toml")?;
+    match goldentests::parser::parse_test(&directory.join("test.py"), &config) {
+        Err(InnerTestError::ErrorParsingDirective(_, 2, directive, _)) => assert_eq!(directive, "# environments:"),
+        Err(error) => panic!("expected the environments to be rejected, but found {:?}", error),
+        Ok(_) => panic!("expected the environments to be rejected"),
+    }
+    Ok(())
+}
+
+#[test]
+fn tests_without_an_environment_fail() -> TestResult<()> {
+    let (directory, mut config) = fixture(
+        "no-environments",
+        &[
+            ("test.py", "print('hello')\n# expected stdout: hello\n"),
+            ("config.toml", "[environments.codegen]\npaths = [\"codegen/**\"]\n"),
+        ],
+    )?;
+    config.load_config_file(directory.join("config.toml"))?;
+    config.ignore_patterns.push(glob::Pattern::new("*.toml").unwrap());
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(
+                matches!(&failures[0], InnerTestError::NoEnvironments(_)),
+                "{:?}",
+                failures[0]
+            );
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn run_wasm_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("tests/fixtures/module.wasm", "tests/wasm", "# ")?;
+    config.load_config_file("tests/wasm.toml")?;
+    config.run_tests()
+}
+
+#[test]
+fn run_wrapper_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/wrapper", "# ")?;
+    config.load_config_file("tests/wrapper.toml")?;
+    config.run_tests()
+}
+
+#[test]
+fn generate_tests_from_inputs() -> TestResult<()> {
+    let (directory, config) = fixture(
+        "generated",
+        &[("input.py", "import sys\nprint('hello')\nsys.exit(3)\n")],
+    )?;
+    config.generate_tests()?;
+
+    let generated = std::fs::read_to_string(directory.join("input.py")).unwrap();
+    assert!(generated.contains("# expected exit status: 3\n# expected stdout: hello\n"));
+    config.run_tests()
+}
+
+#[test]
+fn run_shell_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/shell", "# ")?;
+    config.load_config_file("tests/shell.toml")?;
+    config.run_tests()
+}
+
+#[test]
+fn run_profile_tests() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/profiles", "# ")?;
+    config.load_config_file_with_profile("tests/profiles.toml", "release")?;
+    config.run_tests()
+}
+
+#[test]
+fn run_serial_tests() -> TestResult<()> {
+    let _ = std::fs::remove_file("target/serial.marker");
+    let mut config = TestConfig::new("python", "tests/serial", "# ")?;
+    // Make sure there are enough threads for the tests to overlap if they weren't serial
+    config.max_jobs = Some(3);
+    config.run_tests()
+}
+
+#[test]
+fn run_hermetic_tests() -> TestResult<()> {
+    let config = TestConfig::new("python", "tests/hermetic", "# ")?;
+    config.run_tests()?;
+    assert!(!Path::new("tests/hermetic/artifact.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn write_reports() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/scrub", "# ")?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.reports.push("html=target/report.html".parse().unwrap());
+    config.reports.push("markdown=target/report.md".parse().unwrap());
+    config.run_tests()?;
+
+    let html = std::fs::read_to_string("target/report.html").unwrap();
+    assert!(html.contains("<td>tests/scrub/nondeterministic.py</td>"));
+    assert!(html.contains("<span class=\"failed\">0 failing</span>"));
+
+    let markdown = std::fs::read_to_string("target/report.md").unwrap();
+    assert!(markdown.contains("| `tests/scrub/nondeterministic.py` |  | :white_check_mark: passed"));
+    Ok(())
+}
+
+#[test]
+fn only_tests_whose_golden_text_differs_need_canonicalization() -> TestResult<()> {
+    let mut config = TestConfig::new("python", "tests/scrub", "# ")?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.state_directory = None;
+    config.reports.push("markdown=target/canonical.md".parse().unwrap());
+    config.run_tests()?;
+    let markdown = std::fs::read_to_string("target/canonical.md").unwrap();
+    assert!(!markdown.contains("needs canonicalization"));
+
+    // Passes only because whitespace is ignored, so --overwrite would rewrite its expected output
+    let test = "print('id: 0x1f  ok')\n# ignore whitespace: true\n# expected stdout: id: <pointer> ok\n";
+    let (_, mut config) = fixture("canonicalization", &[("spacing.py", test)])?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.reports.push("markdown=target/noncanonical.md".parse().unwrap());
+    config.run_tests()?;
+    let markdown = std::fs::read_to_string("target/noncanonical.md").unwrap();
+    assert!(markdown.contains("passed, needs canonicalization"));
+    Ok(())
+}
+
+#[test]
+fn failures_are_returned() -> TestResult<()> {
+    let test = "print('actual')\n# expected stdout: expected\n";
+    let (directory, config) = fixture("failing", &[("failing.py", test)])?;
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].path(), directory.join("failing.py"));
+            assert!(matches!(&failures[0], InnerTestError::TestFailed { .. }));
+
+            let diff = failures[0].mismatches()[0].diff.as_ref().unwrap();
+            assert_eq!((diff.expected.as_str(), diff.actual.as_str()), ("expected", "actual"));
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn reports_show_the_name_and_description_of_tests() -> TestResult<()> {
+    let test = "print('actual')\n# name: constant folding of shifts\n# description: shifts are folded\n# issue: #12\n# expected stdout: expected\n";
+    let (_, mut config) = fixture("named", &[("shift.py", test)])?;
+    config.reports.push("markdown=target/named.md".parse().unwrap());
+    assert!(config.run_tests().is_err());
+
+    let markdown = std::fs::read_to_string("target/named.md").unwrap();
+    assert!(markdown.contains("| `constant folding of shifts — target/named/shift.py` |"));
+    assert!(markdown.contains("_shifts are folded_"));
+    assert!(markdown.contains("Issue: #12"));
+    Ok(())
+}
+
+#[test]
+fn tests_with_unmet_requirements_are_skipped() -> TestResult<()> {
+    let failing = "print('actual')\n# expected stdout: expected\n";
+    let program = format!("# requires: goldentests-missing-program\n{}", failing);
+    let variable = format!("# requires: env GOLDENTESTS_MISSING_VARIABLE\n{}", failing);
+    let (_, mut config) = fixture("requires", &[("program.py", &program), ("variable.py", &variable)])?;
+    config.reports.push("markdown=target/requires.md".parse().unwrap());
+    config.run_tests()?;
+
+    let markdown = std::fs::read_to_string("target/requires.md").unwrap();
+    assert!(markdown.contains("Ran 0 tests"));
+    assert!(markdown.contains("requires `goldentests-missing-program`, which isn't on the PATH"));
+    assert!(markdown.contains("requires the GOLDENTESTS_MISSING_VARIABLE environment variable, which isn't set"));
+    Ok(())
+}
+
+#[test]
+fn stray_files_fail_tests() -> TestResult<()> {
+    let test = "for name in ['leak.txt', 'debug.log']:\n    open(name, 'w').close()\n# hermetic: true\n";
+    let (_, mut config) = fixture("stray", &[("leaky.py", test)])?;
+    config.forbid_stray_files = true;
+    config.stray_file_allowlist.push(glob::Pattern::new("*.log").unwrap());
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            let message = &failures[0].mismatches()[0].message;
+            assert_eq!(message, "Created unexpected files:\n  leak.txt\n");
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn expected_directories_are_compared_against_their_golden_tree() -> TestResult<()> {
+    let test = "import os\nos.makedirs('out/nested')\nopen('out/a.txt', 'w').write('a')\nopen('out/nested/b.txt', 'w').write('b')\n# cwd: ..\n# expected directory \"out\": ../golden\n";
+    let (directory, mut config) = fixture("directory", &[("tests/generate.py", test)])?;
+    config.test_path = directory.join("tests");
+    config.overwrite_tests = true;
+    assert!(config.run_tests().is_ok());
+    assert_eq!(
+        std::fs::read_to_string(directory.join("golden/nested/b.txt")).unwrap(),
+        "b"
+    );
+
+    config.overwrite_tests = false;
+    config.run_tests()?;
+
+    std::fs::write(directory.join("golden/a.txt"), "c").unwrap();
+    std::fs::write(directory.join("golden/extra.txt"), "extra").unwrap();
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            let messages: Vec<_> = failures[0].mismatches().iter().map(|mismatch| mismatch.message.as_str()).collect();
+            assert_eq!(messages.len(), 2);
+            assert!(messages[0].starts_with("Actual file \"out/a.txt\" differs"));
+            assert_eq!(
+                messages[1],
+                "Expected file \"out/extra.txt\" to exist after running the test\n"
+            );
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn expected_files_outside_the_run_directory_are_not_removed() -> TestResult<()> {
+    let outside = Path::new("target/outside-expected-file.txt");
+    std::fs::write(outside, "kept\n").unwrap();
+    let test = "# cwd: .\n# expected file \"../outside-expected-file.txt\": kept\n";
+    let (_, config) = fixture("outside-expected-file", &[("test.py", test)])?;
+    config.run_tests()?;
+    assert!(outside.exists());
+    Ok(())
+}
+
+#[test]
+fn encoded_output_is_overwritten_in_the_same_encoding() -> TestResult<()> {
+    let test = "import sys\nsys.stdout.buffer.write(b'\\x1b[0m\\xff')\n# expected stdout (hex):\n";
+    let (directory, mut config) = fixture("encoded", &[("bytes.py", test)])?;
+    assert!(config.run_tests().is_err());
+
+    config.overwrite_tests = true;
+    assert!(config.run_tests().is_ok());
+    let contents = std::fs::read_to_string(directory.join("bytes.py")).unwrap();
+    assert!(contents.contains("# expected stdout (hex):\n# 1b5b306dff\n"));
+
+    config.overwrite_tests = false;
+    config.run_tests()
+}
+
+#[test]
+fn escaped_output_is_overwritten_with_escapes() -> TestResult<()> {
+    let test = "print('a\\tb\\\\\\x1b\\u0085')\n# expected stdout (escaped):\n";
+    let (directory, mut config) = fixture("escaped", &[("tabs.py", test)])?;
+    config.overwrite_tests = true;
+    assert!(config.run_tests().is_ok());
+    let contents = std::fs::read_to_string(directory.join("tabs.py")).unwrap();
+    assert!(contents.contains("# expected stdout (escaped): a\\tb\\\\\\x1b\\u{85}\n"));
+
+    config.overwrite_tests = false;
+    config.run_tests()
+}
+
+#[test]
+fn fenced_output_is_overwritten_with_its_terminator() -> TestResult<()> {
+    let test = "print('a\\n\\nb')\n# expected stdout until: END\n# a\n# END\n";
+    let (directory, mut config) = fixture("fenced", &[("paragraphs.py", test)])?;
+    config.overwrite_tests = true;
+    assert!(config.run_tests().is_ok());
+    let contents = std::fs::read_to_string(directory.join("paragraphs.py")).unwrap();
+    assert!(contents.contains("# expected stdout until: END\n# a\n#\n# b\n# END\n"));
+
+    config.overwrite_tests = false;
+    config.run_tests()?;
+
+    std::fs::write(
+        directory.join("paragraphs.py"),
+        "print('a')\n# expected stdout until: END\n# a\n",
+    )
+    .unwrap();
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            let message = failures[0].to_string();
+            assert!(message.contains("the block isn't ended by a line containing 'END'"));
+        }
+        other => panic!("expected the test to fail to parse, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn ignored_files_are_not_tests() -> TestResult<()> {
+    let not_a_test = "this isn't python\n";
+    let (_, mut config) = fixture(
+        "ignored",
+        &[
+            ("hello.py", "print('hello')\n# expected stdout: hello\n"),
+            ("README.md", not_a_test),
+            ("nested/.hello.py.swp", not_a_test),
+            ("nested/target/build.py", not_a_test),
+        ],
+    )?;
+    assert!(config.run_tests().is_err());
+
+    for pattern in ["**/target/**", "*.md", ".*"] {
+        config.ignore_patterns.push(glob::Pattern::new(pattern).unwrap());
+    }
+    config.run_tests()
+}
+
+#[test]
+fn manifests_run_only_the_tests_they_list() -> TestResult<()> {
+    let (directory, _) = fixture(
+        "manifest",
+        &[
+            ("first.py", "print('first')\n# expected stdout: first\n"),
+            ("smoke/second.py", "print('second')\n# expected stdout: second\n"),
+            ("failing.py", "print('unexpected')\n"),
+            ("smoke.txt", "# smoke tests\nfirst.py\n\nsmoke\n"),
+            ("smoke.toml", "tests = [\"first.py\", \"smoke/second.py\"]\n"),
+        ],
+    )?;
+
+    let mut config = TestConfig::new("python", directory.join("smoke.txt"), "# ")?;
+    config.state_directory = None;
+    assert_eq!(config.list_tests()?.len(), 2);
+    config.run_tests()?;
+
+    let mut config = TestConfig::new("python", directory.join("smoke.toml"), "# ")?;
+    config.state_directory = None;
+    config.run_tests()?;
+
+    let mut config = TestConfig::new("python", &directory, "# ")?.with_test_files(vec![directory.join("failing.py")]);
+    config.state_directory = None;
+    assert!(config.run_tests().is_err());
+    Ok(())
+}
+
+#[test]
+fn run_files_runs_only_the_given_files() -> TestResult<()> {
+    let (directory, config) = fixture(
+        "run_files",
+        &[
+            ("passing.py", "print('pass')\n# expected stdout: pass\n"),
+            ("failing.py", "print('unexpected')\n"),
+        ],
+    )?;
+    config.run_files(&[directory.join("passing.py")])?;
+
+    match config.run_files(&[directory.join("passing.py"), directory.join("failing.py")]) {
+        Err(TestError::TestErrors(failures)) => assert_eq!(failures.len(), 1),
+        other => panic!("expected one test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn nondeterministic_output_fails() -> TestResult<()> {
+    let test = "import time\nprint(time.time_ns())\n# repeat: 2\n";
+    let (_, config) = fixture("nondeterministic", &[("clock.py", test)])?;
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            let mismatches = failures[0].mismatches();
+            assert_eq!(mismatches[0].message, "Actual stdout differs between run 1 and run 2:");
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn session_step_times_out() -> TestResult<()> {
+    let test = "import sys\nfor line in sys.stdin:\n    print(line.upper(), end='', flush=True)\n\n# send: hello\n# expect: HELLO\n# send: bye\n# expect (0.5s): goodbye\n";
+    let (_, config) = fixture("session", &[("echo.py", test)])?;
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            let mismatches = failures[0].mismatches();
+            assert_eq!(
+                mismatches[0].message,
+                "Timed out after 500ms waiting for stdout to contain:"
+            );
+        }
+        other => panic!("expected the test to fail, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn after_commands_run_when_the_program_fails_to_run() -> TestResult<()> {
+    let test = "# cwd: .\n# after: echo done > teardown.txt\n";
+    let (directory, mut config) = fixture("teardown", &[("test.txt", test)])?;
+    config.binary_path = "a-binary-which-does-not-exist".into();
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(
+                matches!(&failures[0], InnerTestError::CommandError(..)),
+                "{:?}",
+                failures[0]
+            );
+        }
+        other => panic!("expected the program to fail to run, but found {:?}", other),
+    }
+    assert!(directory.join("teardown.txt").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn cpu_time_limit_is_enforced() -> TestResult<()> {
+    let (_, mut config) = fixture("limits", &[("spin.py", "while True:\n    pass\n")])?;
+    config.limits.cpu_time = Some(Duration::from_secs(1));
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(matches!(&failures[0], InnerTestError::ResourceLimitExceeded(_, limit) if limit.contains("CPU")));
+        }
+        other => panic!("expected the test to exceed its limit, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn memory_limit_is_enforced() -> TestResult<()> {
+    let test = "chunks = []\nwhile True:\n    chunks.append(b'x' * 1000000)\n";
+    let (_, mut config) = fixture("limits-memory", &[("grow.py", test)])?;
+    config.limits.memory = Some(200 << 20);
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(
+                matches!(&failures[0], InnerTestError::ResourceLimitExceeded(_, limit) if limit.contains("memory"))
+            );
+        }
+        other => panic!("expected the test to exceed its limit, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn crashes_within_the_memory_limit_are_not_blamed_on_it() -> TestResult<()> {
+    let test = "import os\nos.abort()\n\n# expected exit status: 0\n";
+    let (_, mut config) = fixture("limits-crash", &[("abort.py", test)])?;
+    config.limits.memory = Some(4 << 30);
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(
+                !matches!(&failures[0], InnerTestError::ResourceLimitExceeded(..)),
+                "{:?}",
+                failures[0]
+            );
+        }
+        other => panic!("expected the test to crash, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn leftover_processes_are_killed() -> TestResult<()> {
+    // The sleep inherits stdout, so the run would hang until it exits if it were left running
+    let test = "import subprocess\nsubprocess.Popen(['sleep', '60'])\nprint('done')\n# expected stdout: done\n";
+    let (_, config) = fixture("leftover", &[("background.py", test)])?;
+    let start = std::time::Instant::now();
+    config.run_tests()?;
+    assert!(start.elapsed() < Duration::from_secs(30));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn pty_is_a_terminal() -> TestResult<()> {
+    std::fs::write("target/pty-input.txt", "typed\n").unwrap();
+    let test = "import sys\nprint(sys.stdin.isatty(), sys.stdout.isatty(), sys.stderr.isatty())\nprint(input(), file=sys.stderr)\n\n\
+                # pty: true\n# stdin file: ../pty-input.txt\n# expected stdout:\n# True True True\n# typed\n";
+    let (_, config) = fixture("pty", &[("terminal.py", test)])?;
+    config.run_tests()
+}
+
+#[test]
+fn clean_env_only_keeps_allowed_variables() -> TestResult<()> {
+    let test =
+        "import os\nprint('PATH' in os.environ, 'CARGO_MANIFEST_DIR' in os.environ)\n# expected stdout: True False\n";
+    let (_, mut config) = fixture("clean-env", &[("env.py", test)])?;
+    config.clean_env = true;
+    config.env_allowlist = vec!["PATH".to_owned()];
+    config.run_tests()
+}
+
+#[test]
+fn quarantined_failures_do_not_fail_the_run() -> TestResult<()> {
+    let test = "print('actual')\n# expected stdout: expected\n";
+    let (_, mut config) = fixture("quarantine", &[("flaky/failing.py", test)])?;
+    assert!(config.run_tests().is_err());
+
+    config.quarantine = vec![glob::Pattern::new("flaky/*.py").unwrap()];
+    config.run_tests()
+}
+
+#[test]
+fn reference_binary_output_is_compared_instead_of_expected_output() -> TestResult<()> {
+    let test = "print('actual')\n# expected stdout: expected\n";
+    let (_, mut config) = fixture("reference-binary", &[("differs.py", test)])?;
+    config.reference_binary = Some("python".into());
+    config.run_tests()?;
+
+    config.reference_binary = Some("cat".into());
+    assert!(config.run_tests().is_err());
+    Ok(())
+}
+
+#[test]
+fn import_lit_tests_translates_simple_lit_tests() -> TestResult<()> {
+    let simple = "print('hello')\nprint('world')\n# RUN: %python %s | FileCheck %s\n# CHECK: hello\n# CHECK-NOT: goodbye\n# CHECK-NEXT: world\n";
+    let unsupported = "print('hello')\n# RUN: %python %s | FileCheck %s\n# CHECK: {{h.*}}\n";
+    let (directory, config) = fixture("import-lit", &[("simple.py", simple), ("unsupported.py", unsupported)])?;
+    config.import_lit_tests()?;
+
+    let imported = std::fs::read_to_string(directory.join("simple.py")).unwrap();
+    assert!(imported.contains("# check: hello\n# check-not: goodbye\n# check-next: world\n"));
+    assert_eq!(
+        std::fs::read_to_string(directory.join("unsupported.py")).unwrap(),
+        unsupported
+    );
+
+    std::fs::remove_file(directory.join("unsupported.py")).unwrap();
+    config.run_tests()
+}
+
+#[test]
+fn default_exit_status_applies_without_a_directive() -> TestResult<()> {
+    let (_, mut config) = fixture(
+        "default-exit-status",
+        &[
+            ("exits.py", "import sys\nsys.exit(1)\n"),
+            ("expected.py", "import sys\nsys.exit(2)\n# expected exit status: 2\n"),
+        ],
+    )?;
+    config.run_tests()?;
+
+    config.default_exit_status = Some(0);
+    match config.run_tests() {
+        Err(TestError::TestErrors(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].path().ends_with("exits.py"));
+        }
+        other => panic!("expected exits.py to fail, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn keyword_aliases_are_read_as_their_keyword() -> TestResult<()> {
+    let test = "import sys\nprint('out:')\nprint('a')\nsys.exit(3)\n# out (first 2 lines):\n# out:\n# a\n\n# exit: 3\n";
+    let (_, mut config) = fixture("keyword-aliases", &[("aliased.py", test)])?;
+    assert!(config.run_tests().is_err());
+
+    config.add_keyword_alias("expected stdout:", "out:")?;
+    config.add_keyword_alias("expected exit status:", "exit:")?;
+    assert!(config.add_keyword_alias("expected stdout:", "out").is_err());
+    assert!(config.add_keyword_alias("unexpected stdout:", "out:").is_err());
+    config.run_tests()
+}
+
+#[test]
+fn serve_lists_and_runs_tests() -> TestResult<()> {
+    let (_, config) = fixture(
+        "serve",
+        &[
+            ("passing.py", "print('a')\n# expected stdout: a\n"),
+            ("failing.py", "print('a')\n# expected stdout: b\n"),
+        ],
+    )?;
+
+    let requests = [
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "list"}"#,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "run"}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "exit"}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "list"}"#,
+    ];
+    let output_path = Path::new("target/serve-output.jsonl");
+    let output = std::fs::File::create(output_path).unwrap();
+    config.serve(requests.join("\n").as_bytes(), output)?;
+
+    let messages: Vec<serde_json::Value> = std::fs::read_to_string(output_path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let response = |id: i64| messages.iter().find(|message| message["id"] == id);
+
+    assert_eq!(response(1).unwrap()["result"].as_array().unwrap().len(), 2);
+    let finished = messages.iter().filter(|message| message["method"] == "test/finished").count();
+    assert_eq!(finished, 2);
+    let totals = &response(2).unwrap()["result"];
+    assert_eq!(
+        (totals["passing"].as_u64(), totals["failing"].as_u64()),
+        (Some(1), Some(1))
+    );
+
+    // Nothing is read after exit
+    assert!(response(3).is_some() && response(4).is_none());
+    Ok(())
+}
+
+#[test]
+fn actual_output_is_saved() -> TestResult<()> {
+    let state = Path::new("target/save-actual-state");
+    let _ = std::fs::remove_dir_all(state);
+    let test = "import sys\nprint('actual')\nsys.exit(2)\n# expected stdout: expected\n";
+    let (directory, mut config) = fixture("save-actual", &[("failing.py", test)])?;
+    config.state_directory = Some(state.to_owned());
+    config.save_actual = true;
+    assert!(config.run_tests().is_err());
+
+    let saved = state.join(&directory).join("failing.py");
+    let read = |extension: &str| std::fs::read_to_string(saved.with_extension(extension)).unwrap();
+    assert_eq!(read("py.actual.stdout"), "actual\n");
+    assert_eq!(read("py.actual.status"), "2");
+
+    // The saved output is removed once the test passes again
+    let test = "print('expected')\n# expected stdout: expected\n";
+    std::fs::write(directory.join("failing.py"), test).unwrap();
+    config.run_tests()?;
+    assert!(!saved.with_extension("py.actual.stdout").exists());
+    Ok(())
+}
+
+#[test]
+fn incremental_runs_rerun_tests_whose_stdin_changed() -> TestResult<()> {
+    let state = Path::new("target/incremental-state");
+    let _ = std::fs::remove_dir_all(state);
+    let test = "import sys\nprint(sys.stdin.read().strip())\n# stdin file: input.txt\n# expected stdout: hello\n";
+    let (directory, mut config) = fixture("incremental", &[("echo.py", test), ("input.txt", "hello\n")])?;
+    config.state_directory = Some(state.to_owned());
+    config.ignore_patterns.push(glob::Pattern::new("*.txt").unwrap());
+    config.incremental = true;
+    config.run_tests()?;
+
+    // The test itself is unchanged, but its output now differs so it must be ran again
+    std::fs::write(directory.join("input.txt"), "goodbye\n").unwrap();
+    assert!(config.run_tests().is_err());
+    Ok(())
+}
+
+#[test]
+fn saved_output_is_promoted() -> TestResult<()> {
+    let state = Path::new("target/promote-state");
+    let _ = std::fs::remove_dir_all(state);
+    let test = "import sys\nprint('actual')\nsys.exit(2)\n# expected stdout: expected\n";
+    let (directory, mut config) = fixture("promote", &[("changed.py", test)])?;
+    config.state_directory = Some(state.to_owned());
+    config.save_actual = true;
+    assert!(config.run_tests().is_err());
+
+    config.promote_tests(None)?;
+    let promoted = std::fs::read_to_string(directory.join("changed.py")).unwrap();
+    assert!(promoted.contains("# expected stdout: actual"), "{}", promoted);
+    assert!(promoted.contains("# expected exit status: 2"), "{}", promoted);
+    config.run_tests()
+}
+
+#[test]
+fn check_finds_problems_without_running() -> TestResult<()> {
+    let test = "open('ran', 'w')\n# expected exit status: zero\n";
+    let (directory, config) = fixture("check", &[("bad_status.py", test)])?;
+    match config.check_tests() {
+        Err(TestError::TestErrors(problems)) => {
+            assert!(matches!(&problems[0], InnerTestError::ErrorParsingExitStatus(_, 2, ..)));
+        }
+        other => panic!("expected a problem with the test, but found {:?}", other),
+    }
+    assert!(!Path::new("ran").exists());
+
+    let config = TestConfig::new("not-a-real-binary", &directory, "# ")?;
+    assert!(matches!(config.check_tests(), Err(TestError::InvalidBinary(..))));
+    Ok(())
+}
+
+#[test]
+fn lint_finds_suspicious_lines() -> TestResult<()> {
+    let test =
+        "print('a')\n# args: -b\n# expected stdot: a\n# args: -c\n# expected stdout: a\n# expected exit status: one\n";
+    let (directory, config) = fixture("lint", &[("suspicious.py", test)])?;
+    let lines: Vec<_> = goldentests::lint::lint_test(&directory.join("suspicious.py"), &config)
+        .iter()
+        .map(|lint| match lint {
+            InnerTestError::Lint(_, line, _) => *line,
+            other => panic!("expected a lint, but found {:?}", other),
+        })
+        .collect();
+
+    // A mistyped keyword, a repeated args, and an exit status swallowed by the stdout block
+    assert_eq!(lines, [3, 4, 6]);
+    assert!(config.lint_tests().is_err());
+    Ok(())
+}
+
+#[test]
+fn slower_tests_regress() -> TestResult<()> {
+    let baseline = Path::new("target/regression-baseline.json");
+    let (directory, mut config) = fixture("regression", &[("sleep.py", "import time\ntime.sleep(0.2)\n")])?;
+    config.duration_regression = Some(DurationRegression::new(baseline));
+    config.record_baseline = true;
+    config.run_tests()?;
+
+    // Pretend the test used to be much faster
+    let baseline_json = format!("{{ {:?}: 0.01 }}", directory.join("sleep.py"));
+    std::fs::write(baseline, baseline_json).unwrap();
+    config.record_baseline = false;
+
+    // A regression is only a warning by default
+    config.run_tests()?;
+
+    config.duration_regression.as_mut().unwrap().fail = true;
+    match config.run_tests() {
+        Err(TestError::TestErrors(failures)) => {
+            assert!(matches!(&failures[0], InnerTestError::DurationRegressed { .. }));
+        }
+        other => panic!("expected the test to regress, but found {:?}", other),
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct CountingReporter {
+    started: AtomicUsize,
+    completed: AtomicUsize,
+    passing: Arc<AtomicUsize>,
+}
+
+impl Reporter for CountingReporter {
+    fn on_test_start(&self, _path: &Path) {
+        self.started.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_test_complete(&self, _: &Path, _: Option<&str>, _: &Result<(), InnerTestError>, _: Duration) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_run_complete(&self, summary: &RunSummary) {
+        assert_eq!(self.started.load(Ordering::SeqCst), summary.total);
+        assert_eq!(self.completed.load(Ordering::SeqCst), summary.total);
+        self.passing.store(summary.passing, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn reporters_are_notified() -> TestResult<()> {
+    let passing = Arc::new(AtomicUsize::new(0));
+    let mut config = TestConfig::new("python", "tests/scrub", "# ")?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.reporters.push(Box::new(CountingReporter {
+        passing: passing.clone(),
+        ..Default::default()
+    }));
+    config.run_tests()?;
+    assert_eq!(passing.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+/// Runs each command locally, recording the program of each
+struct RecordingRunner(Arc<Mutex<Vec<String>>>);
+
+impl CommandRunner for RecordingRunner {
+    fn run(&self, invocation: &Invocation) -> std::io::Result<std::process::Output> {
+        self.0.lock().unwrap().push(invocation.program.clone());
+        LocalRunner.run(invocation)
+    }
+}
+
+#[test]
+fn custom_command_runner() -> TestResult<()> {
+    let programs = Arc::new(Mutex::new(Vec::new()));
+    let mut config = TestConfig::new("python", "tests/scrub", "# ")?;
+    config.load_config_file("tests/scrub.toml")?;
+    config.command_runner = Box::new(RecordingRunner(programs.clone()));
+    config.run_tests()?;
+    assert_eq!(*programs.lock().unwrap(), vec!["python".to_owned()]);
+    Ok(())
+}